@@ -16,6 +16,10 @@ fn instantiate(linker: &Linker<WasiCtx>, module: &Module) -> Result<()> {
 fn benchmark_name<'a>(strategy: &InstanceAllocationStrategy) -> &'static str {
     match strategy {
         InstanceAllocationStrategy::OnDemand => "default",
+        InstanceAllocationStrategy::Pooling {
+            strategy: PoolingAllocationStrategy::WorkStealing,
+            ..
+        } => "pooling-work-stealing",
         #[cfg(any(not(feature = "uffd"), not(target_os = "linux")))]
         InstanceAllocationStrategy::Pooling { .. } => "pooling",
         #[cfg(all(feature = "uffd", target_os = "linux"))]
@@ -66,6 +70,11 @@ fn bench_parallel(c: &mut Criterion) {
         #[cfg(any(not(feature = "uffd"), not(target_os = "linux")))]
         InstanceAllocationStrategy::OnDemand,
         InstanceAllocationStrategy::pooling(),
+        InstanceAllocationStrategy::Pooling {
+            strategy: PoolingAllocationStrategy::WorkStealing,
+            module_limits: ModuleLimits::default(),
+            instance_limits: InstanceLimits::default(),
+        },
     ] {
         let mut config = Config::default();
         config.allocation_strategy(strategy.clone());
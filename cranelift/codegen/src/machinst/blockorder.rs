@@ -375,7 +375,18 @@ impl BlockLoweringOrder {
         }
 
         postorder.reverse();
-        let mut rpo = postorder;
+        // Move cold blocks after all other blocks, keeping the relative order
+        // of both. The entry block stays first even if it's marked cold.
+        let entry = f.layout.entry_block();
+        let is_cold = |block: Block| Some(block) != entry && f.layout.is_cold(block);
+        let (mut rpo, cold): (Vec<_>, Vec<_>) =
+            postorder.into_iter().partition(|(lb, _)| match *lb {
+                LoweredBlock::Orig { block }
+                | LoweredBlock::OrigAndEdge { block, .. }
+                | LoweredBlock::EdgeAndOrig { block, .. } => !is_cold(block),
+                LoweredBlock::Edge { pred, succ, .. } => !is_cold(pred) && !is_cold(succ),
+            });
+        rpo.extend(cold);
         if let Some(d) = deferred_last {
             rpo.push(d);
         }
@@ -511,6 +522,27 @@ mod test {
         assert!(order.lowered_order[5].out_edge().is_none());
     }
 
+    #[test]
+    fn test_blockorder_cold_blocks() {
+        let mut func = build_test_func(4, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+        func.layout.set_cold(Block::from_u32(1));
+        let order = BlockLoweringOrder::new(&func);
+
+        // The cold block and the edge out of it come last.
+        let blocks = order
+            .lowered_order
+            .iter()
+            .map(|lb| lb.orig_block().map(|b| b.as_u32()))
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, [Some(0), Some(2), None, Some(3), Some(1), None]);
+        assert!(order.lowered_order[5].out_edge().unwrap().0.as_u32() == 1);
+
+        // The entry block can't be moved.
+        func.layout.set_cold(Block::from_u32(0));
+        let order = BlockLoweringOrder::new(&func);
+        assert_eq!(order.lowered_order[0].orig_block().unwrap().as_u32(), 0);
+    }
+
     #[test]
     fn test_blockorder_critedge() {
         //            0
@@ -11,7 +11,7 @@ pub use self::details::{add_to_current, take_current, PassTimes, TimingToken};
 //
 // This macro defines:
 //
-// - A C-style enum containing all the pass names and a `None` variant.
+// - A public C-style enum containing all the pass names and a `None` variant.
 // - A usize constant with the number of defined passes.
 // - A const array of pass descriptions.
 // - A public function per pass used to start the timing of that pass.
@@ -19,9 +19,17 @@ macro_rules! define_passes {
     { $enum:ident, $num_passes:ident, $descriptions:ident;
       $($pass:ident: $desc:expr,)+
     } => {
+        /// A pass that can be timed.
         #[allow(non_camel_case_types)]
         #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-        enum $enum { $($pass,)+ None}
+        pub enum $enum {
+            $(
+                #[doc=$desc]
+                $pass,
+            )+
+            /// No pass.
+            None,
+        }
 
         const $num_passes: usize = $enum::None as usize;
 
@@ -86,6 +94,7 @@ define_passes! {
 }
 
 impl Pass {
+    /// Returns the index of this pass.
     pub fn idx(self) -> usize {
         self as usize
     }
@@ -152,6 +161,15 @@ mod details {
         }
     }
 
+    impl PassTimes {
+        /// Returns the total time spent running `pass`, including its child passes.
+        pub fn total(&self, pass: Pass) -> Duration {
+            self.pass
+                .get(pass.idx())
+                .map_or(Duration::default(), |time| time.total)
+        }
+    }
+
     impl fmt::Display for PassTimes {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             writeln!(f, "======== ========  ==================================")?;
@@ -241,6 +259,12 @@ mod details {
     pub struct TimingToken;
     /// Dummy `PassTimes`
     pub struct PassTimes;
+    impl PassTimes {
+        /// Returns a zero duration
+        pub fn total(&self, _pass: Pass) -> core::time::Duration {
+            Default::default()
+        }
+    }
     /// Returns dummy `PassTimes`
     pub fn take_current() -> PassTimes {
         PassTimes
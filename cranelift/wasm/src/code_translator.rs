@@ -77,7 +77,9 @@ use crate::state::{ControlStackFrame, ElseData, FuncTranslationState};
 use crate::translation_utils::{
     block_with_params, blocktype_params_results, f32_translation, f64_translation,
 };
-use crate::translation_utils::{FuncIndex, GlobalIndex, MemoryIndex, TableIndex, TypeIndex};
+use crate::translation_utils::{
+    FuncIndex, GlobalIndex, MemoryIndex, TableIndex, TagIndex, TypeIndex,
+};
 use crate::wasm_unsupported;
 use core::convert::TryInto;
 use core::{i32, u32};
@@ -549,9 +551,15 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             state.reachable = false;
         }
         /********************************** Exception handing **********************************/
+        Operator::Throw { index } => {
+            let tag_index = TagIndex::from_u32(*index);
+            let count = environ.tag_param_count(tag_index)?;
+            environ.translate_throw(builder, tag_index, state.peekn(count))?;
+            state.popn(count);
+            state.reachable = false;
+        }
         Operator::Try { .. }
         | Operator::Catch { .. }
-        | Operator::Throw { .. }
         | Operator::Rethrow { .. }
         | Operator::Delegate { .. }
         | Operator::CatchAll => {
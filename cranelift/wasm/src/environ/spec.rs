@@ -647,6 +647,49 @@ pub trait FuncEnvironment: TargetEnvironment {
         count: ir::Value,
     ) -> WasmResult<ir::Value>;
 
+    /// Returns the number of values carried by exceptions with the given tag.
+    fn tag_param_count(&self, tag_index: TagIndex) -> WasmResult<usize> {
+        drop(tag_index);
+        Err(WasmError::Unsupported("wasm tags".to_string()))
+    }
+
+    /// Translate a `throw` instruction. `args` are the values carried by the
+    /// exception, in the order of the tag's parameters.
+    ///
+    /// Code following a `throw` is unreachable, so this must terminate the
+    /// current block.
+    fn translate_throw(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        tag_index: TagIndex,
+        args: &[ir::Value],
+    ) -> WasmResult<()> {
+        drop((builder, tag_index, args));
+        Err(WasmError::Unsupported("wasm exceptions".to_string()))
+    }
+
+    /// Translate the start of a `catch` clause for `tag_index`, returning the
+    /// values carried by the caught exception.
+    fn translate_catch(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        tag_index: TagIndex,
+    ) -> WasmResult<Vec<ir::Value>> {
+        drop((builder, tag_index));
+        Err(WasmError::Unsupported("wasm exceptions".to_string()))
+    }
+
+    /// Translate a `rethrow` of the exception caught by the `catch` clause
+    /// `relative_depth` blocks up.
+    fn translate_rethrow(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        relative_depth: u32,
+    ) -> WasmResult<()> {
+        drop((builder, relative_depth));
+        Err(WasmError::Unsupported("wasm exceptions".to_string()))
+    }
+
     /// Emit code at the beginning of every wasm loop.
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
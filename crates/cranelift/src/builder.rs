@@ -7,12 +7,14 @@ use anyhow::Result;
 use cranelift_codegen::isa;
 use cranelift_codegen::settings::{self, Configurable, SetError};
 use std::fmt;
+use std::path::PathBuf;
 use wasmtime_environ::{CompilerBuilder, Setting, SettingKind};
 
 #[derive(Clone)]
 struct Builder {
     flags: settings::Builder,
     isa_flags: isa::Builder,
+    clif_dir: Option<PathBuf>,
 }
 
 pub fn builder() -> Box<dyn CompilerBuilder> {
@@ -32,6 +34,7 @@ pub fn builder() -> Box<dyn CompilerBuilder> {
     Box::new(Builder {
         flags,
         isa_flags: cranelift_native::builder().expect("host machine is not a supported target"),
+        clif_dir: None,
     })
 }
 
@@ -75,12 +78,16 @@ impl CompilerBuilder for Builder {
         Ok(())
     }
 
+    fn dump_clif(&mut self, dir: Option<PathBuf>) {
+        self.clif_dir = dir;
+    }
+
     fn build(&self) -> Box<dyn wasmtime_environ::Compiler> {
         let isa = self
             .isa_flags
             .clone()
             .finish(settings::Flags::new(self.flags.clone()));
-        Box::new(crate::compiler::Compiler::new(isa))
+        Box::new(crate::compiler::Compiler::new(isa, self.clif_dir.clone()))
     }
 
     fn settings(&self) -> Vec<Setting> {
@@ -108,6 +115,7 @@ impl fmt::Debug for Builder {
                 "flags",
                 &settings::Flags::new(self.flags.clone()).to_string(),
             )
+            .field("clif_dir", &self.clif_dir)
             .finish()
     }
 }
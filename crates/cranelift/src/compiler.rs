@@ -6,6 +6,7 @@ use cranelift_codegen::ir::{self, ExternalName, InstBuilder, MemFlags};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::settings;
+use cranelift_codegen::timing;
 use cranelift_codegen::MachSrcLoc;
 use cranelift_codegen::{binemit, Context};
 use cranelift_entity::EntityRef;
@@ -15,15 +16,20 @@ use cranelift_wasm::{
     WasmFuncType,
 };
 use std::cmp;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fs;
 use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use wasmtime_environ::{
-    CompileError, CompiledFunction, CompiledFunctions, FlagValue, FunctionAddressMap,
-    FunctionBodyData, InstructionAddressMap, Module, ModuleMemoryOffset, ModuleTranslation,
-    Relocation, RelocationTarget, StackMapInformation, TrapInformation, Tunables, TypeTables,
-    VMOffsets,
+    CompileError, CompileTimeMetrics, CompiledFunction, CompiledFunctions, FlagValue,
+    FunctionAddressMap, FunctionBodyData, InstructionAddressMap, Module, ModuleMemoryOffset,
+    ModuleTranslation, ProfileData, Relocation, RelocationTarget, StackMapInformation,
+    TrapInformation, Tunables, TypeTables, VMOffsets,
 };
 
 /// A compiler that compiles a WebAssembly module with Compiler, translating
@@ -31,16 +37,71 @@ use wasmtime_environ::{
 pub(crate) struct Compiler {
     translators: Mutex<Vec<FuncTranslator>>,
     isa: Box<dyn TargetIsa>,
+    // Where to dump the CLIF of each compiled function, if anywhere.
+    clif_dir: Option<PathBuf>,
 }
 
 impl Compiler {
-    pub(crate) fn new(isa: Box<dyn TargetIsa>) -> Compiler {
+    pub(crate) fn new(isa: Box<dyn TargetIsa>, clif_dir: Option<PathBuf>) -> Compiler {
         Compiler {
             translators: Default::default(),
             isa,
+            clif_dir,
         }
     }
 
+    /// Writes the CLIF of `func` to
+    /// `<clif dir>/<module fingerprint>/<module name>.<defined func index>.<stage>.clif`
+    /// if a CLIF directory was configured.
+    ///
+    /// Every module gets its own directory, so unnamed modules don't overwrite
+    /// each other's files. Identical modules may still be compiled at the same
+    /// time, so each file is written next to its destination first and then
+    /// renamed into place.
+    fn dump_clif(
+        &self,
+        module: &Module,
+        func_index: FuncIndex,
+        stage: &str,
+        func: &ir::Function,
+    ) -> Result<(), CompileError> {
+        let dir = match &self.clif_dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let dir = dir.join(
+            module
+                .fingerprint
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+        );
+        let name = module
+            .name
+            .as_deref()
+            .unwrap_or("module")
+            .replace(|c| c == '/' || c == '\\', "_");
+        let defined_index = module.defined_func_index(func_index).unwrap();
+        let path = dir.join(format!(
+            "{}.{}.{}.clif",
+            name,
+            defined_index.as_u32(),
+            stage
+        ));
+        static NEXT_TMP_FILE: AtomicUsize = AtomicUsize::new(0);
+        let tmp = dir.join(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            NEXT_TMP_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)
+            .and_then(|()| fs::write(&tmp, func.display(&*self.isa).to_string()))
+            .and_then(|()| fs::rename(&tmp, &path))
+            .map_err(|e| {
+                CompileError::Codegen(format!("failed to write `{}`: {}", path.display(), e))
+            })
+    }
+
     fn take_translator(&self) -> FuncTranslator {
         let candidate = self.translators.lock().unwrap().pop();
         candidate.unwrap_or_else(FuncTranslator::new)
@@ -106,107 +167,30 @@ impl wasmtime_environ::Compiler for Compiler {
         &self,
         translation: &ModuleTranslation<'_>,
         func_index: DefinedFuncIndex,
-        mut input: FunctionBodyData<'_>,
+        input: FunctionBodyData<'_>,
         tunables: &Tunables,
         types: &TypeTables,
     ) -> Result<CompiledFunction, CompileError> {
-        let isa = &*self.isa;
-        let module = &translation.module;
-        let func_index = module.func_index(func_index);
-        let mut context = Context::new();
-        context.func.name = get_func_name(func_index);
-        context.func.signature = func_signature(isa, module, types, func_index);
-        if tunables.generate_native_debuginfo {
-            context.func.collect_debug_info();
-        }
-
-        let mut func_env = FuncEnvironment::new(isa, module, types, tunables);
-
-        // We use these as constant offsets below in
-        // `stack_limit_from_arguments`, so assert their values here. This
-        // allows the closure below to get coerced to a function pointer, as
-        // needed by `ir::Function`.
-        //
-        // Otherwise our stack limit is specially calculated from the vmctx
-        // argument, where we need to load the `*const VMInterrupts`
-        // pointer, and then from that pointer we need to load the stack
-        // limit itself. Note that manual register allocation is needed here
-        // too due to how late in the process this codegen happens.
-        //
-        // For more information about interrupts and stack checks, see the
-        // top of this file.
-        let vmctx = context
-            .func
-            .create_global_value(ir::GlobalValueData::VMContext);
-        let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
-            base: vmctx,
-            offset: i32::try_from(func_env.offsets.vmctx_interrupts())
-                .unwrap()
-                .into(),
-            global_type: isa.pointer_type(),
-            readonly: true,
-        });
-        let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
-            base: interrupts_ptr,
-            offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
-                .unwrap()
-                .into(),
-            global_type: isa.pointer_type(),
-            readonly: false,
-        });
-        context.func.stack_limit = Some(stack_limit);
-        let mut func_translator = self.take_translator();
-        func_translator.translate_body(
-            &mut input.validator,
-            input.body.clone(),
-            &mut context.func,
-            &mut func_env,
-        )?;
-        self.save_translator(func_translator);
-
-        let mut code_buf: Vec<u8> = Vec::new();
-        let mut reloc_sink = RelocSink::new(func_index);
-        let mut trap_sink = TrapSink::new();
-        let mut stack_map_sink = StackMapSink::default();
-        context
-            .compile_and_emit(
-                isa,
-                &mut code_buf,
-                &mut reloc_sink,
-                &mut trap_sink,
-                &mut stack_map_sink,
-            )
-            .map_err(|error| {
-                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-            })?;
-
-        let unwind_info = context.create_unwind_info(isa).map_err(|error| {
-            CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-        })?;
-
-        let address_transform =
-            self.get_function_address_map(&context, &input, code_buf.len() as u32);
-
-        let ranges = if tunables.generate_native_debuginfo {
-            let ranges = context.build_value_labels_ranges(isa).map_err(|error| {
-                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-            })?;
-            Some(ranges)
-        } else {
-            None
-        };
+        self.compile_function_impl(translation, func_index, input, tunables, types, None)
+    }
 
-        Ok(CompiledFunction {
-            body: code_buf,
-            jt_offsets: context.func.jt_offsets,
-            relocations: reloc_sink.func_relocs,
-            address_map: address_transform,
-            value_labels_ranges: ranges.unwrap_or(Default::default()),
-            stack_slots: context.func.stack_slots,
-            traps: trap_sink.traps,
-            unwind_info,
-            stack_maps: stack_map_sink.finish(),
-        })
+    fn compile_function_with_profile(
+        &self,
+        translation: &ModuleTranslation<'_>,
+        func_index: DefinedFuncIndex,
+        input: FunctionBodyData<'_>,
+        tunables: &Tunables,
+        types: &TypeTables,
+        profile: &dyn ProfileData,
+    ) -> Result<CompiledFunction, CompileError> {
+        self.compile_function_impl(
+            translation,
+            func_index,
+            input,
+            tunables,
+            types,
+            Some(profile),
+        )
     }
 
     fn emit_obj(
@@ -320,6 +304,158 @@ fn to_flag_value(v: &settings::Value) -> FlagValue {
 }
 
 impl Compiler {
+    fn compile_function_impl(
+        &self,
+        translation: &ModuleTranslation<'_>,
+        func_index: DefinedFuncIndex,
+        input: FunctionBodyData<'_>,
+        tunables: &Tunables,
+        types: &TypeTables,
+        profile: Option<&dyn ProfileData>,
+    ) -> Result<CompiledFunction, CompileError> {
+        // Cranelift accumulates pass timings per thread, so set aside what has
+        // been gathered so far to get the timings of just this function, and
+        // merge everything back together afterwards.
+        let prev_times = timing::take_current();
+        let start = Instant::now();
+        let result =
+            self.compile_function_untimed(translation, func_index, input, tunables, types, profile);
+        let total = start.elapsed();
+        let times = timing::take_current();
+        timing::add_to_current(&prev_times);
+        timing::add_to_current(&times);
+
+        let mut func = result?;
+        func.compile_time_metrics = compile_time_metrics(total, &times);
+        Ok(func)
+    }
+
+    fn compile_function_untimed(
+        &self,
+        translation: &ModuleTranslation<'_>,
+        func_index: DefinedFuncIndex,
+        mut input: FunctionBodyData<'_>,
+        tunables: &Tunables,
+        types: &TypeTables,
+        profile: Option<&dyn ProfileData>,
+    ) -> Result<CompiledFunction, CompileError> {
+        let isa = &*self.isa;
+        let module = &translation.module;
+        let func_index = module.func_index(func_index);
+        let mut context = Context::new();
+        context.func.name = get_func_name(func_index);
+        context.func.signature = func_signature(isa, module, types, func_index);
+        if tunables.generate_native_debuginfo {
+            context.func.collect_debug_info();
+        }
+
+        let mut func_env = FuncEnvironment::new(isa, module, types, tunables);
+
+        // We use these as constant offsets below in
+        // `stack_limit_from_arguments`, so assert their values here. This
+        // allows the closure below to get coerced to a function pointer, as
+        // needed by `ir::Function`.
+        //
+        // Otherwise our stack limit is specially calculated from the vmctx
+        // argument, where we need to load the `*const VMInterrupts`
+        // pointer, and then from that pointer we need to load the stack
+        // limit itself. Note that manual register allocation is needed here
+        // too due to how late in the process this codegen happens.
+        //
+        // For more information about interrupts and stack checks, see the
+        // top of this file.
+        let vmctx = context
+            .func
+            .create_global_value(ir::GlobalValueData::VMContext);
+        let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
+            base: vmctx,
+            offset: i32::try_from(func_env.offsets.vmctx_interrupts())
+                .unwrap()
+                .into(),
+            global_type: isa.pointer_type(),
+            readonly: true,
+        });
+        let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
+            base: interrupts_ptr,
+            offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
+                .unwrap()
+                .into(),
+            global_type: isa.pointer_type(),
+            readonly: false,
+        });
+        context.func.stack_limit = Some(stack_limit);
+        let mut func_translator = self.take_translator();
+        func_translator.translate_body(
+            &mut input.validator,
+            input.body.clone(),
+            &mut context.func,
+            &mut func_env,
+        )?;
+        self.save_translator(func_translator);
+
+        if let Some(hot) = profile.and_then(|p| p.hot_blocks(func_index)) {
+            place_hot_blocks(&mut context.func, hot);
+        }
+        self.dump_clif(module, func_index, "pre", &context.func)?;
+
+        let mut code_buf: Vec<u8> = Vec::new();
+        let mut reloc_sink = RelocSink::new(func_index);
+        let mut trap_sink = TrapSink::new();
+        let mut stack_map_sink = StackMapSink::default();
+        context
+            .compile_and_emit(
+                isa,
+                &mut code_buf,
+                &mut reloc_sink,
+                &mut trap_sink,
+                &mut stack_map_sink,
+            )
+            .map_err(|error| {
+                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
+            })?;
+        self.dump_clif(module, func_index, "post", &context.func)?;
+
+        let unwind_info = context.create_unwind_info(isa).map_err(|error| {
+            CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
+        })?;
+
+        let address_transform =
+            self.get_function_address_map(&context, &input, code_buf.len() as u32);
+
+        let fixed_frame_size = match &context.mach_compile_result {
+            Some(mcr) => mcr.frame_size,
+            None => context
+                .func
+                .stack_slots
+                .layout_info
+                .map_or(0, |info| info.frame_size),
+        };
+        let frame_size = fixed_frame_size.saturating_add(2 * u32::from(isa.pointer_bytes()));
+
+        let ranges = if tunables.generate_native_debuginfo {
+            let ranges = context.build_value_labels_ranges(isa).map_err(|error| {
+                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
+            })?;
+            Some(ranges)
+        } else {
+            None
+        };
+
+        Ok(CompiledFunction {
+            body: code_buf,
+            jt_offsets: context.func.jt_offsets,
+            relocations: reloc_sink.func_relocs,
+            address_map: address_transform,
+            value_labels_ranges: ranges.unwrap_or(Default::default()),
+            stack_slots: context.func.stack_slots,
+            traps: trap_sink.traps,
+            unwind_info,
+            stack_maps: stack_map_sink.finish(),
+            frame_size,
+            compile_time_metrics: Default::default(),
+        })
+    }
+
     fn host_to_wasm_trampoline(&self, ty: &WasmFuncType) -> Result<CompiledFunction, CompileError> {
         let isa = &*self.isa;
         let value_size = mem::size_of::<u128>();
@@ -512,7 +648,55 @@ impl Compiler {
             traps: Default::default(),
             value_labels_ranges: Default::default(),
             address_map: Default::default(),
+            frame_size: 0,
+            compile_time_metrics: Default::default(),
+        })
+    }
+}
+
+/// Summarizes the Cranelift pass timings recorded while compiling a single
+/// function.
+fn compile_time_metrics(total: Duration, times: &timing::PassTimes) -> CompileTimeMetrics {
+    let nanos = |dur: Duration| u64::try_from(dur.as_nanos()).unwrap_or(u64::max_value());
+    let ns = |passes: &[timing::Pass]| nanos(passes.iter().map(|pass| times.total(*pass)).sum());
+    CompileTimeMetrics {
+        total_ns: nanos(total),
+        ir_gen_ns: ns(&[timing::Pass::wasm_translate_function]),
+        // The old backend legalizes the IR while the new backends lower it to
+        // machine instructions instead; only one of these runs.
+        legalize_ns: ns(&[timing::Pass::legalize, timing::Pass::vcode_lower]),
+        regalloc_ns: ns(&[timing::Pass::regalloc]),
+        emit_ns: ns(&[
+            timing::Pass::vcode_emit,
+            timing::Pass::vcode_emit_finish,
+            timing::Pass::binemit,
+        ]),
+    }
+}
+
+/// Marks the blocks of `func` which don't contain an instruction at any of
+/// the `hot` bytecode offsets as cold, so that they're placed after the hot
+/// blocks in the emitted code.
+///
+/// Nothing is marked if none of the offsets are found in `func`, as the
+/// profile then doesn't tell which of its blocks are hot.
+fn place_hot_blocks(func: &mut ir::Function, hot: &[u32]) {
+    let hot = hot.iter().copied().collect::<HashSet<_>>();
+    let cold = func
+        .layout
+        .blocks()
+        .filter(|block| {
+            !func
+                .layout
+                .block_insts(*block)
+                .any(|inst| hot.contains(&func.srclocs[inst].bits()))
         })
+        .collect::<Vec<_>>();
+    if cold.len() == func.layout.blocks().count() {
+        return;
+    }
+    for block in cold {
+        func.layout.set_cold(block);
     }
 }
 
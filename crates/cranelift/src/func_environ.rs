@@ -10,14 +10,14 @@ use cranelift_frontend::FunctionBuilder;
 use cranelift_frontend::Variable;
 use cranelift_wasm::{
     self, FuncIndex, FuncTranslationState, GlobalIndex, GlobalVariable, MemoryIndex, TableIndex,
-    TargetEnvironment, TypeIndex, WasmError, WasmResult, WasmType,
+    TagIndex, TargetEnvironment, TypeIndex, WasmError, WasmResult, WasmType,
 };
 use std::convert::TryFrom;
 use std::mem;
 use wasmparser::Operator;
 use wasmtime_environ::{
-    BuiltinFunctionIndex, MemoryPlan, MemoryStyle, Module, TableStyle, Tunables, TypeTables,
-    VMOffsets, INTERRUPTED, WASM_PAGE_SIZE,
+    BuiltinFunctionIndex, GcType, MemoryPlan, MemoryStyle, Module, TableStyle, Tunables,
+    TypeTables, VMOffsets, INTERRUPTED, WASM_PAGE_SIZE,
 };
 
 /// Compute an `ir::ExternalName` for a given wasm function index.
@@ -299,6 +299,132 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         )
     }
 
+    /// Emits the read barrier required by the configured garbage collector
+    /// for the reference `val` of type `ty` which was just read out of the
+    /// heap, returning the value to use in its place.
+    ///
+    /// This is a no-op when no garbage collector is configured or when `ty`
+    /// isn't managed by the garbage collector.
+    fn translate_gc_read_barrier(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        val: ir::Value,
+        ty: WasmType,
+    ) -> ir::Value {
+        match (self.tunables.gc_type, ty) {
+            (GcType::DeferredReferenceCounting, WasmType::ExternRef) => {}
+            _ => return val,
+        }
+
+        // Our read barrier for `externref`s is roughly equivalent to the
+        // following pseudocode:
+        //
+        // ```
+        // if val is not null:
+        //     let (next, end) = VMExternRefActivationsTable bump region
+        //     if next != end:
+        //         val.ref_count += 1
+        //         *next = val
+        //         next += 1
+        //     else:
+        //         call activations_table_insert_with_gc(val)
+        // return val
+        // ```
+        //
+        // This ensures that all `externref`s coming out of the heap and onto
+        // the stack are safely held alive by the
+        // `VMExternRefActivationsTable`.
+
+        let pointer_type = self.pointer_type();
+        let reference_type = self.reference_type(ty);
+
+        builder.ensure_inserted_block();
+        let continue_block = builder.create_block();
+        let non_null_elem_block = builder.create_block();
+        let gc_block = builder.create_block();
+        let no_gc_block = builder.create_block();
+        let current_block = builder.current_block().unwrap();
+        builder.insert_block_after(non_null_elem_block, current_block);
+        builder.insert_block_after(no_gc_block, non_null_elem_block);
+        builder.insert_block_after(gc_block, no_gc_block);
+        builder.insert_block_after(continue_block, gc_block);
+
+        let elem_is_null = builder.ins().is_null(val);
+        builder.ins().brnz(elem_is_null, continue_block, &[]);
+        builder.ins().jump(non_null_elem_block, &[]);
+
+        // Load the `VMExternRefActivationsTable::next` bump finger and
+        // the `VMExternRefActivationsTable::end` bump boundary.
+        builder.switch_to_block(non_null_elem_block);
+        let vmctx = self.vmctx(&mut builder.func);
+        let vmctx = builder.ins().global_value(pointer_type, vmctx);
+        let activations_table = builder.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted(),
+            vmctx,
+            i32::try_from(self.offsets.vmctx_externref_activations_table()).unwrap(),
+        );
+        let next = builder.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted(),
+            activations_table,
+            i32::try_from(self.offsets.vm_extern_ref_activation_table_next()).unwrap(),
+        );
+        let end = builder.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted(),
+            activations_table,
+            i32::try_from(self.offsets.vm_extern_ref_activation_table_end()).unwrap(),
+        );
+
+        // If `next == end`, then we are at full capacity. Call a
+        // builtin to do a GC and insert this reference into the
+        // just-swept table for us.
+        let at_capacity = builder.ins().icmp(ir::condcodes::IntCC::Equal, next, end);
+        builder.ins().brnz(at_capacity, gc_block, &[]);
+        builder.ins().jump(no_gc_block, &[]);
+        builder.switch_to_block(gc_block);
+        let builtin_idx = BuiltinFunctionIndex::activations_table_insert_with_gc();
+        let builtin_sig = self
+            .builtin_function_signatures
+            .activations_table_insert_with_gc(builder.func);
+        let (vmctx, builtin_addr) =
+            self.translate_load_builtin_function_address(&mut builder.cursor(), builtin_idx);
+        builder
+            .ins()
+            .call_indirect(builtin_sig, builtin_addr, &[vmctx, val]);
+        builder.ins().jump(continue_block, &[]);
+
+        // If `next != end`, then:
+        //
+        // * increment this reference's ref count,
+        // * store the reference into the bump table at `*next`,
+        // * and finally increment the `next` bump finger.
+        builder.switch_to_block(no_gc_block);
+        self.mutate_extenref_ref_count(builder, val, 1);
+        builder.ins().store(ir::MemFlags::trusted(), val, next, 0);
+
+        let new_next = builder
+            .ins()
+            .iadd_imm(next, i64::from(reference_type.bytes()));
+        builder.ins().store(
+            ir::MemFlags::trusted(),
+            new_next,
+            activations_table,
+            i32::try_from(self.offsets.vm_extern_ref_activation_table_next()).unwrap(),
+        );
+
+        builder.ins().jump(continue_block, &[]);
+        builder.switch_to_block(continue_block);
+
+        builder.seal_block(non_null_elem_block);
+        builder.seal_block(gc_block);
+        builder.seal_block(no_gc_block);
+        builder.seal_block(continue_block);
+
+        val
+    }
+
     fn get_global_location(
         &mut self,
         func: &mut ir::Function,
@@ -609,6 +735,159 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             pos.ins().uextend(I64, val)
         }
     }
+
+    /// Translates `memory.fill` without a bounds check: the destination
+    /// address is computed from the heap's base and passed to the
+    /// `memory_fill_unchecked` builtin, which fills it directly.
+    ///
+    /// This is used instead of the checked translation when
+    /// `Tunables::skip_bounds_checks` is enabled.
+    fn translate_memory_fill_unchecked(
+        &mut self,
+        mut pos: FuncCursor<'_>,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        let func_sig = self
+            .builtin_function_signatures
+            .memory_fill_unchecked(&mut pos.func);
+
+        let addr = self.unchecked_heap_addr(&mut pos, index, heap, dst);
+        let len = self.cast_memory_index_to_i64(&mut pos, len, index);
+
+        let (_, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::memory_fill_unchecked(),
+        );
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[addr, val, len]);
+
+        Ok(())
+    }
+
+    /// Returns the host address of `offset` within the heap for memory
+    /// `index`, without checking that it's in bounds.
+    fn unchecked_heap_addr(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        offset: ir::Value,
+    ) -> ir::Value {
+        let pointer_type = self.pointer_type();
+        let base_gv = pos.func.heaps[heap].base;
+        let base = pos.ins().global_value(pointer_type, base_gv);
+        let mut offset = self.cast_memory_index_to_i64(pos, offset, index);
+        if pointer_type != I64 {
+            offset = pos.ins().ireduce(pointer_type, offset);
+        }
+        pos.ins().iadd(base, offset)
+    }
+
+    /// Translates `memory.copy` without bounds checks: the source and
+    /// destination addresses are computed from the heaps' bases and passed
+    /// to the `memory_copy_unchecked` builtin, which does a plain `memmove`.
+    ///
+    /// This is used instead of the checked translation when
+    /// `Tunables::skip_bounds_checks` is enabled.
+    ///
+    /// Safety: nothing here traps on an out-of-bounds range, so a copy past
+    /// the end of either memory reads or writes whatever host memory follows
+    /// it, which may be another instance's memory rather than a guard page.
+    /// This is only correct for modules whose copies have been verified to
+    /// stay in bounds, for example by a static analysis, which is why it's
+    /// opt-in through an unsafe configuration option.
+    fn translate_memory_copy_unchecked(
+        &mut self,
+        mut pos: FuncCursor<'_>,
+        src_index: MemoryIndex,
+        src_heap: ir::Heap,
+        dst_index: MemoryIndex,
+        dst_heap: ir::Heap,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        let func_sig = self
+            .builtin_function_signatures
+            .memory_copy_unchecked(&mut pos.func);
+
+        let dst = self.unchecked_heap_addr(&mut pos, dst_index, dst_heap, dst);
+        let src = self.unchecked_heap_addr(&mut pos, src_index, src_heap, src);
+        // See `translate_memory_copy` for how the length is widened.
+        let len = if self.memory_index_type(dst_index) == I64
+            && self.memory_index_type(src_index) == I64
+        {
+            len
+        } else {
+            pos.ins().uextend(I64, len)
+        };
+
+        let (_, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::memory_copy_unchecked(),
+        );
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[dst, src, len]);
+
+        Ok(())
+    }
+
+    /// Emits a call to the `memory.grow` builtin, returning the delta passed
+    /// to it and the pointer-sized result.
+    fn memory_grow_call(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        index: MemoryIndex,
+        val: ir::Value,
+    ) -> (ir::Value, ir::Value) {
+        let func_sig = self
+            .builtin_function_signatures
+            .memory32_grow(&mut pos.func);
+        let index_arg = index.index();
+
+        let memory_index = pos.ins().iconst(I32, index_arg as i64);
+        let (vmctx, func_addr) = self
+            .translate_load_builtin_function_address(pos, BuiltinFunctionIndex::memory32_grow());
+
+        let val = self.cast_memory_index_to_i64(pos, val, index);
+        let call_inst = pos
+            .ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, val, memory_index]);
+        let result = *pos.func.dfg.inst_results(call_inst).first().unwrap();
+        (val, result)
+    }
+
+    /// Translates `memory.grow` like `translate_memory_grow`, additionally
+    /// notifying the store's `ResourceLimiter` of the outcome through the
+    /// `memory_grow_notify` builtin before the result is returned to wasm.
+    ///
+    /// This is used instead of the plain translation when
+    /// `Tunables::monitor_memory_grow` is enabled.
+    fn translate_memory_grow_monitored(
+        &mut self,
+        mut pos: FuncCursor<'_>,
+        index: MemoryIndex,
+        val: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let (delta, result) = self.memory_grow_call(&mut pos, index, val);
+
+        let func_sig = self
+            .builtin_function_signatures
+            .memory_grow_notify(&mut pos.func);
+        let memory_index = pos.ins().iconst(I32, index.index() as i64);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::memory_grow_notify(),
+        );
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, memory_index, delta, result]);
+
+        Ok(self.cast_pointer_to_memory_index(pos, result, index))
+    }
 }
 
 impl<'module_environment> TargetEnvironment for FuncEnvironment<'module_environment> {
@@ -750,39 +1029,8 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 }
             },
             WasmType::ExternRef => {
-                // Our read barrier for `externref` tables is roughly equivalent
-                // to the following pseudocode:
-                //
-                // ```
-                // let elem = table[index]
-                // if elem is not null:
-                //     let (next, end) = VMExternRefActivationsTable bump region
-                //     if next != end:
-                //         elem.ref_count += 1
-                //         *next = elem
-                //         next += 1
-                //     else:
-                //         call activations_table_insert_with_gc(elem)
-                // return elem
-                // ```
-                //
-                // This ensures that all `externref`s coming out of tables and
-                // onto the stack are safely held alive by the
-                // `VMExternRefActivationsTable`.
-
                 let reference_type = self.reference_type(WasmType::ExternRef);
 
-                builder.ensure_inserted_block();
-                let continue_block = builder.create_block();
-                let non_null_elem_block = builder.create_block();
-                let gc_block = builder.create_block();
-                let no_gc_block = builder.create_block();
-                let current_block = builder.current_block().unwrap();
-                builder.insert_block_after(non_null_elem_block, current_block);
-                builder.insert_block_after(no_gc_block, non_null_elem_block);
-                builder.insert_block_after(gc_block, no_gc_block);
-                builder.insert_block_after(continue_block, gc_block);
-
                 // Load the table element.
                 let elem_addr = builder.ins().table_addr(pointer_type, table, index, 0);
                 let elem =
@@ -790,80 +1038,7 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                         .ins()
                         .load(reference_type, ir::MemFlags::trusted(), elem_addr, 0);
 
-                let elem_is_null = builder.ins().is_null(elem);
-                builder.ins().brnz(elem_is_null, continue_block, &[]);
-                builder.ins().jump(non_null_elem_block, &[]);
-
-                // Load the `VMExternRefActivationsTable::next` bump finger and
-                // the `VMExternRefActivationsTable::end` bump boundary.
-                builder.switch_to_block(non_null_elem_block);
-                let vmctx = self.vmctx(&mut builder.func);
-                let vmctx = builder.ins().global_value(pointer_type, vmctx);
-                let activations_table = builder.ins().load(
-                    pointer_type,
-                    ir::MemFlags::trusted(),
-                    vmctx,
-                    i32::try_from(self.offsets.vmctx_externref_activations_table()).unwrap(),
-                );
-                let next = builder.ins().load(
-                    pointer_type,
-                    ir::MemFlags::trusted(),
-                    activations_table,
-                    i32::try_from(self.offsets.vm_extern_ref_activation_table_next()).unwrap(),
-                );
-                let end = builder.ins().load(
-                    pointer_type,
-                    ir::MemFlags::trusted(),
-                    activations_table,
-                    i32::try_from(self.offsets.vm_extern_ref_activation_table_end()).unwrap(),
-                );
-
-                // If `next == end`, then we are at full capacity. Call a
-                // builtin to do a GC and insert this reference into the
-                // just-swept table for us.
-                let at_capacity = builder.ins().icmp(ir::condcodes::IntCC::Equal, next, end);
-                builder.ins().brnz(at_capacity, gc_block, &[]);
-                builder.ins().jump(no_gc_block, &[]);
-                builder.switch_to_block(gc_block);
-                let builtin_idx = BuiltinFunctionIndex::activations_table_insert_with_gc();
-                let builtin_sig = self
-                    .builtin_function_signatures
-                    .activations_table_insert_with_gc(builder.func);
-                let (vmctx, builtin_addr) = self
-                    .translate_load_builtin_function_address(&mut builder.cursor(), builtin_idx);
-                builder
-                    .ins()
-                    .call_indirect(builtin_sig, builtin_addr, &[vmctx, elem]);
-                builder.ins().jump(continue_block, &[]);
-
-                // If `next != end`, then:
-                //
-                // * increment this reference's ref count,
-                // * store the reference into the bump table at `*next`,
-                // * and finally increment the `next` bump finger.
-                builder.switch_to_block(no_gc_block);
-                self.mutate_extenref_ref_count(builder, elem, 1);
-                builder.ins().store(ir::MemFlags::trusted(), elem, next, 0);
-
-                let new_next = builder
-                    .ins()
-                    .iadd_imm(next, i64::from(reference_type.bytes()));
-                builder.ins().store(
-                    ir::MemFlags::trusted(),
-                    new_next,
-                    activations_table,
-                    i32::try_from(self.offsets.vm_extern_ref_activation_table_next()).unwrap(),
-                );
-
-                builder.ins().jump(continue_block, &[]);
-                builder.switch_to_block(continue_block);
-
-                builder.seal_block(non_null_elem_block);
-                builder.seal_block(gc_block);
-                builder.seal_block(no_gc_block);
-                builder.seal_block(continue_block);
-
-                Ok(elem)
+                Ok(self.translate_gc_read_barrier(builder, elem, WasmType::ExternRef))
             }
             ty => Err(WasmError::Unsupported(format!(
                 "unsupported table type for `table.get` instruction: {:?}",
@@ -1439,22 +1614,10 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         _heap: ir::Heap,
         val: ir::Value,
     ) -> WasmResult<ir::Value> {
-        let func_sig = self
-            .builtin_function_signatures
-            .memory32_grow(&mut pos.func);
-        let index_arg = index.index();
-
-        let memory_index = pos.ins().iconst(I32, index_arg as i64);
-        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
-            &mut pos,
-            BuiltinFunctionIndex::memory32_grow(),
-        );
-
-        let val = self.cast_memory_index_to_i64(&mut pos, val, index);
-        let call_inst = pos
-            .ins()
-            .call_indirect(func_sig, func_addr, &[vmctx, val, memory_index]);
-        let result = *pos.func.dfg.inst_results(call_inst).first().unwrap();
+        if self.tunables.monitor_memory_grow {
+            return self.translate_memory_grow_monitored(pos, index, val);
+        }
+        let (_, result) = self.memory_grow_call(&mut pos, index, val);
         Ok(self.cast_pointer_to_memory_index(pos, result, index))
     }
 
@@ -1501,13 +1664,19 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         &mut self,
         mut pos: FuncCursor,
         src_index: MemoryIndex,
-        _src_heap: ir::Heap,
+        src_heap: ir::Heap,
         dst_index: MemoryIndex,
-        _dst_heap: ir::Heap,
+        dst_heap: ir::Heap,
         dst: ir::Value,
         src: ir::Value,
         len: ir::Value,
     ) -> WasmResult<()> {
+        if self.tunables.skip_bounds_checks {
+            return self.translate_memory_copy_unchecked(
+                pos, src_index, src_heap, dst_index, dst_heap, dst, src, len,
+            );
+        }
+
         let (vmctx, func_addr) = self
             .translate_load_builtin_function_address(&mut pos, BuiltinFunctionIndex::memory_copy());
 
@@ -1541,11 +1710,15 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         &mut self,
         mut pos: FuncCursor,
         memory_index: MemoryIndex,
-        _heap: ir::Heap,
+        heap: ir::Heap,
         dst: ir::Value,
         val: ir::Value,
         len: ir::Value,
     ) -> WasmResult<()> {
+        if self.tunables.skip_bounds_checks {
+            return self.translate_memory_fill_unchecked(pos, memory_index, heap, dst, val, len);
+        }
+
         let func_sig = self.builtin_function_signatures.memory_fill(&mut pos.func);
         let dst = self.cast_memory_index_to_i64(&mut pos, dst, memory_index);
         let len = self.cast_memory_index_to_i64(&mut pos, len, memory_index);
@@ -1737,6 +1910,89 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
+    fn tag_param_count(&self, tag_index: TagIndex) -> WasmResult<usize> {
+        let sig_index = self.module.tags[tag_index];
+        Ok(self.types.wasm_signatures[sig_index].params.len())
+    }
+
+    fn translate_throw(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        tag_index: TagIndex,
+        args: &[ir::Value],
+    ) -> WasmResult<()> {
+        let sig_index = self.module.tags[tag_index];
+        let params = &self.types.wasm_signatures[sig_index].params;
+        if params
+            .iter()
+            .any(|ty| matches!(ty, WasmType::FuncRef | WasmType::ExternRef))
+        {
+            return Err(WasmError::Unsupported(
+                "exception tags with reference-typed parameters".to_string(),
+            ));
+        }
+
+        // Spill the exception's values to the stack, one `VMInvokeArgument`
+        // sized slot each, and pass the `throw` builtin a pointer to them.
+        // The builtin reads each slot as a whole `u128`, so the bytes of a
+        // slot which the value doesn't cover are zeroed first.
+        let pointer_type = self.pointer_type();
+        let values = if args.is_empty() {
+            builder.ins().iconst(pointer_type, 0)
+        } else {
+            let slot = builder.create_stack_slot(ir::StackSlotData::new(
+                ir::StackSlotKind::ExplicitSlot,
+                u32::try_from(args.len() * 16).unwrap(),
+            ));
+            let zero = builder.ins().iconst(I64, 0);
+            for (i, arg) in args.iter().enumerate() {
+                let offset = i32::try_from(i * 16).unwrap();
+                if builder.func.dfg.value_type(*arg).bytes() < 16 {
+                    builder.ins().stack_store(zero, slot, offset);
+                    builder.ins().stack_store(zero, slot, offset + 8);
+                }
+                builder.ins().stack_store(*arg, slot, offset);
+            }
+            builder.ins().stack_addr(pointer_type, slot, 0)
+        };
+
+        let builtin_index = BuiltinFunctionIndex::throw();
+        let builtin_sig = self.builtin_function_signatures.throw(builder.func);
+        let (vmctx, builtin_addr) =
+            self.translate_load_builtin_function_address(&mut builder.cursor(), builtin_index);
+        let tag = builder.ins().iconst(I32, tag_index.as_u32() as i64);
+        let len = builder.ins().iconst(I32, args.len() as i64);
+        builder
+            .ins()
+            .call_indirect(builtin_sig, builtin_addr, &[vmctx, tag, values, len]);
+
+        // The builtin unwinds back to the host and never returns.
+        builder.ins().trap(ir::TrapCode::UnreachableCodeReached);
+        Ok(())
+    }
+
+    fn translate_catch(
+        &mut self,
+        _builder: &mut FunctionBuilder,
+        _tag_index: TagIndex,
+    ) -> WasmResult<Vec<ir::Value>> {
+        // Exceptions are unwound with the same mechanism as traps, which
+        // can't resume execution in a wasm frame, so they can't be caught.
+        Err(WasmError::Unsupported(
+            "catching wasm exceptions".to_string(),
+        ))
+    }
+
+    fn translate_rethrow(
+        &mut self,
+        _builder: &mut FunctionBuilder,
+        _relative_depth: u32,
+    ) -> WasmResult<()> {
+        Err(WasmError::Unsupported(
+            "catching wasm exceptions".to_string(),
+        ))
+    }
+
     fn translate_loop_header(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
         // If enabled check the interrupt flag to prevent long or infinite
         // loops.
@@ -95,6 +95,7 @@ use target_lexicon::CallingConvention;
 use wasmtime_environ::{Module, TypeTables};
 
 pub use builder::builder;
+pub use wasmtime_environ::OptLevel;
 
 mod builder;
 mod compiler;
@@ -45,6 +45,18 @@ macro_rules! foreach_builtin_function {
             memory_atomic_wait64(vmctx, i32, pointer, i64, i64) -> (i32);
             /// Invoked when fuel has run out while executing a function.
             out_of_gas(vmctx) -> ();
+            /// Returns an index for wasm's `throw` instruction.
+            throw(vmctx, i32, pointer, i32) -> ();
+            /// Returns an index for the notification emitted after wasm's
+            /// `memory.grow` when growth is being monitored.
+            memory_grow_notify(vmctx, i32, i64, pointer) -> ();
+            /// Returns an index for wasm's `memory.fill` instruction when
+            /// bounds checks are skipped, which takes the host address to fill.
+            memory_fill_unchecked(pointer, i32, i64) -> ();
+            /// Returns an index for wasm's `memory.copy` instruction when
+            /// bounds checks are skipped, which takes the host addresses to
+            /// copy to and from.
+            memory_copy_unchecked(pointer, pointer, i64) -> ();
         }
     };
 }
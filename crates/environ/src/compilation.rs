@@ -10,11 +10,18 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[allow(missing_docs)]
 pub type CompiledFunctions = PrimaryMap<DefinedFuncIndex, CompiledFunction>;
 
+/// The direct calls in a compiled function, mapping the offset of each call
+/// in the function's machine code to the function it calls.
+///
+/// See [`Compiler::collect_call_sites`].
+pub type CallSiteMap = HashMap<u32, FuncIndex>;
+
 /// Compiled function: machine code body, jump table offsets, and unwind information.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 #[allow(missing_docs)]
@@ -34,6 +41,28 @@ pub struct CompiledFunction {
     pub stack_slots: ir::StackSlots,
     pub traps: Vec<TrapInformation>,
     pub stack_maps: Vec<StackMapInformation>,
+
+    /// The size, in bytes, of the function's stack frame, including the
+    /// return address and frame pointer saved by every frame.
+    pub frame_size: u32,
+
+    /// Time spent in the various stages of compiling this function.
+    pub compile_time_metrics: CompileTimeMetrics,
+}
+
+/// Timing information, in nanoseconds, gathered while compiling a function.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompileTimeMetrics {
+    /// Total time spent compiling the function.
+    pub total_ns: u64,
+    /// Time spent translating the function into the compiler's IR.
+    pub ir_gen_ns: u64,
+    /// Time spent legalizing the IR or lowering it to machine instructions.
+    pub legalize_ns: u64,
+    /// Time spent in register allocation.
+    pub regalloc_ns: u64,
+    /// Time spent emitting machine code.
+    pub emit_ns: u64,
 }
 
 /// A record of a relocation to perform.
@@ -88,6 +117,15 @@ pub enum CompileError {
     #[error("WebAssembly translation error")]
     Wasm(#[from] WasmError),
 
+    /// The module failed validation.
+    #[error("WebAssembly failed to validate at offset {offset}: {message}")]
+    InvalidWasm {
+        /// A description of the validation error.
+        message: String,
+        /// The offset in the wasm binary where the error occurred.
+        offset: usize,
+    },
+
     /// A compilation error occured.
     #[error("Compilation error: {0}")]
     Codegen(String),
@@ -97,6 +135,25 @@ pub enum CompileError {
     DebugInfoNotSupported,
 }
 
+/// Source of execution profile information used for profile-guided
+/// compilation.
+///
+/// Implementations of this trait are expected to have already symbolized their
+/// samples (from `perf.data`, a `samply` profile, or a custom format) back to
+/// WebAssembly bytecode offsets, which is the same coordinate space used for
+/// `ir::SourceLoc` in compiled functions. No readers for profiler output
+/// formats are provided here.
+pub trait ProfileData: Send + Sync {
+    /// Returns the list of hot blocks for the function `index`, identified by
+    /// the bytecode offset of any instruction within each block.
+    ///
+    /// The remaining blocks of the function are placed after the hot ones, and
+    /// the order of the offsets is not significant.
+    ///
+    /// Returns `None` if no samples were recorded for this function.
+    fn hot_blocks(&self, index: FuncIndex) -> Option<&[u32]>;
+}
+
 /// Abstract trait representing the ability to create a `Compiler` below.
 ///
 /// This is used in Wasmtime to separate compiler implementations, currently
@@ -129,6 +186,33 @@ pub trait CompilerBuilder: Send + Sync + fmt::Debug {
     /// [`CompilerBuilder::set`] and [`CompilerBuilder::enable`].
     fn settings(&self) -> Vec<Setting>;
 
+    /// Configures the optimization level of generated code.
+    ///
+    /// This is a typed alternative to setting the compiler's own optimization
+    /// setting through [`CompilerBuilder::set`]. The default implementation
+    /// sets `opt_level` to `none`, `speed` or `speed_and_size`.
+    fn optimization_level(&mut self, level: OptLevel) -> Result<()> {
+        let val = match level {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        };
+        self.set("opt_level", val)
+    }
+
+    /// Configures a directory to write the IR of every compiled function to,
+    /// or `None` to not write it anywhere.
+    ///
+    /// Files are named after the module and the `DefinedFuncIndex` of the
+    /// function, so that they can be fed back to the compiler's own tools to
+    /// reproduce an issue in isolation.
+    ///
+    /// The default implementation ignores `dir`, for compilers which have no
+    /// IR to dump.
+    fn dump_clif(&mut self, dir: Option<PathBuf>) {
+        let _ = dir;
+    }
+
     /// Builds a new [`Compiler`] object from this configuration.
     fn build(&self) -> Box<dyn Compiler>;
 }
@@ -160,6 +244,19 @@ pub enum SettingKind {
     Preset,
 }
 
+/// Optimization levels that can be configured with
+/// [`CompilerBuilder::optimization_level`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimizations performed, minimizes compilation time.
+    None,
+    /// Generates the fastest possible code, but may take longer.
+    Speed,
+    /// Similar to `Speed`, but also performs transformations aimed at
+    /// reducing code size.
+    SpeedAndSize,
+}
+
 /// An implementation of a compiler which can compile WebAssembly functions to
 /// machine code and perform other miscellaneous tasks needed by the JIT runtime.
 pub trait Compiler: Send + Sync {
@@ -177,6 +274,23 @@ pub trait Compiler: Send + Sync {
         types: &TypeTables,
     ) -> Result<CompiledFunction, CompileError>;
 
+    /// Same as [`Compiler::compile_function`], except that `profile` is used
+    /// to guide code layout of the function.
+    ///
+    /// By default the profile is ignored.
+    fn compile_function_with_profile(
+        &self,
+        translation: &ModuleTranslation<'_>,
+        index: DefinedFuncIndex,
+        data: FunctionBodyData<'_>,
+        tunables: &Tunables,
+        types: &TypeTables,
+        profile: &dyn ProfileData,
+    ) -> Result<CompiledFunction, CompileError> {
+        drop(profile);
+        self.compile_function(translation, index, data, tunables, types)
+    }
+
     /// Collects the results of compilation and emits an in-memory ELF object
     /// which is the serialized representation of all compiler artifacts.
     ///
@@ -193,6 +307,22 @@ pub trait Compiler: Send + Sync {
     /// host-to-wasm and wasm-to-host trampolines for the wasm type given.
     fn emit_trampoline_obj(&self, ty: &WasmFuncType, host_fn: usize) -> Result<Vec<u8>>;
 
+    /// Returns the direct calls to wasm functions in `compiled`.
+    ///
+    /// This is read from the function's relocations, so each offset is that
+    /// of the call's relocated operand rather than the start of the call
+    /// instruction. Indirect calls and calls to libcalls aren't included.
+    fn collect_call_sites(&self, compiled: &CompiledFunction) -> CallSiteMap {
+        compiled
+            .relocations
+            .iter()
+            .filter_map(|reloc| match reloc.reloc_target {
+                RelocationTarget::UserFunc(index) => Some((reloc.offset, index)),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Returns the target triple that this compiler is compiling for.
     fn triple(&self) -> &target_lexicon::Triple;
 
@@ -7,6 +7,87 @@ pub mod ir {
 
 pub mod entity {
     pub use cranelift_entity::{packed_option, BoxedSlice, EntityRef, EntitySet, PrimaryMap};
+
+    use std::convert::TryFrom;
+    use std::ptr;
+
+    /// Writes `items` into consecutive slots of the vmctx starting at `base`,
+    /// where the region starting at `base` has room for `len` items.
+    ///
+    /// Some vmctx regions are sized from counts that include imported entities,
+    /// so the region may be larger than the number of items written to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `items` has more than `len` items, before anything is written.
+    ///
+    /// # Safety
+    ///
+    /// The `base` pointer must be valid for writes of `len` consecutive `T`s.
+    pub unsafe fn bulk_write_vmctx<T>(
+        base: *mut T,
+        len: u32,
+        items: impl ExactSizeIterator<Item = T>,
+    ) {
+        assert!(
+            items.len() <= usize::try_from(len).unwrap(),
+            "too many items for the size of the vmctx region"
+        );
+        for (i, item) in items.enumerate() {
+            ptr::write(base.add(i), item);
+        }
+    }
+
+    // These only use plain heap allocations so that they can also be run
+    // under miri.
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_bulk_write_vmctx() {
+            let mut region = vec![0u32; 4];
+            unsafe {
+                bulk_write_vmctx(region.as_mut_ptr(), 4, vec![1, 2, 3, 4].into_iter());
+            }
+            assert_eq!(region, [1, 2, 3, 4]);
+
+            unsafe {
+                bulk_write_vmctx(region.as_mut_ptr(), 0, Vec::new().into_iter());
+            }
+            assert_eq!(region, [1, 2, 3, 4]);
+
+            unsafe {
+                bulk_write_vmctx(region.as_mut_ptr(), 4, vec![5, 6].into_iter());
+            }
+            assert_eq!(region, [5, 6, 3, 4]);
+        }
+
+        #[test]
+        fn test_bulk_write_vmctx_drop_types() {
+            // The region starts out uninitialized, so the writes mustn't drop
+            // whatever was there before.
+            let mut region = Vec::<String>::with_capacity(3);
+            unsafe {
+                bulk_write_vmctx(
+                    region.as_mut_ptr(),
+                    3,
+                    vec!["a".to_string(), "b".to_string()].into_iter(),
+                );
+                region.set_len(2);
+            }
+            assert_eq!(region, ["a", "b"]);
+        }
+
+        #[test]
+        #[should_panic(expected = "too many items for the size of the vmctx region")]
+        fn test_bulk_write_vmctx_overflow() {
+            let mut region = vec![0u32; 2];
+            unsafe {
+                bulk_write_vmctx(region.as_mut_ptr(), 2, vec![1, 2, 3].into_iter());
+            }
+        }
+    }
 }
 
 pub mod wasm {
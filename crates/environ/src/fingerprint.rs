@@ -0,0 +1,681 @@
+//! The canonical encoding of modules which their fingerprints are computed
+//! from.
+//!
+//! Everything is written field by field, with integers in their shortest
+//! LEB128 form, so the result only depends on the module's contents and not on
+//! how it was encoded or on the `Debug` output of any type.
+
+use crate::module::{Initializer, MemoryInitialization, Module, ModuleUpvar, TypeTables};
+use cranelift_entity::EntityRef;
+use cranelift_wasm::{EntityIndex, GlobalInit, WasmFuncType, WasmType};
+use rustc_hash::FxHasher;
+use std::convert::TryFrom;
+use std::hash::Hasher;
+use wasmparser::{BinaryReader, FunctionBody, MemoryImmediate, Operator, Type, TypeOrFuncType};
+
+/// A destination for canonically encoded data.
+pub(crate) trait CanonicalWrite {
+    /// Writes `bytes` as they are.
+    fn write_raw(&mut self, bytes: &[u8]);
+
+    fn write_u8(&mut self, value: u8) {
+        self.write_raw(&[value]);
+    }
+
+    fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write_u64(u64::from(value));
+    }
+
+    fn write_u64(&mut self, mut value: u64) {
+        let mut buf = [0; 10];
+        let mut len = 0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buf[len] = byte;
+                len += 1;
+                break;
+            }
+            buf[len] = byte | 0x80;
+            len += 1;
+        }
+        self.write_raw(&buf[..len]);
+    }
+
+    fn write_i64(&mut self, mut value: i64) {
+        let mut buf = [0; 10];
+        let mut len = 0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+            buf[len] = if done { byte } else { byte | 0x80 };
+            len += 1;
+            if done {
+                break;
+            }
+        }
+        self.write_raw(&buf[..len]);
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write_u64(u64::try_from(len).unwrap());
+    }
+
+    /// Writes `bytes` prefixed with their length.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_len(bytes.len());
+        self.write_raw(bytes);
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+
+    fn write_option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        match value {
+            None => self.write_u8(0),
+            Some(value) => {
+                self.write_u8(1);
+                write(self, value);
+            }
+        }
+    }
+}
+
+impl CanonicalWrite for Vec<u8> {
+    fn write_raw(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Computes a module's fingerprint and equivalence class from its function
+/// bodies, which are hashed as they're translated, and the rest of the module
+/// once it's complete.
+#[derive(Default)]
+pub(crate) struct ModuleHasher {
+    fingerprint: blake3::Hasher,
+    equivalence_class: FxHasher,
+}
+
+impl CanonicalWrite for ModuleHasher {
+    fn write_raw(&mut self, bytes: &[u8]) {
+        self.fingerprint.update(bytes);
+        self.equivalence_class.write(bytes);
+    }
+}
+
+impl ModuleHasher {
+    /// Hashes the next function body of the module.
+    ///
+    /// Locals are merged into runs by type and instructions are written as
+    /// their opcode followed by their immediates, so bodies which only differ
+    /// in how they're encoded, such as in how their locals are grouped or in
+    /// padded LEB128 immediates, hash the same. Bodies which fail to decode
+    /// are hashed by their raw bytes instead; they fail validation anyway.
+    pub fn hash_function_body(&mut self, body: &FunctionBody<'_>) {
+        let mut encoded = Vec::new();
+        if encode_function_body(&mut encoded, body).is_err() {
+            encoded.clear();
+            encoded.push(0xff);
+            let mut reader = body.get_binary_reader();
+            encoded.extend_from_slice(
+                reader
+                    .read_bytes(reader.bytes_remaining())
+                    .unwrap_or_default(),
+            );
+        }
+        self.write_u8(1);
+        self.write_bytes(&encoded);
+    }
+
+    /// Hashes the rest of `module`: its types, imports, exports, definitions
+    /// and segments, and records the results in it. Custom sections such as
+    /// the name section and the order of the exports have no influence on the
+    /// result.
+    pub fn finish(mut self, module: &mut Module, types: &TypeTables) {
+        // Marks the end of the function bodies.
+        self.write_u8(0);
+        encode_module(&mut self, module, types);
+        module.fingerprint = *self.fingerprint.finalize().as_bytes();
+        module.equivalence_class = self.equivalence_class.finish();
+    }
+}
+
+fn encode_function_body(out: &mut Vec<u8>, body: &FunctionBody<'_>) -> wasmparser::Result<()> {
+    let mut reader = body.get_binary_reader();
+    let mut locals: Vec<(u64, Type)> = Vec::new();
+    for _ in 0..reader.read_var_u32()? {
+        let count = reader.read_var_u32()?;
+        let ty = reader.read_type()?;
+        match locals.last_mut() {
+            Some((n, last)) if *last == ty => *n += u64::from(count),
+            _ if count == 0 => {}
+            _ => locals.push((u64::from(count), ty)),
+        }
+    }
+    out.write_len(locals.len());
+    for (count, ty) in locals {
+        out.write_u64(count);
+        out.write_u8(type_code(ty));
+    }
+
+    while !reader.eof() {
+        let start = reader.current_position();
+        let mut raw = reader.clone();
+        let op = reader.read_operator()?;
+        let mut raw = BinaryReader::new(raw.read_bytes(reader.current_position() - start)?);
+        let prefix = raw.read_u8()?;
+        out.write_u8(prefix as u8);
+        if let 0xfc..=0xfe = prefix {
+            out.write_u32(raw.read_var_u32()?);
+        }
+        if !encode_immediates(out, &op)? {
+            // Not an instruction we know the immediates of, so use them as
+            // they're encoded.
+            out.write_bytes(raw.read_bytes(raw.bytes_remaining())?);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the immediates of `op`, returning whether `op` is an instruction
+/// with known immediates.
+fn encode_immediates(out: &mut Vec<u8>, op: &Operator<'_>) -> wasmparser::Result<bool> {
+    match *op {
+        Operator::Block { ty }
+        | Operator::Loop { ty }
+        | Operator::If { ty }
+        | Operator::Try { ty } => match ty {
+            TypeOrFuncType::Type(ty) => {
+                out.write_u8(0);
+                out.write_u8(type_code(ty));
+            }
+            TypeOrFuncType::FuncType(index) => {
+                out.write_u8(1);
+                out.write_u32(index);
+            }
+        },
+        Operator::BrTable { ref table } => {
+            out.write_len(table.len());
+            for target in table.targets() {
+                out.write_u32(target?.0);
+            }
+        }
+        Operator::Catch { index }
+        | Operator::Throw { index }
+        | Operator::Rethrow {
+            relative_depth: index,
+        }
+        | Operator::Br {
+            relative_depth: index,
+        }
+        | Operator::BrIf {
+            relative_depth: index,
+        }
+        | Operator::Delegate {
+            relative_depth: index,
+        }
+        | Operator::Call {
+            function_index: index,
+        }
+        | Operator::ReturnCall {
+            function_index: index,
+        }
+        | Operator::RefFunc {
+            function_index: index,
+        }
+        | Operator::LocalGet { local_index: index }
+        | Operator::LocalSet { local_index: index }
+        | Operator::LocalTee { local_index: index }
+        | Operator::GlobalGet {
+            global_index: index,
+        }
+        | Operator::GlobalSet {
+            global_index: index,
+        }
+        | Operator::DataDrop { segment: index }
+        | Operator::ElemDrop { segment: index }
+        | Operator::TableFill { table: index }
+        | Operator::TableGet { table: index }
+        | Operator::TableSet { table: index }
+        | Operator::TableGrow { table: index }
+        | Operator::TableSize { table: index }
+        | Operator::MemoryFill { mem: index } => out.write_u32(index),
+        Operator::CallIndirect {
+            index: a,
+            table_index: b,
+        }
+        | Operator::ReturnCallIndirect {
+            index: a,
+            table_index: b,
+        }
+        | Operator::MemoryInit { segment: a, mem: b }
+        | Operator::MemoryCopy { src: a, dst: b }
+        | Operator::TableInit {
+            segment: a,
+            table: b,
+        }
+        | Operator::TableCopy {
+            dst_table: a,
+            src_table: b,
+        } => {
+            out.write_u32(a);
+            out.write_u32(b);
+        }
+        Operator::TypedSelect { ty } | Operator::RefNull { ty } => out.write_u8(type_code(ty)),
+        Operator::I32Load { memarg }
+        | Operator::I64Load { memarg }
+        | Operator::F32Load { memarg }
+        | Operator::F64Load { memarg }
+        | Operator::I32Load8S { memarg }
+        | Operator::I32Load8U { memarg }
+        | Operator::I32Load16S { memarg }
+        | Operator::I32Load16U { memarg }
+        | Operator::I64Load8S { memarg }
+        | Operator::I64Load8U { memarg }
+        | Operator::I64Load16S { memarg }
+        | Operator::I64Load16U { memarg }
+        | Operator::I64Load32S { memarg }
+        | Operator::I64Load32U { memarg }
+        | Operator::I32Store { memarg }
+        | Operator::I64Store { memarg }
+        | Operator::F32Store { memarg }
+        | Operator::F64Store { memarg }
+        | Operator::I32Store8 { memarg }
+        | Operator::I32Store16 { memarg }
+        | Operator::I64Store8 { memarg }
+        | Operator::I64Store16 { memarg }
+        | Operator::I64Store32 { memarg }
+        | Operator::MemoryAtomicNotify { memarg }
+        | Operator::MemoryAtomicWait32 { memarg }
+        | Operator::MemoryAtomicWait64 { memarg }
+        | Operator::I32AtomicLoad { memarg }
+        | Operator::I64AtomicLoad { memarg }
+        | Operator::I32AtomicLoad8U { memarg }
+        | Operator::I32AtomicLoad16U { memarg }
+        | Operator::I64AtomicLoad8U { memarg }
+        | Operator::I64AtomicLoad16U { memarg }
+        | Operator::I64AtomicLoad32U { memarg }
+        | Operator::I32AtomicStore { memarg }
+        | Operator::I64AtomicStore { memarg }
+        | Operator::I32AtomicStore8 { memarg }
+        | Operator::I32AtomicStore16 { memarg }
+        | Operator::I64AtomicStore8 { memarg }
+        | Operator::I64AtomicStore16 { memarg }
+        | Operator::I64AtomicStore32 { memarg }
+        | Operator::I32AtomicRmwAdd { memarg }
+        | Operator::I64AtomicRmwAdd { memarg }
+        | Operator::I32AtomicRmw8AddU { memarg }
+        | Operator::I32AtomicRmw16AddU { memarg }
+        | Operator::I64AtomicRmw8AddU { memarg }
+        | Operator::I64AtomicRmw16AddU { memarg }
+        | Operator::I64AtomicRmw32AddU { memarg }
+        | Operator::I32AtomicRmwSub { memarg }
+        | Operator::I64AtomicRmwSub { memarg }
+        | Operator::I32AtomicRmw8SubU { memarg }
+        | Operator::I32AtomicRmw16SubU { memarg }
+        | Operator::I64AtomicRmw8SubU { memarg }
+        | Operator::I64AtomicRmw16SubU { memarg }
+        | Operator::I64AtomicRmw32SubU { memarg }
+        | Operator::I32AtomicRmwAnd { memarg }
+        | Operator::I64AtomicRmwAnd { memarg }
+        | Operator::I32AtomicRmw8AndU { memarg }
+        | Operator::I32AtomicRmw16AndU { memarg }
+        | Operator::I64AtomicRmw8AndU { memarg }
+        | Operator::I64AtomicRmw16AndU { memarg }
+        | Operator::I64AtomicRmw32AndU { memarg }
+        | Operator::I32AtomicRmwOr { memarg }
+        | Operator::I64AtomicRmwOr { memarg }
+        | Operator::I32AtomicRmw8OrU { memarg }
+        | Operator::I32AtomicRmw16OrU { memarg }
+        | Operator::I64AtomicRmw8OrU { memarg }
+        | Operator::I64AtomicRmw16OrU { memarg }
+        | Operator::I64AtomicRmw32OrU { memarg }
+        | Operator::I32AtomicRmwXor { memarg }
+        | Operator::I64AtomicRmwXor { memarg }
+        | Operator::I32AtomicRmw8XorU { memarg }
+        | Operator::I32AtomicRmw16XorU { memarg }
+        | Operator::I64AtomicRmw8XorU { memarg }
+        | Operator::I64AtomicRmw16XorU { memarg }
+        | Operator::I64AtomicRmw32XorU { memarg }
+        | Operator::I32AtomicRmwXchg { memarg }
+        | Operator::I64AtomicRmwXchg { memarg }
+        | Operator::I32AtomicRmw8XchgU { memarg }
+        | Operator::I32AtomicRmw16XchgU { memarg }
+        | Operator::I64AtomicRmw8XchgU { memarg }
+        | Operator::I64AtomicRmw16XchgU { memarg }
+        | Operator::I64AtomicRmw32XchgU { memarg }
+        | Operator::I32AtomicRmwCmpxchg { memarg }
+        | Operator::I64AtomicRmwCmpxchg { memarg }
+        | Operator::I32AtomicRmw8CmpxchgU { memarg }
+        | Operator::I32AtomicRmw16CmpxchgU { memarg }
+        | Operator::I64AtomicRmw8CmpxchgU { memarg }
+        | Operator::I64AtomicRmw16CmpxchgU { memarg }
+        | Operator::I64AtomicRmw32CmpxchgU { memarg }
+        | Operator::V128Load { memarg }
+        | Operator::V128Load8x8S { memarg }
+        | Operator::V128Load8x8U { memarg }
+        | Operator::V128Load16x4S { memarg }
+        | Operator::V128Load16x4U { memarg }
+        | Operator::V128Load32x2S { memarg }
+        | Operator::V128Load32x2U { memarg }
+        | Operator::V128Load8Splat { memarg }
+        | Operator::V128Load16Splat { memarg }
+        | Operator::V128Load32Splat { memarg }
+        | Operator::V128Load64Splat { memarg }
+        | Operator::V128Load32Zero { memarg }
+        | Operator::V128Load64Zero { memarg }
+        | Operator::V128Store { memarg } => encode_memarg(out, memarg),
+        Operator::V128Load8Lane { memarg, lane }
+        | Operator::V128Load16Lane { memarg, lane }
+        | Operator::V128Load32Lane { memarg, lane }
+        | Operator::V128Load64Lane { memarg, lane }
+        | Operator::V128Store8Lane { memarg, lane }
+        | Operator::V128Store16Lane { memarg, lane }
+        | Operator::V128Store32Lane { memarg, lane }
+        | Operator::V128Store64Lane { memarg, lane } => {
+            encode_memarg(out, memarg);
+            out.write_u8(lane);
+        }
+        Operator::I8x16ExtractLaneS { lane }
+        | Operator::I8x16ExtractLaneU { lane }
+        | Operator::I8x16ReplaceLane { lane }
+        | Operator::I16x8ExtractLaneS { lane }
+        | Operator::I16x8ExtractLaneU { lane }
+        | Operator::I16x8ReplaceLane { lane }
+        | Operator::I32x4ExtractLane { lane }
+        | Operator::I32x4ReplaceLane { lane }
+        | Operator::I64x2ExtractLane { lane }
+        | Operator::I64x2ReplaceLane { lane }
+        | Operator::F32x4ExtractLane { lane }
+        | Operator::F32x4ReplaceLane { lane }
+        | Operator::F64x2ExtractLane { lane }
+        | Operator::F64x2ReplaceLane { lane } => out.write_u8(lane),
+        Operator::MemorySize { mem, mem_byte } | Operator::MemoryGrow { mem, mem_byte } => {
+            out.write_u32(mem);
+            out.write_u8(mem_byte);
+        }
+        Operator::AtomicFence { flags } => out.write_u8(flags),
+        Operator::I32Const { value } => out.write_i64(i64::from(value)),
+        Operator::I64Const { value } => out.write_i64(value),
+        Operator::F32Const { value } => out.write_raw(&value.bits().to_le_bytes()),
+        Operator::F64Const { value } => out.write_raw(&value.bits().to_le_bytes()),
+        Operator::V128Const { value } => out.write_raw(value.bytes()),
+        Operator::I8x16Shuffle { lanes } => out.write_raw(&lanes),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn encode_memarg(out: &mut Vec<u8>, memarg: MemoryImmediate) {
+    out.write_u8(memarg.align);
+    out.write_u64(memarg.offset);
+    out.write_u32(memarg.memory);
+}
+
+/// Returns the binary encoding of `ty`.
+fn type_code(ty: Type) -> u8 {
+    match ty {
+        Type::I32 => 0x7f,
+        Type::I64 => 0x7e,
+        Type::F32 => 0x7d,
+        Type::F64 => 0x7c,
+        Type::V128 => 0x7b,
+        Type::FuncRef => 0x70,
+        Type::ExternRef => 0x6f,
+        Type::ExnRef => 0x68,
+        Type::Func => 0x60,
+        Type::EmptyBlockType => 0x40,
+    }
+}
+
+fn wasm_type_code(ty: WasmType) -> u8 {
+    type_code(match ty {
+        WasmType::I32 => Type::I32,
+        WasmType::I64 => Type::I64,
+        WasmType::F32 => Type::F32,
+        WasmType::F64 => Type::F64,
+        WasmType::V128 => Type::V128,
+        WasmType::FuncRef => Type::FuncRef,
+        WasmType::ExternRef => Type::ExternRef,
+        WasmType::ExnRef => Type::ExnRef,
+    })
+}
+
+fn encode_func_type(out: &mut impl CanonicalWrite, ty: &WasmFuncType) {
+    out.write_len(ty.params.len());
+    for param in ty.params.iter() {
+        out.write_u8(wasm_type_code(*param));
+    }
+    out.write_len(ty.returns.len());
+    for ret in ty.returns.iter() {
+        out.write_u8(wasm_type_code(*ret));
+    }
+}
+
+fn encode_entity_index(out: &mut impl CanonicalWrite, index: EntityIndex) {
+    let (kind, index) = match index {
+        EntityIndex::Function(i) => (0, i.index()),
+        EntityIndex::Table(i) => (1, i.index()),
+        EntityIndex::Memory(i) => (2, i.index()),
+        EntityIndex::Global(i) => (3, i.index()),
+        EntityIndex::Module(i) => (4, i.index()),
+        EntityIndex::Instance(i) => (5, i.index()),
+    };
+    out.write_u8(kind);
+    out.write_len(index);
+}
+
+fn encode_index(out: &mut impl CanonicalWrite, index: impl EntityRef) {
+    out.write_len(index.index());
+}
+
+fn encode_module(out: &mut impl CanonicalWrite, module: &Module, types: &TypeTables) {
+    out.write_len(module.initializers.len());
+    for init in module.initializers.iter() {
+        match init {
+            Initializer::Import { name, field, index } => {
+                out.write_u8(0);
+                out.write_str(name);
+                out.write_option(field.as_deref(), |out, field| out.write_str(field));
+                encode_entity_index(out, *index);
+            }
+            Initializer::AliasInstanceExport { instance, export } => {
+                out.write_u8(1);
+                encode_index(out, *instance);
+                out.write_str(export);
+            }
+            Initializer::Instantiate { module, args } => {
+                out.write_u8(2);
+                encode_index(out, *module);
+                out.write_len(args.len());
+                for (name, index) in args {
+                    out.write_str(name);
+                    encode_entity_index(out, *index);
+                }
+            }
+            Initializer::CreateModule {
+                artifact_index,
+                artifacts,
+                modules,
+            } => {
+                out.write_u8(3);
+                out.write_len(*artifact_index);
+                out.write_len(artifacts.len());
+                for artifact in artifacts {
+                    out.write_len(*artifact);
+                }
+                out.write_len(modules.len());
+                for upvar in modules {
+                    match upvar {
+                        ModuleUpvar::Inherit(i) => {
+                            out.write_u8(0);
+                            out.write_len(*i);
+                        }
+                        ModuleUpvar::Local(i) => {
+                            out.write_u8(1);
+                            encode_index(out, *i);
+                        }
+                    }
+                }
+            }
+            Initializer::DefineModule(i) => {
+                out.write_u8(4);
+                out.write_len(*i);
+            }
+        }
+    }
+
+    out.write_len(module.functions.len());
+    for (_, sig) in module.functions.iter() {
+        encode_func_type(out, &types.wasm_signatures[*sig]);
+    }
+    out.write_len(module.table_plans.len());
+    for (_, plan) in module.table_plans.iter() {
+        out.write_u8(wasm_type_code(plan.table.wasm_ty));
+        out.write_u32(plan.table.minimum);
+        out.write_option(plan.table.maximum, CanonicalWrite::write_u32);
+    }
+    out.write_len(module.memory_plans.len());
+    for (_, plan) in module.memory_plans.iter() {
+        out.write_u64(plan.memory.minimum);
+        out.write_option(plan.memory.maximum, CanonicalWrite::write_u64);
+        out.write_bool(plan.memory.shared);
+        out.write_bool(plan.memory.memory64);
+    }
+    out.write_len(module.globals.len());
+    for (_, global) in module.globals.iter() {
+        out.write_u8(wasm_type_code(global.wasm_ty));
+        out.write_bool(global.mutability);
+        match global.initializer {
+            GlobalInit::I32Const(value) => {
+                out.write_u8(0);
+                out.write_i64(i64::from(value));
+            }
+            GlobalInit::I64Const(value) => {
+                out.write_u8(1);
+                out.write_i64(value);
+            }
+            GlobalInit::F32Const(bits) => {
+                out.write_u8(2);
+                out.write_raw(&bits.to_le_bytes());
+            }
+            GlobalInit::F64Const(bits) => {
+                out.write_u8(3);
+                out.write_raw(&bits.to_le_bytes());
+            }
+            GlobalInit::V128Const(value) => {
+                out.write_u8(4);
+                out.write_raw(value.as_slice());
+            }
+            GlobalInit::GetGlobal(index) => {
+                out.write_u8(5);
+                encode_index(out, index);
+            }
+            GlobalInit::RefNullConst => out.write_u8(6),
+            GlobalInit::RefFunc(index) => {
+                out.write_u8(7);
+                encode_index(out, index);
+            }
+            GlobalInit::Import => out.write_u8(8),
+        }
+    }
+    out.write_len(module.tags.len());
+    for (_, sig) in module.tags.iter() {
+        encode_func_type(out, &types.wasm_signatures[*sig]);
+    }
+
+    let mut exports = module.exports.iter().collect::<Vec<_>>();
+    exports.sort_by_key(|(name, _)| *name);
+    out.write_len(exports.len());
+    for (name, index) in exports {
+        out.write_str(name);
+        encode_entity_index(out, *index);
+    }
+    out.write_option(module.start_func, encode_index);
+
+    out.write_len(module.table_initializers.len());
+    for init in module.table_initializers.iter() {
+        encode_index(out, init.table_index);
+        out.write_option(init.base, encode_index);
+        out.write_u32(init.offset);
+        encode_func_indices(out, &init.elements);
+    }
+    let mut passive_elements = module.passive_elements_map.iter().collect::<Vec<_>>();
+    passive_elements.sort();
+    out.write_len(passive_elements.len());
+    for (index, i) in passive_elements {
+        encode_index(out, *index);
+        encode_func_indices(out, &module.passive_elements[*i]);
+    }
+
+    match &module.memory_initialization {
+        MemoryInitialization::Segmented(initializers) => {
+            out.write_u8(0);
+            out.write_len(initializers.len());
+            for init in initializers {
+                encode_index(out, init.memory_index);
+                out.write_option(init.base, encode_index);
+                out.write_u64(init.offset);
+                out.write_bytes(&init.data);
+            }
+        }
+        MemoryInitialization::Paged { map, out_of_bounds } => {
+            out.write_u8(1);
+            out.write_len(map.len());
+            for (_, pages) in map.iter() {
+                out.write_len(pages.len());
+                for page in pages {
+                    out.write_option(page.as_deref(), CanonicalWrite::write_bytes);
+                }
+            }
+            out.write_bool(*out_of_bounds);
+        }
+    }
+    let mut passive_data = module.passive_data_map.iter().collect::<Vec<_>>();
+    passive_data.sort();
+    out.write_len(passive_data.len());
+    for (index, i) in passive_data {
+        encode_index(out, *index);
+        out.write_bytes(&module.passive_data[*i]);
+    }
+}
+
+fn encode_func_indices(out: &mut impl CanonicalWrite, indices: &[cranelift_wasm::FuncIndex]) {
+    out.write_len(indices.len());
+    for index in indices {
+        encode_index(out, *index);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode(code: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_function_body(&mut out, &FunctionBody::new(0, code)).unwrap();
+        out
+    }
+
+    #[test]
+    fn function_bodies_are_encoded_canonically() {
+        // (local i32 i32) i32.const 1 drop
+        let expected = [0x01, 0x02, 0x7f, 0x41, 0x01, 0x1a, 0x00, 0x0b, 0x00];
+        assert_eq!(
+            encode(&[0x01, 0x02, 0x7f, 0x41, 0x01, 0x1a, 0x0b]),
+            expected
+        );
+        // The same body with split locals and a padded immediate.
+        assert_eq!(
+            encode(&[0x02, 0x01, 0x7f, 0x01, 0x7f, 0x41, 0x81, 0x00, 0x1a, 0x0b]),
+            expected
+        );
+    }
+}
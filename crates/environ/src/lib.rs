@@ -27,6 +27,7 @@ mod address_map;
 mod builtin;
 mod compilation;
 mod data_structures;
+mod fingerprint;
 mod module;
 mod module_environ;
 pub mod obj;
@@ -39,7 +40,7 @@ pub use crate::compilation::*;
 pub use crate::data_structures::*;
 pub use crate::module::*;
 pub use crate::module_environ::*;
-pub use crate::tunables::Tunables;
+pub use crate::tunables::{GcType, Tunables};
 pub use crate::vmoffsets::*;
 
 /// WebAssembly page sizes are defined to be 64KiB.
@@ -7,6 +7,7 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::mem;
 use std::sync::Arc;
 
 /// Implemenation styles for WebAssembly linear memory.
@@ -240,6 +241,26 @@ impl MemoryInitialization {
             }
         }
     }
+
+    /// Returns an estimate of the number of bytes written to linear memories
+    /// when initializing an instance.
+    ///
+    /// For segmented initialization this is the total size of the data
+    /// segments, while for paged initialization it is the total size of the
+    /// non-zero pages, which are copied in full.
+    pub fn estimate_copy_cost(&self) -> u64 {
+        match self {
+            Self::Segmented(initializers) => initializers
+                .iter()
+                .map(|initializer| initializer.data.len() as u64)
+                .sum(),
+            Self::Paged { map, .. } => map
+                .values()
+                .flat_map(|pages| pages.iter().flatten())
+                .map(|page| page.len() as u64)
+                .sum(),
+        }
+    }
 }
 
 impl Default for MemoryInitialization {
@@ -372,6 +393,20 @@ pub struct Module {
     /// Types of functions, imported and local.
     pub functions: PrimaryMap<FuncIndex, SignatureIndex>,
 
+    /// Size, in bytes, of the wasm body of each defined function.
+    pub function_body_sizes: PrimaryMap<DefinedFuncIndex, u32>,
+
+    /// Number of times each opcode occurs in the body of each defined
+    /// function, sorted by opcode.
+    ///
+    /// Opcodes are keyed by their first byte and, for the `0xfc`, `0xfd` and
+    /// `0xfe` prefixes, the sub-opcode which follows it.
+    pub opcode_counts: PrimaryMap<DefinedFuncIndex, Box<[((u8, Option<u32>), u32)]>>,
+
+    /// The functions called with `call` from the body of each defined
+    /// function, sorted and without duplicates.
+    pub direct_calls: PrimaryMap<DefinedFuncIndex, Box<[FuncIndex]>>,
+
     /// WebAssembly tables.
     pub table_plans: PrimaryMap<TableIndex, TablePlan>,
 
@@ -381,6 +416,9 @@ pub struct Module {
     /// WebAssembly global variables.
     pub globals: PrimaryMap<GlobalIndex, Global>,
 
+    /// Types of exception tags.
+    pub tags: PrimaryMap<TagIndex, SignatureIndex>,
+
     /// The type of each wasm instance this module defines.
     pub instances: PrimaryMap<InstanceIndex, InstanceTypeIndex>,
 
@@ -390,6 +428,23 @@ pub struct Module {
     /// The set of defined functions within this module which are located in
     /// element segments.
     pub possibly_exported_funcs: HashSet<DefinedFuncIndex>,
+
+    /// A hash of the canonical encoding of this module's contents.
+    pub fingerprint: [u8; 32],
+
+    /// An `FxHasher` hash of the same encoding as `fingerprint`.
+    pub equivalence_class: u64,
+}
+
+/// The names recorded from a module's name section, as removed by
+/// `Module::strip_names_section`.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct NamesSection {
+    /// The name of the module.
+    pub module_name: Option<String>,
+
+    /// The names of the module's functions.
+    pub func_names: HashMap<FuncIndex, String>,
 }
 
 /// Initialization routines for creating an instance, encompassing imports,
@@ -486,6 +541,71 @@ impl Module {
         }
     }
 
+    /// Removes the names recorded from this module's name section, returning
+    /// them so that they can later be put back with `restore_names_section`.
+    pub fn strip_names_section(&mut self) -> NamesSection {
+        NamesSection {
+            module_name: self.name.take(),
+            func_names: mem::take(&mut self.func_names),
+        }
+    }
+
+    /// Restores names previously removed with `strip_names_section`.
+    pub fn restore_names_section(&mut self, names: NamesSection) {
+        if names.module_name.is_some() {
+            self.name = names.module_name;
+        }
+        self.func_names.extend(names.func_names);
+    }
+
+    /// Returns the total size, in bytes, of the wasm bodies of all functions
+    /// defined by this module.
+    pub fn total_function_body_bytes(&self) -> u64 {
+        self.function_body_sizes
+            .values()
+            .map(|size| u64::from(*size))
+            .sum()
+    }
+
+    /// Estimates the maximum size, in bytes, of the wasm call stack for this
+    /// module, given the size of the stack frame of each defined function.
+    ///
+    /// This is the largest sum of `frame_sizes` along a chain of direct calls
+    /// between functions defined in this module. Indirect calls and calls to
+    /// imports aren't followed, so this is a heuristic rather than a
+    /// guarantee. If the direct call graph contains a cycle the depth is
+    /// unbounded and `u32::max_value()` is returned.
+    pub fn max_stack_depth_estimate(&self, frame_sizes: &PrimaryMap<DefinedFuncIndex, u32>) -> u32 {
+        crate::module_environ::max_stack_depth(self, &self.direct_calls, frame_sizes)
+    }
+
+    /// Counts the instructions in all function bodies defined in this module,
+    /// keyed by the first byte of their opcode.
+    ///
+    /// Prefixed opcodes, such as SIMD or bulk memory instructions, are all
+    /// counted under their prefix byte; use
+    /// [`Module::count_prefixed_instructions_by_opcode`] to tell them apart.
+    pub fn count_instructions_by_opcode(&self) -> HashMap<u8, u64> {
+        let mut counts = HashMap::new();
+        for ((byte, _), count) in self.opcode_counts.values().flat_map(|c| c.iter()) {
+            *counts.entry(*byte).or_insert(0) += u64::from(*count);
+        }
+        counts
+    }
+
+    /// Counts the multi-byte instructions in all function bodies defined in
+    /// this module, keyed by their prefix byte (`0xfc`, `0xfd` or `0xfe`) and
+    /// sub-opcode.
+    pub fn count_prefixed_instructions_by_opcode(&self) -> HashMap<(u8, u32), u64> {
+        let mut counts = HashMap::new();
+        for ((byte, sub), count) in self.opcode_counts.values().flat_map(|c| c.iter()) {
+            if let Some(sub) = sub {
+                *counts.entry((*byte, *sub)).or_insert(0) += u64::from(*count);
+            }
+        }
+        counts
+    }
+
     /// Test whether the given function index is for an imported function.
     #[inline]
     pub fn is_imported_function(&self, index: FuncIndex) -> bool {
@@ -664,3 +784,62 @@ mod passive_data_serde {
         de.deserialize_seq(PassiveDataVisitor)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_estimate_copy_cost() {
+        let mut module = Module::new();
+        module.memory_plans.push(MemoryPlan::for_memory(
+            Memory {
+                minimum: 4,
+                maximum: None,
+                shared: false,
+                memory64: false,
+            },
+            &Tunables::default(),
+        ));
+
+        let initializer = |offset: u64, len: usize| MemoryInitializer {
+            memory_index: MemoryIndex::new(0),
+            base: None,
+            offset,
+            data: vec![1; len].into_boxed_slice(),
+        };
+        let page_size = u64::from(crate::WASM_PAGE_SIZE);
+        let init = MemoryInitialization::Segmented(vec![
+            initializer(0, 10),
+            initializer(page_size * 3 - 5, 10),
+        ]);
+        assert_eq!(init.estimate_copy_cost(), 20);
+
+        // The second segment straddles two pages, and the page in between
+        // isn't written to at all.
+        let paged = init.to_paged(&module).unwrap();
+        assert_eq!(paged.estimate_copy_cost(), page_size * 3);
+
+        assert_eq!(MemoryInitialization::default().estimate_copy_cost(), 0);
+    }
+
+    #[test]
+    fn test_strip_and_restore_names_section() {
+        let mut module = Module::new();
+        module.name = Some("m".to_string());
+        module
+            .func_names
+            .insert(FuncIndex::from_u32(1), "f".to_string());
+
+        let names = module.strip_names_section();
+        assert_eq!(module.name, None);
+        assert!(module.func_names.is_empty());
+        assert_eq!(names.module_name.as_deref(), Some("m"));
+        assert_eq!(names.func_names[&FuncIndex::from_u32(1)], "f");
+
+        module.restore_names_section(names);
+        assert_eq!(module.name.as_deref(), Some("m"));
+        assert_eq!(module.func_names.len(), 1);
+        assert_eq!(module.func_names[&FuncIndex::from_u32(1)], "f");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::fingerprint::ModuleHasher;
 use crate::module::{
     Initializer, InstanceSignature, MemoryInitialization, MemoryInitializer, MemoryPlan, Module,
     ModuleSignature, ModuleType, ModuleUpvar, TableInitializer, TablePlan, TypeTables,
@@ -5,22 +6,22 @@ use crate::module::{
 use crate::tunables::Tunables;
 use cranelift_codegen::ir::immediates::V128Imm;
 use cranelift_codegen::packed_option::ReservedValue;
-use cranelift_entity::PrimaryMap;
+use cranelift_entity::{EntityRef, EntitySet, PrimaryMap};
 use cranelift_wasm::{
     self, DataIndex, DefinedFuncIndex, ElemIndex, EntityIndex, EntityType, FuncIndex, Global,
     GlobalIndex, GlobalInit, InstanceIndex, InstanceTypeIndex, MemoryIndex, ModuleIndex,
     ModuleTypeIndex, SignatureIndex, TableIndex, TypeIndex, WasmError, WasmFuncType, WasmResult,
 };
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
 use std::convert::{TryFrom, TryInto};
 use std::mem;
 use std::path::PathBuf;
 use std::sync::Arc;
 use wasmparser::Type as WasmType;
 use wasmparser::{
-    Alias, DataKind, ElementItem, ElementKind, ExternalKind, FuncValidator, FunctionBody,
-    ImportSectionEntryType, NameSectionReader, Naming, Operator, Parser, Payload, TypeDef,
-    Validator, ValidatorResources, WasmFeatures,
+    Alias, BinaryReader, DataKind, ElementItem, ElementKind, ExternalKind, FuncValidator,
+    FunctionBody, ImportSectionEntryType, NameSectionReader, Naming, Operator, Parser, Payload,
+    TypeDef, Validator, ValidatorResources, WasmFeatures,
 };
 
 /// Object containing the standalone environment information.
@@ -82,6 +83,87 @@ pub struct ModuleTranslation<'data> {
 
     /// Same as `creation_artifacts`, but for modules instead of artifacts.
     creation_modules: Vec<ModuleUpvar>,
+
+    /// Hasher for the module's fingerprint, which has seen every function
+    /// body so far.
+    hasher: ModuleHasher,
+}
+
+/// The body used in place of trimmed functions: no locals, `unreachable`,
+/// `end`. This is valid for any function signature.
+const TRAP_FUNCTION_BODY: &[u8] = &[0x00, 0x00, 0x0b];
+
+impl<'data> ModuleTranslation<'data> {
+    /// Computes the set of functions that can be executed when only `roots`
+    /// are called from outside the module.
+    ///
+    /// Besides `roots` this always includes the start function and every
+    /// function whose reference the module can take itself: the elements of
+    /// element segments, `ref.func` global initializers and the targets of
+    /// `ref.func` instructions in any function body. Those can be reached
+    /// through `call_indirect` or `call_ref` whether or not they're also
+    /// exported. From there all direct calls are followed transitively.
+    /// Imported functions are included in the result when they're reachable
+    /// but aren't traversed.
+    pub fn reachable_functions(&self, roots: &[FuncIndex]) -> EntitySet<FuncIndex> {
+        let module = &self.module;
+        let mut worklist = roots.to_vec();
+        worklist.extend(module.start_func);
+        worklist.extend(
+            module
+                .table_initializers
+                .iter()
+                .flat_map(|init| init.elements.iter().copied()),
+        );
+        worklist.extend(
+            module
+                .passive_elements
+                .iter()
+                .flat_map(|e| e.iter().copied()),
+        );
+        worklist.extend(module.globals.values().filter_map(|g| match g.initializer {
+            GlobalInit::RefFunc(func) => Some(func),
+            _ => None,
+        }));
+        for input in self.function_body_inputs.values() {
+            worklist.extend(ref_func_targets(&input.body));
+        }
+
+        let mut reachable = EntitySet::with_capacity(module.functions.len());
+        while let Some(func) = worklist.pop() {
+            // Null entries in element segments use the reserved value.
+            if func == FuncIndex::reserved_value() || reachable.contains(func) {
+                continue;
+            }
+            reachable.insert(func);
+            if let Some(defined) = module.defined_func_index(func) {
+                worklist.extend(module.direct_calls[defined].iter().copied());
+            }
+        }
+        reachable
+    }
+
+    /// Removes the bodies of all functions which can't be reached from
+    /// `roots`, as computed by `reachable_functions`.
+    ///
+    /// The signatures and indices of trimmed functions are left in place so
+    /// the module can still be instantiated and linked as before, but their
+    /// bodies are replaced with a single `unreachable` which is trivial to
+    /// compile and traps if it's ever called.
+    pub fn trim_unreachable_functions(mut self, roots: &[FuncIndex]) -> Self {
+        let reachable = self.reachable_functions(roots);
+        for (index, input) in self.function_body_inputs.iter_mut() {
+            let func = self.module.func_index(index);
+            if reachable.contains(func) {
+                continue;
+            }
+            input.body = FunctionBody::new(input.body.range().start, TRAP_FUNCTION_BODY);
+            self.module.function_body_sizes[index] = TRAP_FUNCTION_BODY.len() as u32;
+            self.module.opcode_counts[index] = opcode_counts(&input.body).into_boxed_slice();
+            self.module.direct_calls[index] = Box::new([]);
+        }
+        self
+    }
 }
 
 /// Contains function data: byte code and its offset in the module.
@@ -207,6 +289,7 @@ impl<'data> ModuleEnvironment<'data> {
 
                 self.result.creation_artifacts.shrink_to_fit();
                 self.result.creation_modules.shrink_to_fit();
+                mem::take(&mut self.result.hasher).finish(&mut self.result.module, &self.types);
 
                 let (record_initializer, mut done) = match self.in_progress.pop() {
                     Some(m) => (true, mem::replace(&mut self.result, m)),
@@ -376,9 +459,14 @@ impl<'data> ModuleEnvironment<'data> {
             Payload::TagSection(tags) => {
                 validator.tag_section(&tags)?;
 
-                // This feature isn't enabled at this time, so we should
-                // never get here.
-                unreachable!();
+                let cnt = usize::try_from(tags.get_count()).unwrap();
+                self.result.module.tags.reserve_exact(cnt);
+
+                for entry in tags {
+                    let ty = TypeIndex::from_u32(entry?.type_index);
+                    let sig_index = self.result.module.types[ty].unwrap_function();
+                    self.result.module.tags.push(sig_index);
+                }
             }
 
             Payload::GlobalSection(globals) => {
@@ -537,6 +625,7 @@ impl<'data> ModuleEnvironment<'data> {
                 validator.code_section_start(count, &range)?;
                 let cnt = usize::try_from(count).unwrap();
                 self.result.function_body_inputs.reserve_exact(cnt);
+                self.result.module.function_body_sizes.reserve_exact(cnt);
                 self.result.debuginfo.wasm_file.code_section_offset = range.start as u64;
             }
 
@@ -562,7 +651,21 @@ impl<'data> ModuleEnvironment<'data> {
                             params: sig.params.iter().cloned().map(|i| i.into()).collect(),
                         });
                 }
+                let range = body.range();
+                self.result
+                    .module
+                    .function_body_sizes
+                    .push(u32::try_from(range.end - range.start).unwrap());
                 body.allow_memarg64(self.features.memory64);
+                self.result.hasher.hash_function_body(&body);
+                self.result
+                    .module
+                    .opcode_counts
+                    .push(opcode_counts(&body).into_boxed_slice());
+                self.result
+                    .module
+                    .direct_calls
+                    .push(direct_calls(&body).into_boxed_slice());
                 self.result
                     .function_body_inputs
                     .push(FunctionBodyData { validator, body });
@@ -590,7 +693,10 @@ impl<'data> ModuleEnvironment<'data> {
                             let memory_index = MemoryIndex::from_u32(memory_index);
                             let mut init_expr_reader = init_expr.get_binary_reader();
                             let (base, offset) = match init_expr_reader.read_operator()? {
-                                Operator::I32Const { value } => (None, value as u64),
+                                // Offsets into 32-bit memories are unsigned, so the
+                                // constant is zero-extended rather than
+                                // sign-extended.
+                                Operator::I32Const { value } => (None, u64::from(value as u32)),
                                 Operator::I64Const { value } => (None, value as u64),
                                 Operator::GlobalGet { global_index } => {
                                     (Some(GlobalIndex::from_u32(global_index)), 0)
@@ -1248,3 +1354,374 @@ and for re-adding support for interface types you can see this issue:
         Ok(())
     }
 }
+
+/// Returns the functions called directly from `body`, without duplicates.
+///
+/// Any errors reading the body are ignored here, since the body is still
+/// validated when it's compiled.
+fn direct_calls(body: &FunctionBody<'_>) -> Vec<FuncIndex> {
+    let mut calls = Vec::new();
+    if let Ok(mut reader) = body.get_operators_reader() {
+        while !reader.eof() {
+            match reader.read() {
+                Ok(Operator::Call { function_index }) => {
+                    calls.push(FuncIndex::from_u32(function_index))
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+    calls.sort();
+    calls.dedup();
+    calls
+}
+
+/// Returns the largest sum of `frame_sizes` along a chain of `calls` between
+/// functions defined in `module`, or `u32::max_value()` if they contain a
+/// cycle.
+pub(crate) fn max_stack_depth(
+    module: &Module,
+    calls: &PrimaryMap<DefinedFuncIndex, Box<[FuncIndex]>>,
+    frame_sizes: &PrimaryMap<DefinedFuncIndex, u32>,
+) -> u32 {
+    #[derive(Clone, Copy)]
+    enum State {
+        Unvisited,
+        Visiting,
+        Done(u32),
+    }
+
+    let mut states = vec![State::Unvisited; calls.len()];
+    let mut max_depth = 0;
+
+    for root in calls.keys() {
+        // Iterative DFS over `(function, index of the next callee)` pairs
+        // so that deep call graphs don't overflow the host stack.
+        let mut stack = vec![(root, 0)];
+        while let Some((func, next)) = stack.pop() {
+            if next == 0 {
+                match states[func.index()] {
+                    State::Done(_) => continue,
+                    State::Visiting => return u32::max_value(),
+                    State::Unvisited => states[func.index()] = State::Visiting,
+                }
+            }
+
+            let callees = &calls[func];
+            let next_callee = callees[next..]
+                .iter()
+                .enumerate()
+                .find_map(|(i, callee)| Some((next + i, module.defined_func_index(*callee)?)));
+            match next_callee {
+                Some((pos, callee)) => {
+                    stack.push((func, pos + 1));
+                    match states[callee.index()] {
+                        State::Done(_) => {}
+                        State::Visiting => return u32::max_value(),
+                        State::Unvisited => stack.push((callee, 0)),
+                    }
+                }
+                None => {
+                    let depth = callees
+                        .iter()
+                        .filter_map(|callee| module.defined_func_index(*callee))
+                        .map(|callee| match states[callee.index()] {
+                            State::Done(depth) => depth,
+                            _ => unreachable!(),
+                        })
+                        .max()
+                        .unwrap_or(0)
+                        .saturating_add(frame_sizes[func]);
+                    states[func.index()] = State::Done(depth);
+                    max_depth = max_depth.max(depth);
+                }
+            }
+        }
+    }
+
+    max_depth
+}
+
+/// Returns the functions whose reference is taken with `ref.func` in `body`.
+///
+/// As with `direct_calls`, any errors reading the body are ignored and only
+/// the instructions before the error are inspected.
+fn ref_func_targets(body: &FunctionBody<'_>) -> Vec<FuncIndex> {
+    let mut targets = Vec::new();
+    if let Ok(mut reader) = body.get_operators_reader() {
+        while !reader.eof() {
+            match reader.read() {
+                Ok(Operator::RefFunc { function_index }) => {
+                    targets.push(FuncIndex::from_u32(function_index))
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+    targets
+}
+
+/// Returns how many times each opcode occurs in `body`, sorted by opcode.
+///
+/// As with `direct_calls`, any errors reading the body are ignored and only
+/// the instructions before the error are counted.
+fn opcode_counts(body: &FunctionBody<'_>) -> Vec<((u8, Option<u32>), u32)> {
+    let mut counts = BTreeMap::new();
+    let mut reader = body.get_binary_reader();
+    let skip_locals = |reader: &mut BinaryReader<'_>| -> wasmparser::Result<()> {
+        for _ in 0..reader.read_var_u32()? {
+            reader.read_var_u32()?;
+            reader.read_type()?;
+        }
+        Ok(())
+    };
+    if skip_locals(&mut reader).is_err() {
+        return Vec::new();
+    }
+    while !reader.eof() {
+        let mut peek = reader.clone();
+        let opcode = match peek.read_u8() {
+            Ok(byte @ 0xfc..=0xfe) => match peek.read_var_u32() {
+                Ok(sub) => (byte as u8, Some(sub)),
+                Err(_) => break,
+            },
+            Ok(byte) => (byte as u8, None),
+            Err(_) => break,
+        };
+        if reader.read_operator().is_err() {
+            break;
+        }
+        *counts.entry(opcode).or_insert(0) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Computes the maximum stack depth of a module with a frame of size 1
+    /// for each function, that is, the longest chain of calls.
+    fn max_depth(num_imported_funcs: usize, calls: &[&[u32]]) -> u32 {
+        let mut module = Module::new();
+        module.num_imported_funcs = num_imported_funcs;
+        for callees in calls {
+            module
+                .direct_calls
+                .push(callees.iter().map(|i| FuncIndex::from_u32(*i)).collect());
+        }
+        let frame_sizes = calls.iter().map(|_| 1).collect();
+        module.max_stack_depth_estimate(&frame_sizes)
+    }
+
+    fn reachable(wat: &str, roots: &[u32]) -> Vec<u32> {
+        let wasm = wat::parse_str(wat).unwrap();
+        let (_, translations, _) =
+            ModuleEnvironment::new(&Tunables::default(), &WasmFeatures::default())
+                .translate(&wasm)
+                .unwrap();
+        let roots = roots
+            .iter()
+            .map(|i| FuncIndex::from_u32(*i))
+            .collect::<Vec<_>>();
+        let set = translations[0].reachable_functions(&roots);
+        (0..translations[0].module.functions.len() as u32)
+            .filter(|i| set.contains(FuncIndex::from_u32(*i)))
+            .collect()
+    }
+
+    #[test]
+    fn test_reachable_functions() {
+        // import 0, defined 1 -> 2 -> import 0, defined 3 -> 3, defined 4
+        let calls = r#"
+            (import "" "" (func))
+            (func call 2)
+            (func call 0)
+            (func call 3)
+            (func)
+        "#;
+        let module = format!("(module {})", calls);
+        assert_eq!(reachable(&module, &[]), []);
+        assert_eq!(reachable(&module, &[1]), [0, 1, 2]);
+        assert_eq!(reachable(&module, &[3]), [3]);
+
+        // Functions placed in tables may be called indirectly.
+        let elems = "(table 2 funcref) (elem (i32.const 0) funcref (ref.null func) (ref.func 4))";
+        let module = format!("(module {} {})", calls, elems);
+        assert_eq!(reachable(&module, &[3]), [3, 4]);
+
+        let module = format!("(module {} {} (start 2))", calls, elems);
+        assert_eq!(reachable(&module, &[]), [0, 2, 4]);
+    }
+
+    #[test]
+    fn test_reachable_functions_ref_func() {
+        // Functions referenced with `ref.func` are reachable whether they're
+        // exported (1) or only declared (2).
+        let module = r#"
+            (module
+                (func (export "a") ref.func 1 drop ref.func 2 drop)
+                (func (export "b"))
+                (func)
+                (func (export "c"))
+                (elem declare func 2))
+        "#;
+        assert_eq!(reachable(module, &[0]), [0, 1, 2]);
+        assert_eq!(reachable(module, &[]), [1, 2]);
+    }
+
+    #[test]
+    fn test_trim_unreachable_functions() {
+        // (module
+        //   (func (export "a") (result i32) call 1)
+        //   (func (result i32) i32.const 1)
+        //   (func (result i32) i32.const 2))
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x04, 0x03, 0x00, 0x00, 0x00, // function section
+            0x07, 0x05, 0x01, 0x01, 0x61, 0x00, 0x00, // export section
+            0x0a, 0x10, 0x03, // code section
+            0x04, 0x00, 0x10, 0x01, 0x0b, // body 0
+            0x04, 0x00, 0x41, 0x01, 0x0b, // body 1
+            0x04, 0x00, 0x41, 0x02, 0x0b, // body 2
+        ];
+        let (_, mut translations, _) =
+            ModuleEnvironment::new(&Tunables::default(), &WasmFeatures::default())
+                .translate(&wasm)
+                .unwrap();
+        let translation = translations
+            .remove(0)
+            .trim_unreachable_functions(&[FuncIndex::from_u32(0)]);
+
+        let sizes = translation.module.function_body_sizes.values();
+        assert_eq!(sizes.copied().collect::<Vec<_>>(), [4, 4, 3]);
+        let mut ops = translation.function_body_inputs[DefinedFuncIndex::from_u32(2)]
+            .body
+            .get_operators_reader()
+            .unwrap();
+        assert!(matches!(ops.read().unwrap(), Operator::Unreachable));
+
+        // The opcode counts describe the stub rather than the original body.
+        let stub_counts = opcode_counts(&FunctionBody::new(0, TRAP_FUNCTION_BODY));
+        assert_eq!(stub_counts, [((0x00, None), 1), ((0x0b, None), 1)]);
+        assert_eq!(
+            &*translation.module.opcode_counts[DefinedFuncIndex::from_u32(2)],
+            &stub_counts[..]
+        );
+
+        // The stub body still validates against the original signature.
+        for (_, mut input) in translation.function_body_inputs {
+            input.validator.validate(&input.body).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_data_segment_offset_is_unsigned() {
+        // (module
+        //   (memory 1)
+        //   (data (i32.const -1) "a"))
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory section
+            0x0b, 0x07, 0x01, 0x00, 0x41, 0x7f, 0x0b, 0x01, 0x61, // data section
+        ];
+        let (_, translations, _) =
+            ModuleEnvironment::new(&Tunables::default(), &WasmFeatures::default())
+                .translate(&wasm)
+                .unwrap();
+        match &translations[0].module.memory_initialization {
+            MemoryInitialization::Segmented(initializers) => {
+                assert_eq!(initializers[0].offset, 0xffff_ffff);
+            }
+            other => panic!("unexpected memory initialization: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_count_instructions_by_opcode() {
+        // (module
+        //   (func (result i32)
+        //     f32.const 1 i32.trunc_sat_f32_s i32.const 1 i32.add))
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x0a, 0x0e, 0x01, // code section
+            0x0c, 0x00, 0x43, 0x00, 0x00, 0x80, 0x3f, 0xfc, 0x00, 0x41, 0x01, 0x6a, 0x0b,
+        ];
+        let (_, translations, _) =
+            ModuleEnvironment::new(&Tunables::default(), &WasmFeatures::default())
+                .translate(&wasm)
+                .unwrap();
+        let module = &translations[0].module;
+
+        let counts = module.count_instructions_by_opcode();
+        assert_eq!(counts.len(), 5);
+        for opcode in [0x43, 0xfc, 0x41, 0x6a, 0x0b].iter() {
+            assert_eq!(counts[opcode], 1);
+        }
+        let prefixed = module.count_prefixed_instructions_by_opcode();
+        assert_eq!(prefixed.len(), 1);
+        assert_eq!(prefixed[&(0xfc, 0x00)], 1);
+    }
+
+    #[test]
+    fn test_total_function_body_bytes() {
+        // (module
+        //   (import "" "" (func))
+        //   (func)
+        //   (func (local i32) i32.const 1 drop))
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+            0x02, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, // import section
+            0x03, 0x03, 0x02, 0x00, 0x00, // function section
+            0x0a, 0x0c, 0x02, // code section
+            0x02, 0x00, 0x0b, // 2-byte body
+            0x07, 0x01, 0x01, 0x7f, 0x41, 0x01, 0x1a, 0x0b, // 7-byte body
+        ];
+        let (_, translations, _) =
+            ModuleEnvironment::new(&Tunables::default(), &WasmFeatures::default())
+                .translate(&wasm)
+                .unwrap();
+        let module = &translations[0].module;
+
+        let sizes = module.function_body_sizes.values();
+        assert_eq!(sizes.copied().collect::<Vec<_>>(), [2, 7]);
+        assert_eq!(module.total_function_body_bytes(), 9);
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        assert_eq!(max_depth(0, &[]), 0);
+        assert_eq!(max_depth(0, &[&[]]), 1);
+
+        // 0 -> 1 -> 2 -> 3 and 0 -> 3
+        assert_eq!(max_depth(0, &[&[1, 3], &[2], &[3], &[]]), 4);
+
+        // Calls to imports aren't followed: import 0, defined 1 -> 2 -> import 0
+        assert_eq!(max_depth(1, &[&[0, 2], &[0]]), 2);
+
+        // 0 -> 1 -> 2 -> 1
+        assert_eq!(max_depth(0, &[&[1], &[2], &[1]]), u32::max_value());
+
+        // Self-recursion
+        assert_eq!(max_depth(0, &[&[], &[1]]), u32::max_value());
+    }
+
+    #[test]
+    fn test_max_stack_depth_estimate() {
+        // 0 -> 1 and 2 -> 1, where 2 has the larger frame.
+        let mut module = Module::new();
+        for callees in [&[1][..], &[], &[1]].iter() {
+            module
+                .direct_calls
+                .push(callees.iter().map(|i| FuncIndex::from_u32(*i)).collect());
+        }
+        let frame_sizes = [16, 32, 48].iter().copied().collect();
+        assert_eq!(module.max_stack_depth_estimate(&frame_sizes), 80);
+    }
+}
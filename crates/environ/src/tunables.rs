@@ -36,6 +36,34 @@ pub struct Tunables {
     /// Whether or not linear memory allocations will have a guard region at the
     /// beginning of the allocation in addition to the end.
     pub guard_before_linear_memory: bool,
+
+    /// The garbage collector that reference-typed values are managed by, which
+    /// determines the barriers emitted in generated code.
+    pub gc_type: GcType,
+
+    /// Whether or not generated code notifies the store's `ResourceLimiter`
+    /// after every `memory.grow` instruction.
+    pub monitor_memory_grow: bool,
+
+    /// Whether or not `memory.fill` and `memory.copy` skip their bounds checks
+    /// and access the memory's base plus the given offsets directly.
+    ///
+    /// This is only sound for code already known to stay within bounds.
+    pub skip_bounds_checks: bool,
+}
+
+/// The kind of garbage collector that generated code must cooperate with.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GcType {
+    /// No garbage collector is configured, so no barriers are emitted.
+    ///
+    /// This is only valid when reference types are disabled.
+    None,
+
+    /// The deferred reference counting collector used for `externref`s, which
+    /// requires references read from the heap to be held alive by the
+    /// `VMExternRefActivationsTable`.
+    DeferredReferenceCounting,
 }
 
 impl Default for Tunables {
@@ -72,6 +100,9 @@ impl Default for Tunables {
             consume_fuel: false,
             static_memory_bound_is_maximum: false,
             guard_before_linear_memory: true,
+            gc_type: GcType::DeferredReferenceCounting,
+            monitor_memory_grow: false,
+            skip_bounds_checks: false,
         }
     }
 }
@@ -28,6 +28,7 @@ use cranelift_wasm::{
 };
 use more_asserts::assert_lt;
 use std::convert::TryFrom;
+use thiserror::Error;
 
 /// Sentinel value indicating that wasm has been interrupted.
 // Note that this has a bit of an odd definition. See the `insert_stack_check`
@@ -91,6 +92,15 @@ pub struct VMOffsets<P> {
     size: u32,
 }
 
+/// An error returned by `VMOffsets::assert_valid` when a region of the
+/// `VMContext` layout overflows or runs into the region after it.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("`VMContext` layout is invalid: the {region} region overflows")]
+pub struct OverflowError {
+    /// The name of the region that failed the check.
+    pub region: &'static str,
+}
+
 /// Trait used for the `ptr` representation of the field of `VMOffsets`
 pub trait PtrSize {
     /// Returns the pointer size, in bytes, for the target.
@@ -597,6 +607,207 @@ impl<P: PtrSize> VMOffsets<P> {
         self.size
     }
 
+    /// Checks that the precalculated offsets are consistent with the entity
+    /// counts.
+    ///
+    /// Every region must fit in a `u32` without overflowing and must end at
+    /// or before the start of the region that follows it. The counts are
+    /// public fields, so this catches them being changed after the offsets
+    /// were computed.
+    pub fn assert_valid(&self) -> Result<(), OverflowError> {
+        let regions = [
+            (
+                "signature ids",
+                self.signature_ids,
+                self.num_signature_ids,
+                self.size_of_vmshared_signature_index(),
+                self.imported_functions,
+            ),
+            (
+                "imported functions",
+                self.imported_functions,
+                self.num_imported_functions,
+                self.size_of_vmfunction_import(),
+                self.imported_tables,
+            ),
+            (
+                "imported tables",
+                self.imported_tables,
+                self.num_imported_tables,
+                self.size_of_vmtable_import(),
+                self.imported_memories,
+            ),
+            (
+                "imported memories",
+                self.imported_memories,
+                self.num_imported_memories,
+                self.size_of_vmmemory_import(),
+                self.imported_globals,
+            ),
+            (
+                "imported globals",
+                self.imported_globals,
+                self.num_imported_globals,
+                self.size_of_vmglobal_import(),
+                self.defined_tables,
+            ),
+            (
+                "defined tables",
+                self.defined_tables,
+                self.num_defined_tables,
+                self.size_of_vmtable_definition(),
+                self.defined_memories,
+            ),
+            (
+                "defined memories",
+                self.defined_memories,
+                self.num_defined_memories,
+                self.size_of_vmmemory_definition(),
+                self.defined_globals,
+            ),
+            (
+                "defined globals",
+                self.defined_globals,
+                self.num_defined_globals,
+                self.size_of_vmglobal_definition(),
+                self.defined_anyfuncs,
+            ),
+            (
+                "anyfuncs",
+                self.defined_anyfuncs,
+                self.num_imported_functions
+                    .checked_add(self.num_defined_functions)
+                    .ok_or(OverflowError { region: "anyfuncs" })?,
+                self.size_of_vmcaller_checked_anyfunc(),
+                self.builtin_functions,
+            ),
+            (
+                "builtin functions",
+                self.builtin_functions,
+                BuiltinFunctionIndex::builtin_functions_total_number(),
+                self.pointer_size(),
+                self.size,
+            ),
+        ];
+
+        for (region, begin, count, elem_size, next) in regions.iter().copied() {
+            let end = count
+                .checked_mul(u32::from(elem_size))
+                .and_then(|len| begin.checked_add(len));
+            match end {
+                Some(end) if end <= next => {}
+                _ => return Err(OverflowError { region }),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns a human-readable map of the `VMContext` layout, one field per
+    /// line, for debugging.
+    ///
+    /// Each line gives the byte range of a field followed by its name, such
+    /// as `[0x0000..0x0008] interrupts`. Arrays also list their element count
+    /// and size, and any alignment padding between fields is shown too.
+    pub fn dump_layout(&self) -> String {
+        let ptr = u32::from(self.pointer_size());
+        let num_anyfuncs = self.num_imported_functions + self.num_defined_functions;
+        // Scalar fields have no element count; `store` is a fat pointer.
+        let fields = [
+            ("interrupts", self.interrupts, None, ptr),
+            (
+                "externref_activations_table",
+                self.externref_activations_table,
+                None,
+                ptr,
+            ),
+            ("store", self.store, None, 2 * ptr),
+            (
+                "signature_ids",
+                self.signature_ids,
+                Some(self.num_signature_ids),
+                u32::from(self.size_of_vmshared_signature_index()),
+            ),
+            (
+                "imported_functions",
+                self.imported_functions,
+                Some(self.num_imported_functions),
+                u32::from(self.size_of_vmfunction_import()),
+            ),
+            (
+                "imported_tables",
+                self.imported_tables,
+                Some(self.num_imported_tables),
+                u32::from(self.size_of_vmtable_import()),
+            ),
+            (
+                "imported_memories",
+                self.imported_memories,
+                Some(self.num_imported_memories),
+                u32::from(self.size_of_vmmemory_import()),
+            ),
+            (
+                "imported_globals",
+                self.imported_globals,
+                Some(self.num_imported_globals),
+                u32::from(self.size_of_vmglobal_import()),
+            ),
+            (
+                "tables",
+                self.defined_tables,
+                Some(self.num_defined_tables),
+                u32::from(self.size_of_vmtable_definition()),
+            ),
+            (
+                "memories",
+                self.defined_memories,
+                Some(self.num_defined_memories),
+                u32::from(self.size_of_vmmemory_definition()),
+            ),
+            (
+                "globals",
+                self.defined_globals,
+                Some(self.num_defined_globals),
+                u32::from(self.size_of_vmglobal_definition()),
+            ),
+            (
+                "anyfuncs",
+                self.defined_anyfuncs,
+                Some(num_anyfuncs),
+                u32::from(self.size_of_vmcaller_checked_anyfunc()),
+            ),
+            (
+                "builtins",
+                self.builtin_functions,
+                Some(BuiltinFunctionIndex::builtin_functions_total_number()),
+                ptr,
+            ),
+        ];
+
+        let mut layout = String::new();
+        let mut prev_end = 0;
+        for (name, begin, count, size) in fields.iter().copied() {
+            if begin > prev_end {
+                layout += &format!("[{:#06x}..{:#06x}] <padding>\n", prev_end, begin);
+            }
+            let end = begin + count.unwrap_or(1) * size;
+            match count {
+                Some(count) => {
+                    layout += &format!(
+                        "[{:#06x}..{:#06x}] {} ({} x {} bytes)\n",
+                        begin, end, name, count, size
+                    );
+                }
+                None => layout += &format!("[{:#06x}..{:#06x}] {}\n", begin, end, name),
+            }
+            prev_end = end;
+        }
+        if self.size > prev_end {
+            layout += &format!("[{:#06x}..{:#06x}] <padding>\n", prev_end, self.size);
+        }
+        layout
+    }
+
     /// Return the offset to `VMSharedSignatureId` index `index`.
     #[inline]
     pub fn vmctx_vmshared_signature_id(&self, index: TypeIndex) -> u32 {
@@ -779,7 +990,7 @@ impl TargetSharedSignatureIndex {
 
 #[cfg(test)]
 mod tests {
-    use crate::vmoffsets::align;
+    use crate::vmoffsets::{align, HostPtr, OverflowError, VMOffsets, VMOffsetsFields};
 
     #[test]
     fn alignment() {
@@ -791,4 +1002,67 @@ mod tests {
         assert!(is_aligned(align(33, 16)));
         assert!(is_aligned(align(31, 16)));
     }
+
+    #[test]
+    fn assert_valid() {
+        let mut offsets = VMOffsets::from(VMOffsetsFields {
+            ptr: HostPtr,
+            num_signature_ids: 1,
+            num_imported_functions: 2,
+            num_imported_tables: 1,
+            num_imported_memories: 1,
+            num_imported_globals: 1,
+            num_defined_functions: 3,
+            num_defined_tables: 1,
+            num_defined_memories: 1,
+            num_defined_globals: 2,
+        });
+        assert_eq!(offsets.assert_valid(), Ok(()));
+
+        offsets.num_defined_globals += 100;
+        assert_eq!(
+            offsets.assert_valid(),
+            Err(OverflowError {
+                region: "defined globals"
+            })
+        );
+
+        offsets.num_defined_globals = u32::max_value();
+        assert_eq!(
+            offsets.assert_valid(),
+            Err(OverflowError {
+                region: "defined globals"
+            })
+        );
+    }
+
+    #[test]
+    fn dump_layout() {
+        let offsets = VMOffsets::from(VMOffsetsFields {
+            ptr: 8u8,
+            num_signature_ids: 1,
+            num_imported_functions: 0,
+            num_imported_tables: 0,
+            num_imported_memories: 0,
+            num_imported_globals: 0,
+            num_defined_functions: 2,
+            num_defined_tables: 0,
+            num_defined_memories: 1,
+            num_defined_globals: 1,
+        });
+        let layout = offsets.dump_layout();
+        let lines = layout.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "[0x0000..0x0008] interrupts");
+        assert_eq!(lines[1], "[0x0008..0x0010] externref_activations_table");
+        assert_eq!(lines[2], "[0x0010..0x0020] store");
+        assert_eq!(lines[3], "[0x0020..0x0024] signature_ids (1 x 4 bytes)");
+        assert!(lines
+            .iter()
+            .any(|l| l.ends_with("] memories (1 x 16 bytes)")));
+        assert!(lines
+            .iter()
+            .any(|l| l.ends_with("] anyfuncs (2 x 24 bytes)")));
+        let last = lines.last().unwrap();
+        assert!(last.contains(&format!("..{:#06x}]", offsets.size_of_vmctx())));
+    }
 }
@@ -1,17 +1,26 @@
 //! JIT compilation.
 
-use crate::instantiate::SetupError;
+use crate::instantiate::{CompilationArtifacts, CompiledModule, SetupError};
 #[cfg(feature = "parallel-compilation")]
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+#[cfg(feature = "parallel-compilation")]
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::mem;
+use std::sync::Arc;
+#[cfg(feature = "parallel-compilation")]
+use std::sync::Mutex;
 use wasmparser::WasmFeatures;
+use wasmtime_environ::wasm::WasmError;
 use wasmtime_environ::{
-    CompiledFunctions, Compiler as EnvCompiler, CompilerBuilder, ModuleTranslation, Tunables,
-    TypeTables,
+    CompileError, CompiledFunctions, Compiler as EnvCompiler, CompilerBuilder, ModuleTranslation,
+    ProfileData, Tunables, TypeTables,
 };
+use wasmtime_profiling::ProfilingAgent;
+#[cfg(feature = "parallel-compilation")]
+use wasmtime_runtime::InstantiationError;
 
 /// Select which kind of compilation to use.
 #[derive(Copy, Clone, Debug, Hash, Serialize, Deserialize, Eq, PartialEq)]
@@ -33,6 +42,8 @@ pub struct Compiler {
     tunables: Tunables,
     features: WasmFeatures,
     parallel_compilation: bool,
+    #[cfg(feature = "parallel-compilation")]
+    thread_pools: Mutex<HashMap<usize, Arc<rayon::ThreadPool>>>,
 }
 
 impl Compiler {
@@ -60,6 +71,8 @@ impl Compiler {
             tunables,
             features,
             parallel_compilation,
+            #[cfg(feature = "parallel-compilation")]
+            thread_pools: Mutex::new(HashMap::new()),
         }
     }
 }
@@ -96,6 +109,35 @@ impl Compiler {
         self.compiler.triple()
     }
 
+    /// Validates the whole `wasm` binary with this compiler's features and
+    /// compiles it and all of its submodules.
+    ///
+    /// The module is validated once, as it's translated: its sections are
+    /// validated before any function is compiled, and each function body is
+    /// validated operator by operator before that operator is translated to
+    /// Cranelift IR. Any validation failure is reported as
+    /// `CompileError::InvalidWasm`.
+    ///
+    /// Returns the index of the main module in the returned list of compiled
+    /// modules, as with `CompilationArtifacts::build`.
+    pub fn validate_and_compile(
+        &self,
+        wasm: &[u8],
+        use_paged_mem_init: bool,
+        profiler: &dyn ProfilingAgent,
+    ) -> Result<(usize, Vec<Arc<CompiledModule>>, crate::TypeTables), SetupError> {
+        let (main_module, artifacts, types) =
+            CompilationArtifacts::build(self, wasm, use_paged_mem_init).map_err(|e| match e {
+                SetupError::Compile(CompileError::Wasm(WasmError::InvalidWebAssembly {
+                    message,
+                    offset,
+                })) => SetupError::Compile(CompileError::InvalidWasm { message, offset }),
+                e => e,
+            })?;
+        let modules = CompiledModule::from_artifacts_list(artifacts, profiler, self)?;
+        Ok((main_module, modules, types))
+    }
+
     /// Compile the given function bodies.
     pub fn compile<'data>(
         &self,
@@ -113,6 +155,110 @@ impl Compiler {
             .into_iter()
             .collect::<CompiledFunctions>();
 
+        self.finish_compile(translation, types, funcs)
+    }
+
+    /// Compile the given function bodies on a dedicated pool of at most
+    /// `parallelism` threads.
+    ///
+    /// Unlike `compile`, this doesn't depend on the global rayon pool or on
+    /// whether parallel compilation was enabled when this compiler was
+    /// created. The pool for each `parallelism` is created on first use and
+    /// then kept for the lifetime of this compiler. If the
+    /// `parallel-compilation` feature is disabled, or `parallelism` is 1 or
+    /// less, functions are compiled on the current thread.
+    ///
+    /// If several functions fail to compile, the error of the function with
+    /// the lowest index is returned, regardless of which thread finished
+    /// first.
+    pub fn compile_module_concurrent(
+        &self,
+        translation: &mut ModuleTranslation,
+        types: &TypeTables,
+        parallelism: usize,
+    ) -> Result<Compilation, SetupError> {
+        let functions = mem::take(&mut translation.function_body_inputs);
+        let functions = functions.into_iter().collect::<Vec<_>>();
+        let compile = |(index, func)| {
+            self.compiler
+                .compile_function(translation, index, func, &self.tunables, types)
+        };
+
+        #[cfg(feature = "parallel-compilation")]
+        let funcs = match self.thread_pool(parallelism)? {
+            Some(pool) => pool.install(|| run_in_current_pool(true, functions, compile)),
+            None => run_in_current_pool(false, functions, compile),
+        };
+        #[cfg(not(feature = "parallel-compilation"))]
+        let funcs = {
+            let _ = parallelism;
+            run_in_current_pool(false, functions, compile)
+        };
+
+        let funcs = funcs?.into_iter().collect::<CompiledFunctions>();
+        self.finish_compile(translation, types, funcs)
+    }
+
+    /// Returns the pool of `parallelism` threads used by
+    /// `compile_module_concurrent`, or `None` if a single thread was asked
+    /// for.
+    #[cfg(feature = "parallel-compilation")]
+    fn thread_pool(
+        &self,
+        parallelism: usize,
+    ) -> Result<Option<Arc<rayon::ThreadPool>>, SetupError> {
+        if parallelism <= 1 {
+            return Ok(None);
+        }
+        let mut pools = self.thread_pools.lock().unwrap();
+        if let Some(pool) = pools.get(&parallelism) {
+            return Ok(Some(pool.clone()));
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(parallelism)
+            .build()
+            .map_err(|e| {
+                SetupError::Instantiate(InstantiationError::Resource(anyhow::Error::new(e)))
+            })?;
+        let pool = Arc::new(pool);
+        pools.insert(parallelism, pool.clone());
+        Ok(Some(pool))
+    }
+
+    /// Compile the given function bodies, using `profile` to guide the
+    /// placement of hot blocks within each function.
+    pub fn profile_guided_compile(
+        &self,
+        translation: &mut ModuleTranslation,
+        types: &TypeTables,
+        profile: &dyn ProfileData,
+    ) -> Result<Compilation, SetupError> {
+        let functions = mem::take(&mut translation.function_body_inputs);
+        let functions = functions.into_iter().collect::<Vec<_>>();
+
+        let funcs = self
+            .run_maybe_parallel(functions, |(index, func)| {
+                self.compiler.compile_function_with_profile(
+                    translation,
+                    index,
+                    func,
+                    &self.tunables,
+                    types,
+                    profile,
+                )
+            })?
+            .into_iter()
+            .collect::<CompiledFunctions>();
+
+        self.finish_compile(translation, types, funcs)
+    }
+
+    fn finish_compile(
+        &self,
+        translation: &ModuleTranslation,
+        types: &TypeTables,
+        funcs: CompiledFunctions,
+    ) -> Result<Compilation, SetupError> {
         let obj = self.compiler.emit_obj(
             &translation,
             types,
@@ -134,21 +280,37 @@ impl Compiler {
         input: Vec<A>,
         f: F,
     ) -> Result<Vec<B>, E> {
-        if self.parallel_compilation {
-            #[cfg(feature = "parallel-compilation")]
-            return input
-                .into_par_iter()
-                .map(|a| f(a))
-                .collect::<Result<Vec<B>, E>>();
-        }
+        run_in_current_pool(self.parallel_compilation, input, f)
+    }
+}
 
-        // In case the parallel-compilation feature is disabled or the parallel_compilation config
-        // was turned off dynamically fallback to the non-parallel version.
-        input
-            .into_iter()
+/// Run the given closure over `input` in parallel if `parallel` is set, on
+/// the rayon pool this is called from (the global one outside of
+/// `ThreadPool::install`).
+fn run_in_current_pool<A: Send, B: Send, E: Send, F: Fn(A) -> Result<B, E> + Send + Sync>(
+    parallel: bool,
+    input: Vec<A>,
+    f: F,
+) -> Result<Vec<B>, E> {
+    if parallel {
+        // Collecting a parallel iterator straight into a `Result` returns
+        // whichever error rayon happens to see first, so collect every result
+        // and report the error of the first input that failed instead.
+        #[cfg(feature = "parallel-compilation")]
+        return input
+            .into_par_iter()
             .map(|a| f(a))
-            .collect::<Result<Vec<B>, E>>()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .collect::<Result<Vec<B>, E>>();
     }
+
+    // In case the parallel-compilation feature is disabled or the parallel_compilation config
+    // was turned off dynamically fallback to the non-parallel version.
+    input
+        .into_iter()
+        .map(|a| f(a))
+        .collect::<Result<Vec<B>, E>>()
 }
 
 impl Hash for Compiler {
@@ -158,6 +320,8 @@ impl Hash for Compiler {
             tunables,
             features,
             parallel_compilation: _,
+            #[cfg(feature = "parallel-compilation")]
+                thread_pools: _,
         } = self;
 
         compiler.triple().hash(hasher);
@@ -178,3 +342,271 @@ impl Hash for Compiler {
         env!("CARGO_PKG_VERSION").hash(hasher);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasmtime_environ::entity::EntityRef;
+    use wasmtime_environ::wasm::{DefinedFuncIndex, FuncIndex};
+    use wasmtime_environ::{ModuleEnvironment, RelocationTarget};
+    use wasmtime_profiling::NullProfilerAgent;
+
+    #[test]
+    fn validate_and_compile_rejects_invalid_wasm() {
+        let compiler = Compiler::new(
+            &*Compiler::builder(CompilationStrategy::Auto),
+            Tunables::default(),
+            WasmFeatures::default(),
+            false,
+        );
+
+        // A function declared to return an `i32` whose body is just `end`.
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+        ];
+
+        match compiler.validate_and_compile(&wasm, false, &NullProfilerAgent) {
+            Err(SetupError::Compile(CompileError::InvalidWasm { message, offset })) => {
+                assert!(message.contains("type mismatch"), "{}", message);
+                assert_eq!(offset, 24);
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("invalid module should not compile"),
+        }
+    }
+
+    struct HotOffsets(Vec<u32>);
+
+    impl ProfileData for HotOffsets {
+        fn hot_blocks(&self, _index: FuncIndex) -> Option<&[u32]> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn profile_guided_compile_places_hot_blocks_first() {
+        let compiler = Compiler::new(
+            &*Compiler::builder(CompilationStrategy::Auto),
+            Tunables::default(),
+            WasmFeatures::default(),
+            false,
+        );
+
+        // (func (param i32) (result i32)
+        //   (if (result i32) (local.get 0)
+        //     (then (i32.mul (local.get 0) (i32.const 7)))
+        //     (else (i32.add (local.get 0) (i32.const 9)))))
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x0a, 0x14, 0x01, 0x12, 0x00, // code section
+            0x20, 0x00, 0x04, 0x7f, // local.get 0, if
+            0x20, 0x00, 0x41, 0x07, 0x6c, // i32.mul at offset 33
+            0x05, // else
+            0x20, 0x00, 0x41, 0x09, 0x6a, // i32.add at offset 39
+            0x0b, 0x0b, // end, end
+        ];
+        const MUL: u32 = 33;
+        const ADD: u32 = 39;
+
+        // Returns the code offset of the first instruction emitted for each
+        // of the `mul` and `add` bytecode offsets.
+        let code_offsets = |profile: Option<&dyn ProfileData>| {
+            let (_, mut translations, types) =
+                ModuleEnvironment::new(compiler.tunables(), compiler.features())
+                    .translate(&wasm)
+                    .unwrap();
+            let translation = &mut translations[0];
+            let Compilation { funcs, .. } = match profile {
+                Some(profile) => compiler.profile_guided_compile(translation, &types, profile),
+                None => compiler.compile(translation, &types),
+            }
+            .unwrap();
+            let instructions = &funcs[DefinedFuncIndex::new(0)].address_map.instructions;
+            let offset_of = |srcloc: u32| {
+                instructions
+                    .iter()
+                    .find(|i| i.srcloc.bits() == srcloc)
+                    .map(|i| i.code_offset)
+                    .unwrap()
+            };
+            (offset_of(MUL), offset_of(ADD))
+        };
+
+        let (mul, add) = code_offsets(None);
+        assert!(add < mul, "`else` should be placed first without a profile");
+
+        let (mul, add) = code_offsets(Some(&HotOffsets(vec![MUL])));
+        assert!(mul < add, "hot `then` should be placed before `else`");
+
+        let (mul, add) = code_offsets(Some(&HotOffsets(vec![ADD])));
+        assert!(add < mul, "hot `else` should stay before `then`");
+    }
+    #[test]
+    fn compile_module_concurrent_matches_sequential() {
+        let compiler = Compiler::new(
+            &*Compiler::builder(CompilationStrategy::Auto),
+            Tunables::default(),
+            WasmFeatures::default(),
+            false,
+        );
+
+        // Four functions of type `() -> i32` returning 1, 2, 3 and 4.
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x05, 0x04, 0x00, 0x00, 0x00, 0x00, // function section
+            0x0a, 0x15, 0x04, // code section
+            0x04, 0x00, 0x41, 0x01, 0x0b, // i32.const 1
+            0x04, 0x00, 0x41, 0x02, 0x0b, // i32.const 2
+            0x04, 0x00, 0x41, 0x03, 0x0b, // i32.const 3
+            0x04, 0x00, 0x41, 0x04, 0x0b, // i32.const 4
+        ];
+
+        let compile = |parallelism| {
+            let (_, mut translations, types) =
+                ModuleEnvironment::new(compiler.tunables(), compiler.features())
+                    .translate(&wasm)
+                    .unwrap();
+            compiler
+                .compile_module_concurrent(&mut translations[0], &types, parallelism)
+                .unwrap()
+        };
+
+        let sequential = compile(1);
+        assert_eq!(sequential.funcs.len(), 4);
+        // Run twice to also go through the cached pool.
+        for _ in 0..2 {
+            let concurrent = compile(4);
+            assert_eq!(concurrent.funcs.len(), sequential.funcs.len());
+            for (index, func) in sequential.funcs.iter() {
+                assert_eq!(concurrent.funcs[index].body, func.body);
+            }
+            assert_eq!(concurrent.obj, sequential.obj);
+        }
+    }
+    #[test]
+    fn compile_module_concurrent_reports_lowest_index_error() {
+        let compiler = Compiler::new(
+            &*Compiler::builder(CompilationStrategy::Auto),
+            Tunables::default(),
+            WasmFeatures::default(),
+            false,
+        );
+
+        // Four functions of type `() -> i32` where the second and the fourth
+        // are just `end`.
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x05, 0x04, 0x00, 0x00, 0x00, 0x00, // function section
+            0x0a, 0x11, 0x04, // code section
+            0x04, 0x00, 0x41, 0x01, 0x0b, // i32.const 1
+            0x02, 0x00, 0x0b, // end at offset 32
+            0x04, 0x00, 0x41, 0x03, 0x0b, // i32.const 3
+            0x02, 0x00, 0x0b, // end at offset 40
+        ];
+
+        for _ in 0..10 {
+            let (_, mut translations, types) =
+                ModuleEnvironment::new(compiler.tunables(), compiler.features())
+                    .translate(&wasm)
+                    .unwrap();
+            match compiler.compile_module_concurrent(&mut translations[0], &types, 4) {
+                Err(SetupError::Compile(CompileError::Wasm(WasmError::InvalidWebAssembly {
+                    offset,
+                    ..
+                }))) => assert_eq!(offset, 32),
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("invalid function bodies compiled"),
+            }
+        }
+    }
+    #[test]
+    fn compiled_functions_record_compile_time_metrics() {
+        let compiler = Compiler::new(
+            &*Compiler::builder(CompilationStrategy::Auto),
+            Tunables::default(),
+            WasmFeatures::default(),
+            false,
+        );
+
+        // (func (param i32) (result i32)
+        //   (i32.add (i32.mul (local.get 0) (i32.const 7)) (i32.const 9)))
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x0a, 0x0c, 0x01, 0x0a, 0x00, // code section
+            0x20, 0x00, 0x41, 0x07, 0x6c, 0x41, 0x09, 0x6a, 0x0b,
+        ];
+
+        let (_, mut translations, types) =
+            ModuleEnvironment::new(compiler.tunables(), compiler.features())
+                .translate(&wasm)
+                .unwrap();
+        let Compilation { funcs, .. } = compiler.compile(&mut translations[0], &types).unwrap();
+        let metrics = funcs[DefinedFuncIndex::new(0)].compile_time_metrics;
+        assert!(metrics.total_ns > 0);
+        let phases =
+            metrics.ir_gen_ns + metrics.legalize_ns + metrics.regalloc_ns + metrics.emit_ns;
+        assert!(phases <= metrics.total_ns, "{:?}", metrics);
+    }
+    #[test]
+    fn collect_call_sites_returns_direct_calls() {
+        let mut builder = Compiler::builder(CompilationStrategy::Auto);
+        // Without SSE4.1 `f32.floor` is compiled to a libcall.
+        if cfg!(target_arch = "x86_64") {
+            builder.set("has_sse41", "false").unwrap();
+        }
+        let compiler = Compiler::new(
+            &*builder,
+            Tunables::default(),
+            WasmFeatures::default(),
+            false,
+        );
+
+        // (table 1 funcref)
+        // (func (result f32)
+        //   (call 1)
+        //   (call_indirect (type 0) (i32.const 0))
+        //   (f32.floor (f32.const 0)))
+        // (func)
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x08, 0x02, 0x60, 0x00, 0x00, 0x60, 0x00, 0x01, 0x7d, // type section
+            0x03, 0x03, 0x02, 0x01, 0x00, // function section
+            0x04, 0x04, 0x01, 0x70, 0x00, 0x01, // table section
+            0x0a, 0x14, 0x02, 0x0f, 0x00, // code section
+            0x10, 0x01, // call 1
+            0x41, 0x00, 0x11, 0x00, 0x00, // call_indirect
+            0x43, 0x00, 0x00, 0x00, 0x00, 0x8e, // f32.floor
+            0x0b, // end
+            0x02, 0x00, 0x0b, // func 1
+        ];
+
+        let (_, mut translations, types) =
+            ModuleEnvironment::new(compiler.tunables(), compiler.features())
+                .translate(&wasm)
+                .unwrap();
+        let Compilation { funcs, .. } = compiler.compile(&mut translations[0], &types).unwrap();
+        let caller = &funcs[DefinedFuncIndex::new(0)];
+        if cfg!(target_arch = "x86_64") {
+            assert!(caller
+                .relocations
+                .iter()
+                .any(|r| matches!(r.reloc_target, RelocationTarget::LibCall(_))));
+        }
+
+        // Neither the indirect call nor the libcall are included.
+        let call_sites = compiler.compiler().collect_call_sites(caller);
+        assert_eq!(call_sites.len(), 1);
+        let (offset, callee) = call_sites.into_iter().next().unwrap();
+        assert_eq!(callee, FuncIndex::new(1));
+        assert!((offset as usize) < caller.body.len());
+    }
+}
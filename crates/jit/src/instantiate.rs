@@ -9,12 +9,13 @@ use crate::debug::create_gdbjit_image;
 use crate::link::link_module;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::mem;
 use std::ops::Range;
 use std::sync::Arc;
 use thiserror::Error;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::{
-    DefinedFuncIndex, InstanceTypeIndex, ModuleTypeIndex, SignatureIndex, WasmFuncType,
+    DefinedFuncIndex, EntityIndex, InstanceTypeIndex, ModuleTypeIndex, SignatureIndex, WasmFuncType,
 };
 use wasmtime_environ::{
     CompileError, DebugInfoData, FunctionAddressMap, InstanceSignature, Module, ModuleEnvironment,
@@ -97,11 +98,41 @@ impl CompilationArtifacts {
         data: &[u8],
         use_paged_mem_init: bool,
     ) -> Result<(usize, Vec<CompilationArtifacts>, TypeTables), SetupError> {
-        let (main_module, translations, types) =
+        Self::build_with_roots(compiler, data, use_paged_mem_init, None)
+    }
+
+    /// Like `build`, but when `roots` is given, the bodies of the main
+    /// module's functions which can't be reached from the functions it
+    /// exports under those names are replaced with a trap rather than
+    /// compiled.
+    ///
+    /// See `ModuleTranslation::trim_unreachable_functions`.
+    pub fn build_with_roots(
+        compiler: &Compiler,
+        data: &[u8],
+        use_paged_mem_init: bool,
+        roots: Option<&[&str]>,
+    ) -> Result<(usize, Vec<CompilationArtifacts>, TypeTables), SetupError> {
+        let (main_module, mut translations, types) =
             ModuleEnvironment::new(compiler.tunables(), compiler.features())
                 .translate(data)
                 .map_err(|error| SetupError::Compile(CompileError::Wasm(error)))?;
 
+        if let Some(roots) = roots {
+            let translation = &mut translations[main_module];
+            let roots = roots
+                .iter()
+                .map(|name| match translation.module.exports.get(*name) {
+                    Some(EntityIndex::Function(func)) => Ok(*func),
+                    _ => Err(SetupError::Validate(format!(
+                        "module has no function export named `{}`",
+                        name
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            *translation = mem::take(translation).trim_unreachable_functions(&roots);
+        }
+
         let list = compiler.run_maybe_parallel::<_, _, SetupError, _>(
             translations,
             |mut translation| {
@@ -129,6 +160,7 @@ impl CompilationArtifacts {
                             stack_maps: func.stack_maps,
                             traps: func.traps,
                             address_map: func.address_map,
+                            frame_size: func.frame_size,
                         })
                         .collect(),
                     native_debug_info_present: compiler.tunables().generate_native_debuginfo,
@@ -165,6 +197,7 @@ pub struct FunctionInfo {
     pub traps: Vec<TrapInformation>,
     pub address_map: FunctionAddressMap,
     pub stack_maps: Vec<StackMapInformation>,
+    pub frame_size: u32,
 }
 
 /// This is intended to mirror the type tables in `wasmtime_environ`, except that
@@ -345,6 +378,20 @@ impl CompiledModule {
             .expect("defined function should be present")
     }
 
+    /// Estimates the maximum size, in bytes, of the wasm call stack for this
+    /// module from the stack frame sizes of its compiled functions.
+    ///
+    /// See `Module::max_stack_depth_estimate`.
+    pub fn max_stack_depth_estimate(&self) -> u32 {
+        let frame_sizes = self
+            .artifacts
+            .funcs
+            .values()
+            .map(|info| info.frame_size)
+            .collect();
+        self.module().max_stack_depth_estimate(&frame_sizes)
+    }
+
     /// Returns all ranges covered by JIT code.
     pub fn jit_code_ranges<'a>(&'a self) -> impl Iterator<Item = (usize, usize)> + 'a {
         self.code.code_memory.published_ranges()
@@ -644,6 +644,15 @@ impl VMExternRefActivationsTable {
             .insert(VMExternRefWithTraits(externref));
     }
 
+    /// Get the number of references currently held by this table.
+    ///
+    /// This is an over-approximation of the number of live references: it
+    /// includes duplicates and references that have been dropped by Wasm but
+    /// not yet collected.
+    pub fn occupancy(&self) -> usize {
+        self.num_filled_in_bump_chunk() + self.over_approximated_stack_roots.len()
+    }
+
     fn num_filled_in_bump_chunk(&self) -> usize {
         let next = unsafe { *self.alloc.next.get() };
         let bytes_unused = (self.alloc.end.as_ptr() as usize) - (next.as_ptr() as usize);
@@ -1036,4 +1045,16 @@ mod tests {
             actual_offset
         );
     }
+
+    #[test]
+    fn occupancy_counts_inserted_refs() {
+        let mut table = VMExternRefActivationsTable::new();
+        assert_eq!(table.occupancy(), 0);
+
+        let r = VMExternRef::new(42_u32);
+        for _ in 0..3 {
+            assert!(table.try_insert(r.clone()).is_ok());
+        }
+        assert_eq!(table.occupancy(), 3);
+    }
 }
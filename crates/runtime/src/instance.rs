@@ -3,13 +3,14 @@
 //! `InstanceHandle` is a reference-counting handle for an `Instance`.
 
 use crate::export::Export;
-use crate::externref::VMExternRefActivationsTable;
+use crate::externref::{VMExternRef, VMExternRefActivationsTable};
 use crate::memory::{Memory, RuntimeMemoryCreator};
 use crate::table::{Table, TableElement, TableElementType};
-use crate::traphandlers::Trap;
+use crate::traphandlers::{catch_traps, Trap};
 use crate::vmcontext::{
     VMCallerCheckedAnyfunc, VMContext, VMFunctionImport, VMGlobalDefinition, VMGlobalImport,
     VMInterrupts, VMMemoryDefinition, VMMemoryImport, VMTableDefinition, VMTableImport,
+    VMTrampoline,
 };
 use crate::{ExportFunction, ExportGlobal, ExportMemory, ExportTable, Store};
 use memoffset::offset_of;
@@ -18,8 +19,10 @@ use std::alloc::Layout;
 use std::any::Any;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::{mem, ptr, slice};
 use wasmtime_environ::entity::{packed_option::ReservedValue, EntityRef, EntitySet, PrimaryMap};
@@ -27,7 +30,7 @@ use wasmtime_environ::wasm::{
     DataIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex, ElemIndex, EntityIndex,
     FuncIndex, GlobalIndex, MemoryIndex, TableIndex, WasmType,
 };
-use wasmtime_environ::{ir, HostPtr, Module, VMOffsets};
+use wasmtime_environ::{ir, HostPtr, Initializer, Module, VMOffsets};
 
 mod allocator;
 
@@ -65,6 +68,21 @@ pub trait ResourceLimiter {
     /// this method.
     fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> bool;
 
+    /// Notifies the resource limiter that a `memory.grow` instruction has
+    /// executed.
+    ///
+    /// * `memory` is the index of the grown memory within its instance.
+    /// * `delta` is the number of pages the memory was requested to grow by.
+    /// * `result` is the previous size of the memory in pages, or `None` if
+    ///   the memory couldn't be grown.
+    ///
+    /// This is only invoked for modules compiled with `memory.grow`
+    /// monitoring enabled, and it is invoked after `memory_growing` has been
+    /// consulted about the growth. The default implementation does nothing.
+    fn memory_grew(&mut self, memory: u32, delta: u64, result: Option<u64>) {
+        drop((memory, delta, result));
+    }
+
     /// Notifies the resource limiter that an instance's table has been requested to grow.
     ///
     /// * `current` is the current number of elements in the table.
@@ -154,6 +172,10 @@ pub(crate) struct Instance {
     /// allocation, but some host-defined objects will store their state here.
     host_state: Box<dyn Any + Send + Sync>,
 
+    /// The range of addresses spanned by the compiled code of the module's
+    /// defined functions, which is empty if it defines none.
+    code_range: Range<usize>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -170,6 +192,15 @@ impl Instance {
             .cast()
     }
 
+    /// Like `vmctx_plus_offset`, but derives the pointer from `&mut self` so
+    /// that it may be written through. Writes through a pointer derived from
+    /// `&self` are allowed to be optimized away.
+    unsafe fn vmctx_plus_offset_mut<T>(&mut self, offset: u32) -> *mut T {
+        (&mut self.vmctx as *mut VMContext as *mut u8)
+            .add(usize::try_from(offset).unwrap())
+            .cast()
+    }
+
     pub(crate) fn module(&self) -> &Arc<Module> {
         &self.module
     }
@@ -201,9 +232,10 @@ impl Instance {
     }
 
     /// Updates the value for a defined table to `VMTableDefinition`.
-    fn set_table(&self, index: DefinedTableIndex, table: VMTableDefinition) {
+    fn set_table(&mut self, index: DefinedTableIndex, table: VMTableDefinition) {
         unsafe {
-            *self.table_ptr(index) = table;
+            let offset = self.offsets.vmctx_vmtable_definition(index);
+            *self.vmctx_plus_offset_mut::<VMTableDefinition>(offset) = table;
         }
     }
 
@@ -228,9 +260,10 @@ impl Instance {
     }
 
     /// Set the indexed memory to `VMMemoryDefinition`.
-    fn set_memory(&self, index: DefinedMemoryIndex, mem: VMMemoryDefinition) {
+    fn set_memory(&mut self, index: DefinedMemoryIndex, mem: VMMemoryDefinition) {
         unsafe {
-            *self.memory_ptr(index) = mem;
+            let offset = self.offsets.vmctx_vmmemory_definition(index);
+            *self.vmctx_plus_offset_mut::<VMMemoryDefinition>(offset) = mem;
         }
     }
 
@@ -416,17 +449,7 @@ impl Instance {
     /// of pages. Returns `Some` with the old size in bytes if growth was
     /// successful.
     pub(crate) fn memory_grow(&mut self, index: MemoryIndex, delta: u64) -> Option<usize> {
-        let (idx, instance) = if let Some(idx) = self.module.defined_memory_index(index) {
-            (idx, self)
-        } else {
-            let import = self.imported_memory(index);
-            unsafe {
-                let foreign_instance = (*import.vmctx).instance_mut();
-                let foreign_memory_def = &*import.from;
-                let foreign_memory_index = foreign_instance.memory_index(foreign_memory_def);
-                (foreign_memory_index, foreign_instance)
-            }
-        };
+        let (idx, instance) = self.defining_memory(index);
         let limiter = unsafe { (*instance.store()).limiter() };
         let memory = &mut instance.memories[idx];
 
@@ -440,6 +463,35 @@ impl Instance {
         result
     }
 
+    /// Replaces the memory at `index` with `new_memory`, returning the
+    /// previous memory.
+    ///
+    /// Imported memories are replaced in the instance that defines them, so
+    /// every instance sharing the memory observes the new one.
+    pub(crate) fn replace_memory(&mut self, index: MemoryIndex, new_memory: Memory) -> Memory {
+        let (idx, instance) = self.defining_memory(index);
+        let old = mem::replace(&mut instance.memories[idx], new_memory);
+        let vmmemory = instance.memories[idx].vmmemory();
+        instance.set_memory(idx, vmmemory);
+        old
+    }
+
+    /// Resolves `index` to the instance that defines the memory, along with
+    /// the memory's index within that instance.
+    fn defining_memory(&mut self, index: MemoryIndex) -> (DefinedMemoryIndex, &mut Instance) {
+        if let Some(idx) = self.module.defined_memory_index(index) {
+            (idx, self)
+        } else {
+            let import = self.imported_memory(index);
+            unsafe {
+                let foreign_instance = (*import.vmctx).instance_mut();
+                let foreign_memory_def = &*import.from;
+                let foreign_memory_index = foreign_instance.memory_index(foreign_memory_def);
+                (foreign_memory_index, foreign_instance)
+            }
+        }
+    }
+
     pub(crate) fn table_element_type(&mut self, table_index: TableIndex) -> TableElementType {
         unsafe { (*self.get_table(table_index)).element_type() }
     }
@@ -476,7 +528,8 @@ impl Instance {
 
         // Keep the `VMContext` pointers used by compiled Wasm code up to
         // date.
-        self.set_table(table_index, self.tables[table_index].vmtable());
+        let vmtable = self.tables[table_index].vmtable();
+        self.set_table(table_index, vmtable);
 
         result
     }
@@ -510,6 +563,66 @@ impl Instance {
         self.vmctx_plus_offset(self.offsets.vmctx_anyfuncs_begin())
     }
 
+    /// Replace the function imported as `module`/`field` with `new_import`,
+    /// returning the previously imported function.
+    unsafe fn replace_import(
+        &mut self,
+        module: &str,
+        field: &str,
+        new_import: ExportFunction,
+    ) -> Result<VMCallerCheckedAnyfunc, LinkError> {
+        let index = self
+            .module
+            .initializers
+            .iter()
+            .find_map(|init| match init {
+                Initializer::Import {
+                    name,
+                    field: Some(f),
+                    index,
+                } if name == module && f == field => Some(*index),
+                _ => None,
+            })
+            .ok_or_else(|| LinkError::Other(format!("unknown import: {}::{}", module, field)))?;
+        let index = match index {
+            EntityIndex::Function(index) => index,
+            _ => {
+                return Err(LinkError::Other(format!(
+                    "import {}::{} is not a function",
+                    module, field
+                )))
+            }
+        };
+
+        let new = new_import.anyfunc.as_ref().clone();
+        let old_anyfunc: *const VMCallerCheckedAnyfunc =
+            self.vmctx_plus_offset(self.offsets.vmctx_anyfunc(index));
+        if (*old_anyfunc).type_index != new.type_index {
+            return Err(LinkError::Other(format!(
+                "function types incompatible for import {}::{}",
+                module, field
+            )));
+        }
+
+        // Everything has been checked by now, so nothing below can fail and
+        // leave the import half-replaced. Both the import itself and this
+        // instance's anyfunc for it need to be updated: the former is used by
+        // direct calls and the latter by `ref.func`, tables, and exports of
+        // the import.
+        let import: *mut VMFunctionImport =
+            self.vmctx_plus_offset_mut(self.offsets.vmctx_vmfunction_import(index));
+        ptr::write(
+            import,
+            VMFunctionImport {
+                body: new.func_ptr,
+                vmctx: new.vmctx,
+            },
+        );
+        let anyfunc: *mut VMCallerCheckedAnyfunc =
+            self.vmctx_plus_offset_mut(self.offsets.vmctx_anyfunc(index));
+        Ok(ptr::replace(anyfunc, new))
+    }
+
     fn find_passive_segment<'a, I, D, T>(
         index: I,
         index_map: &HashMap<I, usize>,
@@ -795,6 +908,70 @@ impl Instance {
             }
         }
     }
+
+    #[cfg(debug_assertions)]
+    fn assert_invariants(&self) {
+        for i in 0..self.module.num_imported_funcs {
+            let import = self.imported_function(FuncIndex::new(i));
+            assert!(
+                !import.vmctx.is_null(),
+                "imported function {} has a null vmctx",
+                i
+            );
+        }
+
+        let page_size = region::page::size();
+        for index in self.module.memory_plans.keys() {
+            let memory = self.get_memory(index);
+            if memory.current_length == 0 {
+                continue;
+            }
+            assert_eq!(
+                memory.base as usize % page_size,
+                0,
+                "base of memory {} is not page-aligned",
+                index.index()
+            );
+        }
+
+        for (index, global) in self.module.globals.iter() {
+            let defined_index = match self.module.defined_global_index(index) {
+                Some(i) => i,
+                None => continue,
+            };
+            let width = match global.wasm_ty {
+                WasmType::I32 | WasmType::F32 => 4,
+                WasmType::I64 | WasmType::F64 => 8,
+                WasmType::V128 => 16,
+                WasmType::FuncRef | WasmType::ExternRef | WasmType::ExnRef => {
+                    mem::size_of::<usize>()
+                }
+            };
+            let bits = unsafe { self.global(defined_index).as_u128_bits() };
+            assert!(
+                bits[width..].iter().all(|b| *b == 0),
+                "global {} holds a value out of range for {:?}",
+                index.index(),
+                global.wasm_ty
+            );
+        }
+
+        for (index, table) in self.tables.iter() {
+            let vmtable = self.table(index);
+            assert_eq!(
+                vmtable.current_elements,
+                table.size(),
+                "element count of table {} doesn't match its definition",
+                index.index()
+            );
+            assert_eq!(
+                vmtable.base,
+                table.vmtable().base,
+                "base of table {} doesn't match its definition",
+                index.index()
+            );
+        }
+    }
 }
 
 impl Drop for Instance {
@@ -804,9 +981,44 @@ impl Drop for Instance {
 }
 
 /// A handle holding an `Instance` of a WebAssembly module.
-#[derive(Hash, PartialEq, Eq)]
+///
+/// Handles compare equal, and hash the same, when they point at the same
+/// instance.
 pub struct InstanceHandle {
     instance: *mut Instance,
+    // The version of the instance's slot when this handle was allocated, if
+    // the instance allocator tracks slot versions.
+    version: Option<SlotVersion>,
+}
+
+/// The version of a reusable instance slot at the time an `InstanceHandle`
+/// was allocated for it.
+///
+/// See `PoolingInstanceAllocator::with_slot_version_counter`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub(crate) struct SlotVersion {
+    /// The slot's version counter, which is bumped whenever the slot is
+    /// deallocated.
+    pub(crate) counter: *const AtomicU64,
+    /// The value of `counter` when the handle was allocated.
+    pub(crate) version: u64,
+}
+
+// A handle's slot version only serves to catch stale handles, so it doesn't
+// take part in comparisons: handles created from a `VMContext` don't record
+// it but still refer to the same instance.
+impl PartialEq for InstanceHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.instance == other.instance
+    }
+}
+
+impl Eq for InstanceHandle {}
+
+impl Hash for InstanceHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.instance.hash(state);
+    }
 }
 
 // These are only valid if the `Instance` type is send/sync, hence the
@@ -831,9 +1043,16 @@ impl InstanceHandle {
         let instance = (&mut *vmctx).instance();
         Self {
             instance: instance as *const Instance as *mut Instance,
+            version: None,
         }
     }
 
+    /// Creates a handle for an instance which was just allocated, optionally
+    /// recording the version of the slot it was allocated in.
+    pub(crate) fn new(instance: *mut Instance, version: Option<SlotVersion>) -> Self {
+        Self { instance, version }
+    }
+
     /// Return a reference to the vmctx used by compiled wasm code.
     pub fn vmctx(&self) -> &VMContext {
         self.instance().vmctx()
@@ -869,6 +1088,35 @@ impl InstanceHandle {
         self.instance().host_state()
     }
 
+    /// Returns a clone of the custom state attached to this instance, or
+    /// `None` if it isn't a `T`.
+    pub fn clone_host_state<T: Any + Clone>(&self) -> Option<T> {
+        self.host_state().downcast_ref::<T>().cloned()
+    }
+
+    /// Replaces the custom state attached to this instance with `new`,
+    /// returning the previous state, or `None` if the instance had no state,
+    /// which is represented by `()`.
+    ///
+    /// # Safety
+    ///
+    /// Code associated with this instance may rely on the type of its host
+    /// state without checking it; for example host functions defined by
+    /// `wasmtime::Func` cast their host state back to their closure type. The
+    /// caller must ensure that nothing will access the state as its previous
+    /// type after it has been replaced.
+    pub unsafe fn replace_host_state<T: Any + Send + Sync>(
+        &mut self,
+        new: T,
+    ) -> Option<Box<dyn Any + Send + Sync>> {
+        let old = mem::replace(&mut self.instance_mut().host_state, Box::new(new));
+        if old.is::<()>() {
+            None
+        } else {
+            Some(old)
+        }
+    }
+
     /// Return the memory index for the given `VMMemoryDefinition` in this instance.
     pub unsafe fn memory_index(&self, memory: &VMMemoryDefinition) -> DefinedMemoryIndex {
         self.instance().memory_index(memory)
@@ -889,16 +1137,428 @@ impl InstanceHandle {
         self.instance_mut().get_defined_table(index)
     }
 
+    /// Returns a new reference to the `externref` stored in the global at
+    /// `index`, or `None` if the global holds a null reference.
+    ///
+    /// The returned reference keeps its value alive even if wasm overwrites
+    /// the global afterwards.
+    ///
+    /// Returns an error if the global's type isn't `externref`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn read_global_extern_ref(
+        &self,
+        index: GlobalIndex,
+    ) -> anyhow::Result<Option<VMExternRef>> {
+        let instance = self.instance();
+        if instance.module.globals[index].wasm_ty != WasmType::ExternRef {
+            anyhow::bail!("global {} is not an externref global", index.index());
+        }
+        Ok(unsafe {
+            (*instance.defined_or_imported_global_ptr(index))
+                .as_externref()
+                .clone()
+        })
+    }
+
+    /// Checks that the `VMContext` of this instance is consistent with the
+    /// instance's module and runtime state.
+    ///
+    /// This is meant to be called after the host modifies an instance, e.g.
+    /// replacing an import, growing a memory or writing a global. It only
+    /// does anything in builds with `debug_assertions` enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an imported function has a null `VMContext`, if the
+    /// base of a non-empty memory isn't page-aligned, if a defined global has
+    /// bits set beyond the width of its type, or if a defined table's
+    /// `VMTableDefinition` is out of sync with the table.
+    pub fn debug_assert_invariants(&self) {
+        #[cfg(debug_assertions)]
+        self.instance().assert_invariants();
+    }
+
+    /// Overwrite the value of the defined global at `index` with `val`.
+    ///
+    /// The bits of `val` are copied as-is without being interpreted, except
+    /// for `externref` globals: the reference held by `val` is moved into the
+    /// global and the reference previously stored there is released.
+    ///
+    /// Returns an error if the global is imported or immutable.
+    ///
+    /// # Safety
+    ///
+    /// `val` must hold a valid value of the global's type.
+    pub unsafe fn write_global_raw(
+        &mut self,
+        index: GlobalIndex,
+        mut val: VMGlobalDefinition,
+    ) -> anyhow::Result<()> {
+        let instance = self.instance_mut();
+        let global = &instance.module.globals[index];
+        let defined_index = match instance.module.defined_global_index(index) {
+            Some(i) => i,
+            None => anyhow::bail!("global {} is imported", index.index()),
+        };
+        if !global.mutability {
+            anyhow::bail!("global {} is immutable", index.index());
+        }
+        let dst = instance.global_ptr(defined_index);
+        match global.wasm_ty {
+            WasmType::ExternRef => {
+                *(*dst).as_externref_mut() = val.as_externref_mut().take();
+            }
+            _ => ptr::write(dst, val),
+        }
+        Ok(())
+    }
+
+    /// Replace the function imported as `module`/`field` with `new_import`,
+    /// returning the function that was previously imported.
+    ///
+    /// Returns an error if there is no such import, if it isn't a function
+    /// import, or if the type of `new_import` doesn't match the type of the
+    /// import.
+    ///
+    /// # Safety
+    ///
+    /// The replacement isn't atomic with respect to running wasm code, so this
+    /// must not be called while wasm code from this instance is executing on
+    /// another thread. Calls to the old function that are already in flight
+    /// on this thread keep running it, so the caller must keep the old
+    /// function (and its `VMContext`) alive until they have all returned, and
+    /// must keep `new_import` alive as long as this instance.
+    pub unsafe fn replace_import(
+        &mut self,
+        module: &str,
+        field: &str,
+        new_import: ExportFunction,
+    ) -> Result<VMCallerCheckedAnyfunc, LinkError> {
+        self.instance_mut()
+            .replace_import(module, field, new_import)
+    }
+
+    /// Replaces the linear memory at `index` with `new_memory`, returning the
+    /// memory that was previously in place.
+    ///
+    /// The `VMMemoryDefinition` in the defining instance's `VMContext` is
+    /// updated to describe `new_memory`, so subsequent wasm accesses (including
+    /// from instances importing the memory) use it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds for this instance's module.
+    ///
+    /// # Safety
+    ///
+    /// This must only be called while no wasm code from this instance, or any
+    /// instance sharing the memory, is executing. `new_memory` must satisfy
+    /// the memory's `MemoryPlan`: compiled code may elide bounds checks based
+    /// on the plan's style and guard sizes. With the pooling allocator the
+    /// original memory must be put back before the instance is deallocated.
+    pub unsafe fn replace_memory(&mut self, index: MemoryIndex, new_memory: Memory) -> Memory {
+        self.instance_mut().replace_memory(index, new_memory)
+    }
+
+    /// Returns the size, in bytes, of the allocation holding this instance and
+    /// its `VMContext`.
+    ///
+    /// This doesn't include the instance's linear memories or table elements.
+    pub fn instance_size_bytes(&self) -> usize {
+        self.instance().alloc_layout().size()
+    }
+
+    /// Returns the number of frames of this instance's wasm code on this
+    /// thread's stack.
+    ///
+    /// Frames are attributed by the code they're running, and instances of
+    /// the same module share their code, so frames of other instances of this
+    /// instance's module executing on this thread are counted as well. Returns
+    /// 0 if none of that code is currently executing on this thread.
+    pub fn wasm_call_stack_depth(&self) -> usize {
+        let code_range = &self.instance().code_range;
+        crate::traphandlers::count_wasm_frames(|pc| code_range.contains(&pc))
+    }
+
+    /// Calls the function exported as `name` through `trampoline`, returning
+    /// any trap raised while it runs.
+    ///
+    /// The `nargs` arguments are read from `args` and, if the call returns
+    /// normally, the `nresults` results are written to `results`. Values use
+    /// the same 16-byte slots as `VMTrampoline`. Unless wasm is already
+    /// running on this thread, wasm is given 1 MiB of stack below the
+    /// caller's frame.
+    ///
+    /// This bypasses everything a `Store` sets up around calls, such as the
+    /// externref stack canary, and is only intended for test harnesses.
+    ///
+    /// Trampolines are compiled per signature alongside the module's code
+    /// and are owned by the module's compiled artifacts rather than by the
+    /// instance, so the caller has to look up the one for the export's
+    /// signature and pass it in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no function exported as `name`, or if the instance
+    /// was allocated without a store.
+    ///
+    /// # Safety
+    ///
+    /// `trampoline` must be the trampoline for the export's signature, and
+    /// `nargs` and `nresults` must match that signature. `args` must be valid
+    /// for `nargs` reads and `results` for `nresults` writes.
+    pub unsafe fn call_exported_func_raw(
+        &mut self,
+        name: &str,
+        trampoline: VMTrampoline,
+        args: *const u128,
+        nargs: usize,
+        results: *mut u128,
+        nresults: usize,
+    ) -> Result<(), Trap> {
+        let anyfunc = match self.module().exports.get(name) {
+            Some(index @ EntityIndex::Function(_)) => match self.lookup_by_declaration(index) {
+                Export::Function(f) => f.anyfunc,
+                _ => unreachable!(),
+            },
+            _ => panic!("no function exported as `{}`", name),
+        };
+        let interrupts = *self.instance().interrupts() as *mut VMInterrupts;
+        assert!(!interrupts.is_null(), "instance has no store");
+
+        let mut values = vec![0u128; nargs.max(nresults)];
+        ptr::copy_nonoverlapping(args, values.as_mut_ptr(), nargs);
+
+        // Only set a stack limit if there isn't one already, in which case
+        // it's restored when the call returns.
+        let stack_pointer = &values as *const _ as usize;
+        let limit = stack_pointer.saturating_sub(1 << 20);
+        let set_limit = (*interrupts)
+            .stack_limit
+            .compare_exchange(
+                usize::max_value(),
+                limit,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok();
+
+        let result = catch_traps(interrupts, None, self.vmctx_ptr(), |caller| {
+            let anyfunc = anyfunc.as_ref();
+            trampoline(
+                anyfunc.vmctx,
+                caller,
+                anyfunc.func_ptr.as_ptr(),
+                values.as_mut_ptr(),
+            )
+        });
+
+        // An interrupt requested during the call replaces the limit with
+        // `INTERRUPTED`, which must stay pending for the next call.
+        if set_limit {
+            let _ = (*interrupts).stack_limit.compare_exchange(
+                limit,
+                usize::max_value(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+
+        if result.is_ok() {
+            ptr::copy_nonoverlapping(values.as_ptr(), results, nresults);
+        }
+        result
+    }
+
+    /// Copies `data` into the memory at `memory_index`, starting at
+    /// `dst_offset`.
+    ///
+    /// This has the same semantics as a wasm `memory.init` of the whole of
+    /// `data`: if the destination range is out of bounds an out-of-bounds
+    /// memory access trap is returned and the memory is left unmodified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory_index` is out of bounds for this instance's module.
+    pub fn bulk_memory_init(
+        &mut self,
+        memory_index: MemoryIndex,
+        dst_offset: u64,
+        data: &[u8],
+    ) -> Result<(), Trap> {
+        let len =
+            u32::try_from(data.len()).map_err(|_| Trap::wasm(ir::TrapCode::HeapOutOfBounds))?;
+        self.instance_mut()
+            .memory_init_segment(memory_index, data, dst_offset, 0, len)
+    }
+
+    /// Maps `len` bytes of `file`, starting at `file_offset`, over the bytes
+    /// `offset..offset + len` of the memory at `index`, so that reads and
+    /// writes of that range go to the file.
+    ///
+    /// The mapping is shared, so the file must be open for reading and
+    /// writing, and writes through the memory are visible to other users of
+    /// the file. `offset` and `file_offset` must be multiples of the host page
+    /// size and the range must be within the memory's current length, so the
+    /// mapping never covers the memory's guard pages. If `len` isn't a
+    /// multiple of the host page size the rest of the last page is mapped
+    /// from the file as well.
+    ///
+    /// Only memories defined by this instance and allocated on demand can be
+    /// mapped; memories owned by the pooling allocator are rejected, since it
+    /// manages their pages itself. A memory created by a custom
+    /// `RuntimeMemoryCreator` must be backed by host pages that can be
+    /// replaced with `mmap`.
+    ///
+    /// # Safety
+    ///
+    /// This must not be called while wasm code using the memory is executing,
+    /// and the file must be at least `len` bytes long for as long as it's
+    /// mapped, or accesses trap on the host. The range must be restored with
+    /// `memory_unmap_region` before the memory grows or the instance is
+    /// deallocated, as neither knows about the mapping.
+    #[cfg(unix)]
+    pub unsafe fn memory_map_file(
+        &mut self,
+        index: MemoryIndex,
+        offset: u64,
+        len: u64,
+        file: &std::fs::File,
+        file_offset: u64,
+    ) -> anyhow::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        if file_offset % region::page::size() as u64 != 0 {
+            anyhow::bail!(
+                "file offset {:#x} is not a multiple of the page size",
+                file_offset
+            );
+        }
+        let file_offset = libc::off_t::try_from(file_offset)
+            .map_err(|_| anyhow::anyhow!("file offset {:#x} is too large", file_offset))?;
+        let (addr, len) = self.memory_region(index, offset, len)?;
+        let ptr = libc::mmap(
+            addr as _,
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_FIXED | libc::MAP_SHARED,
+            file.as_raw_fd(),
+            file_offset,
+        );
+        if ptr == libc::MAP_FAILED {
+            anyhow::bail!("mmap failed: {}", std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Replaces the bytes `offset..offset + len` of the memory at `index`
+    /// with fresh zeroed pages, removing a mapping made by `memory_map_file`.
+    ///
+    /// The same alignment, bounds and ownership rules as `memory_map_file`
+    /// apply.
+    ///
+    /// # Safety
+    ///
+    /// This must not be called while wasm code using the memory is executing.
+    #[cfg(unix)]
+    pub unsafe fn memory_unmap_region(
+        &mut self,
+        index: MemoryIndex,
+        offset: u64,
+        len: u64,
+    ) -> anyhow::Result<()> {
+        let (addr, len) = self.memory_region(index, offset, len)?;
+        let ptr = libc::mmap(
+            addr as _,
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_FIXED | libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            anyhow::bail!("mmap failed: {}", std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Checks that the memory at `index` is defined by this instance and
+    /// allocated on demand, and that `offset..offset + len` is a page-aligned,
+    /// non-empty range within its current length, returning the range's
+    /// address and its length rounded up to whole pages.
+    #[cfg(unix)]
+    fn memory_region(
+        &self,
+        index: MemoryIndex,
+        offset: u64,
+        len: u64,
+    ) -> anyhow::Result<(*mut u8, usize)> {
+        let instance = self.instance();
+        match instance.module.defined_memory_index(index) {
+            Some(defined) => {
+                if let Memory::Static { .. } = instance.memories[defined] {
+                    anyhow::bail!("memories of the pooling allocator cannot be remapped");
+                }
+            }
+            None => anyhow::bail!(
+                "imported memories can only be remapped through the instance that defines them"
+            ),
+        }
+        let memory = instance.get_memory(index);
+        let page_size = region::page::size();
+        if offset % page_size as u64 != 0 {
+            anyhow::bail!(
+                "memory offset {:#x} is not a multiple of the page size",
+                offset
+            );
+        }
+        if len == 0 {
+            anyhow::bail!("cannot map an empty memory region");
+        }
+        match offset.checked_add(len) {
+            Some(end) if end <= memory.current_length as u64 => {}
+            _ => anyhow::bail!(
+                "memory region {:#x}..{:#x} is out of bounds of memory of length {:#x}",
+                offset,
+                offset.saturating_add(len),
+                memory.current_length
+            ),
+        }
+
+        // The current length is a multiple of the wasm page size, so rounding
+        // up to whole host pages stays within it.
+        let len = (len as usize + page_size - 1) & !(page_size - 1);
+        Ok((unsafe { memory.base.add(offset as usize) }, len))
+    }
+
     /// Return a reference to the contained `Instance`.
     #[inline]
     pub(crate) fn instance(&self) -> &Instance {
+        self.check_version();
         unsafe { &*(self.instance as *const Instance) }
     }
 
     pub(crate) fn instance_mut(&mut self) -> &mut Instance {
+        self.check_version();
         unsafe { &mut *self.instance }
     }
 
+    /// Panics if this handle's instance slot has been deallocated since the
+    /// handle was allocated.
+    #[inline]
+    pub(crate) fn check_version(&self) {
+        if let Some(SlotVersion { counter, version }) = self.version {
+            let current = unsafe { (*counter).load(Ordering::Acquire) };
+            assert_eq!(
+                current, version,
+                "instance handle used after its slot was deallocated"
+            );
+        }
+    }
+
     /// Returns the `Store` pointer that was stored on creation
     #[inline]
     pub fn store(&self) -> *mut dyn Store {
@@ -923,6 +1583,7 @@ impl InstanceHandle {
     pub unsafe fn clone(&self) -> InstanceHandle {
         InstanceHandle {
             instance: self.instance,
+            version: self.version,
         }
     }
 }
@@ -13,13 +13,16 @@ use std::alloc;
 use std::any::Any;
 use std::convert::TryFrom;
 use std::marker;
+use std::ops::Range;
 use std::ptr::{self, NonNull};
 use std::slice;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use wasmtime_environ::entity::{EntityRef, EntitySet, PrimaryMap};
+use wasmtime_environ::entity::{bulk_write_vmctx, EntitySet, PrimaryMap};
 use wasmtime_environ::wasm::{
-    DefinedFuncIndex, DefinedMemoryIndex, DefinedTableIndex, GlobalInit, SignatureIndex, WasmType,
+    DefinedFuncIndex, DefinedMemoryIndex, DefinedTableIndex, GlobalInit, SignatureIndex,
+    WasmFuncType, WasmType,
 };
 use wasmtime_environ::{
     ir, HostPtr, MemoryInitialization, MemoryInitializer, Module, ModuleType, TableInitializer,
@@ -29,7 +32,9 @@ use wasmtime_environ::{
 mod pooling;
 
 pub use self::pooling::{
-    InstanceLimits, ModuleLimits, PoolingAllocationStrategy, PoolingInstanceAllocator,
+    GcPressureCallback, InstanceLimits, LimitViolation, ModuleLimit, ModuleLimits, ModuleSlab,
+    ModuleValidationReport, PoolingAllocationStrategy, PoolingInstanceAllocator, PoolingStats,
+    ShutdownTimeout, SlotStats,
 };
 
 /// Represents a request for a new runtime instance.
@@ -64,12 +69,112 @@ pub struct InstanceAllocationRequest<'a> {
     /// We use a number of `PhantomPinned` declarations to indicate this to the
     /// compiler. More info on this in `wasmtime/src/store.rs`
     pub store: Option<*mut dyn Store>,
+
+    /// The number of additional host pages of guard region to place after
+    /// each linear memory defined by the instance, on top of the offset guard
+    /// chosen by the module's tunables.
+    pub(crate) extra_memory_guard_pages: u32,
+}
+
+impl<'a> InstanceAllocationRequest<'a> {
+    /// Creates a new request to allocate an instance of `module`.
+    ///
+    /// No extra memory guard pages are requested; see
+    /// [`InstanceAllocationRequest::with_extra_memory_guard_pages`].
+    pub fn new(
+        module: Arc<Module>,
+        finished_functions: &'a PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        imports: Imports<'a>,
+        shared_signatures: SharedSignatures<'a>,
+        host_state: Box<dyn Any + Send + Sync>,
+        store: Option<*mut dyn Store>,
+    ) -> Self {
+        Self {
+            module,
+            finished_functions,
+            imports,
+            shared_signatures,
+            host_state,
+            store,
+            extra_memory_guard_pages: 0,
+        }
+    }
+
+    /// Places `count` additional host pages of inaccessible guard region
+    /// after each linear memory defined by the instance.
+    ///
+    /// This overrides the guard size from the module's `Tunables` for this
+    /// allocation only, which allows modules with different guard
+    /// requirements to share a process. Allocators that can't honor the
+    /// request fail the allocation.
+    pub fn with_extra_memory_guard_pages(mut self, count: u32) -> Self {
+        self.extra_memory_guard_pages = count;
+        self
+    }
+
+    /// Creates a request for `module` without imports, host state or store,
+    /// which is all most tests need.
+    #[cfg(test)]
+    pub(crate) fn for_test(
+        module: Arc<Module>,
+        finished_functions: &'a PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+    ) -> Self {
+        Self::new(
+            module,
+            finished_functions,
+            Imports::default(),
+            VMSharedSignatureIndex::default().into(),
+            Box::new(()),
+            None,
+        )
+    }
 }
 
 /// An link error while instantiating a module.
 #[derive(Error, Debug)]
-#[error("Link error: {0}")]
-pub struct LinkError(pub String);
+pub enum LinkError {
+    /// An imported function's type doesn't match the type of the import.
+    #[error(
+        "Link error: function types incompatible for import {}: expected {}, found {}",
+        import_display(import_module, import_name),
+        func_type_display(expected),
+        func_type_display(actual)
+    )]
+    SignatureMismatch {
+        /// The module name of the import.
+        import_module: String,
+        /// The field name of the import, or an empty string if the import
+        /// has no field name.
+        import_name: String,
+        /// The type the import was declared with.
+        expected: WasmFuncType,
+        /// The type of the function that was provided.
+        actual: WasmFuncType,
+    },
+
+    /// Any other link error, described by a message.
+    #[error("Link error: {0}")]
+    Other(String),
+}
+
+fn import_display(module: &str, name: &str) -> String {
+    if name.is_empty() {
+        format!("`{}`", module)
+    } else {
+        format!("`{}::{}`", module, name)
+    }
+}
+
+fn func_type_display(ty: &WasmFuncType) -> String {
+    let list = |types: &[WasmType]| {
+        types
+            .iter()
+            .map(|ty| format!("{:?}", ty).to_lowercase())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!("({}) -> ({})", list(&ty.params), list(&ty.returns))
+}
 
 /// An error while instantiating a module.
 #[derive(Error, Debug)]
@@ -89,6 +194,12 @@ pub enum InstantiationError {
     /// A limit on how many instances are supported has been reached.
     #[error("Limit of {0} concurrent instances has been reached")]
     Limit(u32),
+
+    /// An instance slot was claimed by more than one allocation at once.
+    ///
+    /// This indicates a bug in slot assignment rather than a resource problem.
+    #[error("Instance slot {0} was concurrently allocated")]
+    Concurrent(u32),
 }
 
 /// An error while creating a fiber stack.
@@ -125,6 +236,18 @@ pub unsafe trait InstanceAllocator: Send + Sync {
         drop(tunables);
     }
 
+    /// Returns whether allocating an instance for the given request is
+    /// expected to succeed, without allocating anything.
+    ///
+    /// This is a dry run of `allocate` intended for applying back-pressure
+    /// before attempting an allocation; as other threads may allocate or
+    /// deallocate instances concurrently, the answer may be out of date by the
+    /// time `allocate` is called.
+    fn can_allocate(&self, req: &InstanceAllocationRequest) -> bool {
+        drop(req);
+        true
+    }
+
     /// Allocates an instance for the given allocation request.
     ///
     /// # Safety
@@ -158,6 +281,30 @@ pub unsafe trait InstanceAllocator: Send + Sync {
     /// Use extreme care when deallocating an instance so that there are no dangling instance pointers.
     unsafe fn deallocate(&self, handle: &InstanceHandle);
 
+    /// Returns the number of bytes of linear memory currently in use by the
+    /// memories defined by the given instance.
+    ///
+    /// Imported memories aren't included; they're accounted to the instance
+    /// that defines them.
+    fn memory_bytes_used(&self, handle: &InstanceHandle) -> u64 {
+        handle
+            .instance()
+            .memories
+            .values()
+            .map(|memory| memory.byte_size() as u64)
+            .sum()
+    }
+
+    /// Stops allocating instances and waits up to `timeout` for all live
+    /// instances to be deallocated.
+    ///
+    /// Allocators that don't keep track of their instances have nothing to
+    /// wait for, so the default implementation returns immediately.
+    fn drain_and_shutdown(&self, timeout: Duration) -> Result<(), ShutdownTimeout> {
+        drop(timeout);
+        Ok(())
+    }
+
     /// Allocates a fiber stack for calling async functions on.
     #[cfg(feature = "async")]
     fn allocate_fiber_stack(&self) -> Result<wasmtime_fiber::FiberStack, FiberStackError>;
@@ -226,7 +373,7 @@ fn get_table_init_start(
             };
 
             init.offset.checked_add(val).ok_or_else(|| {
-                InstantiationError::Link(LinkError(
+                InstantiationError::Link(LinkError::Other(
                     "element segment global base overflows".to_owned(),
                 ))
             })
@@ -250,7 +397,7 @@ fn check_table_init_bounds(
                 // Initializer is in bounds
             }
             _ => {
-                return Err(InstantiationError::Link(LinkError(
+                return Err(InstantiationError::Link(LinkError::Other(
                     "table out of bounds: elements segment does not fit".to_owned(),
                 )))
             }
@@ -299,7 +446,9 @@ fn get_memory_init_start(
             };
 
             init.offset.checked_add(val).ok_or_else(|| {
-                InstantiationError::Link(LinkError("data segment global base overflows".to_owned()))
+                InstantiationError::Link(LinkError::Other(
+                    "data segment global base overflows".to_owned(),
+                ))
             })
         }
         None => Ok(init.offset),
@@ -310,7 +459,10 @@ fn check_memory_init_bounds(
     instance: &Instance,
     initializers: &[MemoryInitializer],
 ) -> Result<(), InstantiationError> {
-    for init in initializers {
+    // Stop at the first segment that doesn't fit so the error always refers to
+    // the earliest failing segment, matching the order in which the segments
+    // would have been applied.
+    for (index, init) in initializers.iter().enumerate() {
         let memory = instance.get_memory(init.memory_index);
         let start = get_memory_init_start(init, instance)?;
         let end = usize::try_from(start)
@@ -322,9 +474,10 @@ fn check_memory_init_bounds(
                 // Initializer is in bounds
             }
             _ => {
-                return Err(InstantiationError::Link(LinkError(
-                    "memory out of bounds: data segment does not fit".into(),
-                )))
+                return Err(InstantiationError::Link(LinkError::Other(format!(
+                    "memory out of bounds: data segment does not fit (segment {})",
+                    index
+                ))))
             }
         }
     }
@@ -357,7 +510,7 @@ fn check_init_bounds(instance: &mut Instance, module: &Module) -> Result<(), Ins
     match &instance.module.memory_initialization {
         MemoryInitialization::Paged { out_of_bounds, .. } => {
             if *out_of_bounds {
-                return Err(InstantiationError::Link(LinkError(
+                return Err(InstantiationError::Link(LinkError::Other(
                     "memory out of bounds: data segment does not fit".into(),
                 )));
             }
@@ -427,17 +580,19 @@ unsafe fn initialize_vmcontext(instance: &mut Instance, req: InstanceAllocationR
         instance.set_store(store);
     }
 
+    instance.code_range = code_range(req.finished_functions);
+
     let module = &instance.module;
 
     // Initialize shared signatures
-    let mut ptr = instance.vmctx_plus_offset(instance.offsets.vmctx_signature_ids_begin());
-    for sig in module.types.values() {
-        *ptr = match sig {
+    bulk_write_vmctx(
+        instance.vmctx_plus_offset(instance.offsets.vmctx_signature_ids_begin()),
+        instance.offsets.num_signature_ids,
+        module.types.values().map(|sig| match sig {
             ModuleType::Function(sig) => req.shared_signatures.lookup(*sig),
             _ => VMSharedSignatureIndex::new(u32::max_value()),
-        };
-        ptr = ptr.add(1);
-    }
+        }),
+    );
 
     // Initialize the built-in functions
     ptr::write(
@@ -472,52 +627,66 @@ unsafe fn initialize_vmcontext(instance: &mut Instance, req: InstanceAllocationR
     );
 
     // Initialize the functions
-    let mut base = instance.anyfunc_base();
-    for (index, sig) in instance.module.functions.iter() {
-        let type_index = req.shared_signatures.lookup(*sig);
-
-        let (func_ptr, vmctx) = if let Some(def_index) = instance.module.defined_func_index(index) {
-            (
-                NonNull::new(req.finished_functions[def_index] as *mut _).unwrap(),
-                instance.vmctx_ptr(),
-            )
-        } else {
-            let import = instance.imported_function(index);
-            (import.body, import.vmctx)
-        };
+    bulk_write_vmctx(
+        instance.anyfunc_base(),
+        instance.offsets.num_imported_functions + instance.offsets.num_defined_functions,
+        instance.module.functions.iter().map(|(index, sig)| {
+            let type_index = req.shared_signatures.lookup(*sig);
+
+            let (func_ptr, vmctx) =
+                if let Some(def_index) = instance.module.defined_func_index(index) {
+                    (
+                        NonNull::new(req.finished_functions[def_index] as *mut _).unwrap(),
+                        instance.vmctx_ptr(),
+                    )
+                } else {
+                    let import = instance.imported_function(index);
+                    (import.body, import.vmctx)
+                };
 
-        ptr::write(
-            base,
             VMCallerCheckedAnyfunc {
                 func_ptr,
                 type_index,
                 vmctx,
-            },
-        );
-        base = base.add(1);
-    }
+            }
+        }),
+    );
 
     // Initialize the defined tables
-    let mut ptr = instance.vmctx_plus_offset(instance.offsets.vmctx_tables_begin());
-    for i in 0..module.table_plans.len() - module.num_imported_tables {
-        ptr::write(ptr, instance.tables[DefinedTableIndex::new(i)].vmtable());
-        ptr = ptr.add(1);
-    }
+    bulk_write_vmctx(
+        instance.vmctx_plus_offset(instance.offsets.vmctx_tables_begin()),
+        instance.offsets.num_defined_tables,
+        instance.tables.values().map(|table| table.vmtable()),
+    );
 
     // Initialize the defined memories
-    let mut ptr = instance.vmctx_plus_offset(instance.offsets.vmctx_memories_begin());
-    for i in 0..module.memory_plans.len() - module.num_imported_memories {
-        ptr::write(
-            ptr,
-            instance.memories[DefinedMemoryIndex::new(i)].vmmemory(),
-        );
-        ptr = ptr.add(1);
-    }
+    bulk_write_vmctx(
+        instance.vmctx_plus_offset(instance.offsets.vmctx_memories_begin()),
+        instance.offsets.num_defined_memories,
+        instance.memories.values().map(|memory| memory.vmmemory()),
+    );
 
     // Initialize the defined globals
     initialize_vmcontext_globals(instance);
 }
 
+/// Returns the range of addresses spanned by `functions`, which is empty if
+/// there are none.
+fn code_range(functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>) -> Range<usize> {
+    functions
+        .values()
+        .map(|body| {
+            let body: &[VMFunctionBody] = unsafe { &**body };
+            let start = body.as_ptr() as usize;
+            start..start + body.len()
+        })
+        .fold(None, |range: Option<Range<usize>>, body| match range {
+            Some(range) => Some(range.start.min(body.start)..range.end.max(body.end)),
+            None => Some(body),
+        })
+        .unwrap_or(0..0)
+}
+
 unsafe fn initialize_vmcontext_globals(instance: &Instance) {
     let module = &instance.module;
     let num_imports = module.num_imported_globals;
@@ -563,11 +732,29 @@ unsafe fn initialize_vmcontext_globals(instance: &Instance) {
     }
 }
 
+/// Spells the `!` type, which can't be named as a closure's return type on
+/// stable Rust yet, through the return type of a function pointer.
+mod never {
+    pub type Never = <fn() -> ! as FnReturn>::Output;
+
+    pub trait FnReturn {
+        type Output;
+    }
+
+    impl<T> FnReturn for fn() -> T {
+        type Output = T;
+    }
+}
+
+use self::never::Never;
+
 /// Represents the on-demand instance allocator.
 #[derive(Clone)]
 pub struct OnDemandInstanceAllocator {
     mem_creator: Option<Arc<dyn RuntimeMemoryCreator>>,
     stack_size: usize,
+    oom_handler: Option<Arc<dyn Fn(alloc::Layout) -> Never + Send + Sync>>,
+    pre_init_hook: Option<Arc<dyn Fn(&InstanceHandle) -> Result<()> + Send + Sync>>,
 }
 
 // rustc is quite strict with the lifetimes when dealing with mutable borrows,
@@ -582,14 +769,66 @@ fn borrow_limiter<'a>(
 }
 
 impl OnDemandInstanceAllocator {
+    /// Returns a builder for an on-demand instance allocator, starting from
+    /// the default configuration.
+    pub fn builder() -> OnDemandInstanceAllocatorBuilder {
+        OnDemandInstanceAllocatorBuilder::default()
+    }
+
     /// Creates a new on-demand instance allocator.
     pub fn new(mem_creator: Option<Arc<dyn RuntimeMemoryCreator>>, stack_size: usize) -> Self {
         Self {
             mem_creator,
             stack_size,
+            oom_handler: None,
+            pre_init_hook: None,
         }
     }
 
+    /// Sets the creator used for the linear memories of allocated instances,
+    /// instead of allocating them with `mmap` directly.
+    pub fn with_mem_creator(mut self, mem_creator: Arc<dyn RuntimeMemoryCreator>) -> Self {
+        self.mem_creator = Some(mem_creator);
+        self
+    }
+
+    /// Sets the size of the fiber stacks allocated for async execution.
+    ///
+    /// A size of zero, the default, means fiber stacks aren't supported.
+    pub fn with_stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// Replaces the handler invoked when allocating an instance fails, which
+    /// by default is `std::alloc::handle_alloc_error`.
+    ///
+    /// The handler must not return; embedders which want to recover from an
+    /// out-of-memory condition can unwind out of it instead, for example with
+    /// `std::panic::resume_unwind`.
+    pub fn with_oom_handler(
+        mut self,
+        handler: Box<dyn Fn(alloc::Layout) -> Never + Send + Sync>,
+    ) -> Self {
+        self.oom_handler = Some(Arc::from(handler));
+        self
+    }
+
+    /// Sets a hook which is invoked with each newly allocated instance before
+    /// it is initialized, and therefore before any of its wasm code runs.
+    ///
+    /// The instance's vmcontext has already been initialized when the hook is
+    /// called, making this the place to install security policies such as
+    /// seccomp filters. If the hook returns an error the instantiation fails
+    /// with `InstantiationError::Resource`.
+    pub fn with_pre_init_hook(
+        mut self,
+        hook: Box<dyn Fn(&InstanceHandle) -> Result<()> + Send + Sync>,
+    ) -> Self {
+        self.pre_init_hook = Some(Arc::from(hook));
+        self
+    }
+
     fn create_tables(
         module: &Module,
         mut limiter: Option<&mut dyn ResourceLimiter>,
@@ -610,22 +849,70 @@ impl OnDemandInstanceAllocator {
         &self,
         module: &Module,
         mut limiter: Option<&mut dyn ResourceLimiter>,
+        extra_guard_pages: u32,
     ) -> Result<PrimaryMap<DefinedMemoryIndex, Memory>, InstantiationError> {
         let creator = self
             .mem_creator
             .as_deref()
             .unwrap_or_else(|| &DefaultMemoryCreator);
+        let extra_guard_size = u64::from(extra_guard_pages) * region::page::size() as u64;
         let num_imports = module.num_imported_memories;
         let mut memories: PrimaryMap<DefinedMemoryIndex, _> =
             PrimaryMap::with_capacity(module.memory_plans.len() - num_imports);
         for plan in &module.memory_plans.values().as_slice()[num_imports..] {
+            // Growing the guard region past what the code was compiled for is
+            // always safe: bounds checks elided for the smaller guard still
+            // land in inaccessible memory.
+            let mut plan = plan.clone();
+            plan.offset_guard_size += extra_guard_size;
             memories.push(
-                Memory::new_dynamic(plan, creator, borrow_limiter(&mut limiter))
+                Memory::new_dynamic(&plan, creator, borrow_limiter(&mut limiter))
                     .map_err(InstantiationError::Resource)?,
             );
         }
         Ok(memories)
     }
+
+    unsafe fn alloc_instance(&self, layout: alloc::Layout) -> *mut Instance {
+        let ptr = alloc::alloc(layout) as *mut Instance;
+        if ptr.is_null() {
+            match &self.oom_handler {
+                Some(handler) => handler(layout),
+                None => alloc::handle_alloc_error(layout),
+            }
+        }
+        ptr
+    }
+}
+
+/// Builds an `OnDemandInstanceAllocator`, which leaves room for new options
+/// without changing `OnDemandInstanceAllocator::new`.
+#[derive(Clone, Default)]
+pub struct OnDemandInstanceAllocatorBuilder {
+    allocator: OnDemandInstanceAllocator,
+}
+
+impl OnDemandInstanceAllocatorBuilder {
+    /// Sets the creator used for the linear memories of allocated instances.
+    ///
+    /// See `OnDemandInstanceAllocator::with_mem_creator`.
+    pub fn mem_creator(&mut self, mem_creator: Arc<dyn RuntimeMemoryCreator>) -> &mut Self {
+        self.allocator.mem_creator = Some(mem_creator);
+        self
+    }
+
+    /// Sets the size of the fiber stacks allocated for async execution.
+    ///
+    /// See `OnDemandInstanceAllocator::with_stack_size`.
+    pub fn stack_size(&mut self, stack_size: usize) -> &mut Self {
+        self.allocator.stack_size = stack_size;
+        self
+    }
+
+    /// Builds the allocator.
+    pub fn build(&self) -> OnDemandInstanceAllocator {
+        self.allocator.clone()
+    }
 }
 
 impl Default for OnDemandInstanceAllocator {
@@ -633,6 +920,8 @@ impl Default for OnDemandInstanceAllocator {
         Self {
             mem_creator: None,
             stack_size: 0,
+            oom_handler: None,
+            pre_init_hook: None,
         }
     }
 }
@@ -643,33 +932,36 @@ unsafe impl InstanceAllocator for OnDemandInstanceAllocator {
         mut req: InstanceAllocationRequest,
     ) -> Result<InstanceHandle, InstantiationError> {
         let mut limiter = req.store.and_then(|s| (*s).limiter());
-        let memories = self.create_memories(&req.module, borrow_limiter(&mut limiter))?;
+        let memories = self.create_memories(
+            &req.module,
+            borrow_limiter(&mut limiter),
+            req.extra_memory_guard_pages,
+        )?;
         let tables = Self::create_tables(&req.module, borrow_limiter(&mut limiter))?;
 
         let host_state = std::mem::replace(&mut req.host_state, Box::new(()));
 
+        let offsets = VMOffsets::new(HostPtr, &req.module);
+        debug_assert_eq!(offsets.assert_valid(), Ok(()));
+
         let mut handle = {
             let instance = Instance {
                 module: req.module.clone(),
-                offsets: VMOffsets::new(HostPtr, &req.module),
+                offsets,
                 memories,
                 tables,
                 dropped_elements: EntitySet::with_capacity(req.module.passive_elements.len()),
                 dropped_data: EntitySet::with_capacity(req.module.passive_data.len()),
                 host_state,
+                code_range: 0..0,
                 vmctx: VMContext {
                     _marker: marker::PhantomPinned,
                 },
             };
             let layout = instance.alloc_layout();
-            let instance_ptr = alloc::alloc(layout) as *mut Instance;
-            if instance_ptr.is_null() {
-                alloc::handle_alloc_error(layout);
-            }
+            let instance_ptr = self.alloc_instance(layout);
             ptr::write(instance_ptr, instance);
-            InstanceHandle {
-                instance: instance_ptr,
-            }
+            InstanceHandle::new(instance_ptr, None)
         };
 
         initialize_vmcontext(handle.instance_mut(), req);
@@ -683,6 +975,9 @@ unsafe impl InstanceAllocator for OnDemandInstanceAllocator {
         module: &Module,
         is_bulk_memory: bool,
     ) -> Result<(), InstantiationError> {
+        if let Some(hook) = &self.pre_init_hook {
+            hook(handle).map_err(InstantiationError::Resource)?;
+        }
         initialize_instance(handle.instance_mut(), module, is_bulk_memory)
     }
 
@@ -707,3 +1002,656 @@ unsafe impl InstanceAllocator for OnDemandInstanceAllocator {
         // The on-demand allocator has no further bookkeeping for fiber stacks
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ops::{Deref, DerefMut};
+    use wasmtime_environ::entity::EntityRef;
+    use wasmtime_environ::wasm::{Memory, MemoryIndex};
+    use wasmtime_environ::{MemoryPlan, MemoryStyle};
+
+    /// Returns a module defining a single one-page memory. The memory plan is
+    /// static so that the module can be allocated by either allocator.
+    pub(super) fn one_page_memory_module() -> Arc<Module> {
+        let mut module = Module::default();
+        module.memory_plans.push(MemoryPlan {
+            style: MemoryStyle::Static { bound: 1 },
+            memory: Memory {
+                minimum: 1,
+                maximum: None,
+                shared: false,
+                memory64: false,
+            },
+            pre_guard_size: 0,
+            offset_guard_size: 0,
+        });
+        Arc::new(module)
+    }
+
+    /// An instance allocated for a test, which is deallocated when dropped.
+    struct TestInstance {
+        allocator: OnDemandInstanceAllocator,
+        handle: InstanceHandle,
+    }
+
+    impl TestInstance {
+        /// Allocates an instance of `module` with the default allocator.
+        fn new(module: Arc<Module>) -> Result<Self, InstantiationError> {
+            Self::allocate(
+                OnDemandInstanceAllocator::default(),
+                InstanceAllocationRequest::for_test(module, &PrimaryMap::new()),
+            )
+        }
+
+        /// Allocates an instance for `req` with `allocator`.
+        fn allocate(
+            allocator: OnDemandInstanceAllocator,
+            req: InstanceAllocationRequest,
+        ) -> Result<Self, InstantiationError> {
+            let handle = unsafe { allocator.allocate(req)? };
+            Ok(Self { allocator, handle })
+        }
+    }
+
+    impl Deref for TestInstance {
+        type Target = InstanceHandle;
+
+        fn deref(&self) -> &InstanceHandle {
+            &self.handle
+        }
+    }
+
+    impl DerefMut for TestInstance {
+        fn deref_mut(&mut self) -> &mut InstanceHandle {
+            &mut self.handle
+        }
+    }
+
+    impl Drop for TestInstance {
+        fn drop(&mut self) {
+            unsafe {
+                self.allocator.deallocate(&self.handle);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bulk_memory_init() -> Result<()> {
+        let mut handle = TestInstance::new(one_page_memory_module())?;
+
+        let index = MemoryIndex::new(0);
+        let page = u64::from(WASM_PAGE_SIZE);
+        assert!(handle.bulk_memory_init(index, 8, &[1, 2, 3]).is_ok());
+        assert!(handle.bulk_memory_init(index, page - 2, &[4, 5]).is_ok());
+        assert!(handle.bulk_memory_init(index, page, &[]).is_ok());
+        assert!(handle.bulk_memory_init(index, page - 1, &[6, 7]).is_err());
+        assert!(handle.bulk_memory_init(index, page + 1, &[]).is_err());
+
+        let memory = handle.instance().get_memory(index);
+        let contents = unsafe { slice::from_raw_parts(memory.base, memory.current_length) };
+        assert_eq!(&contents[7..12], &[0, 1, 2, 3, 0]);
+        assert_eq!(&contents[contents.len() - 2..], &[4, 5]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extra_memory_guard_pages() -> Result<()> {
+        use crate::memory::RuntimeLinearMemory;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct RecordingCreator(Mutex<Vec<u64>>);
+
+        impl RuntimeMemoryCreator for RecordingCreator {
+            fn new_memory(
+                &self,
+                plan: &MemoryPlan,
+                minimum: usize,
+                maximum: Option<usize>,
+            ) -> Result<Box<dyn RuntimeLinearMemory>> {
+                self.0.lock().unwrap().push(plan.offset_guard_size);
+                DefaultMemoryCreator.new_memory(plan, minimum, maximum)
+            }
+        }
+
+        let mut module = one_page_memory_module();
+        Arc::get_mut(&mut module).unwrap().memory_plans[MemoryIndex::new(0)].offset_guard_size =
+            0x1000;
+
+        let creator = Arc::new(RecordingCreator::default());
+        let _handle = TestInstance::allocate(
+            OnDemandInstanceAllocator::default().with_mem_creator(creator.clone()),
+            InstanceAllocationRequest::for_test(module, &PrimaryMap::new())
+                .with_extra_memory_guard_pages(3),
+        )?;
+
+        let page_size = region::page::size() as u64;
+        assert_eq!(*creator.0.lock().unwrap(), [0x1000 + 3 * page_size]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_memory() -> Result<()> {
+        let module = one_page_memory_module();
+        let plan = MemoryPlan {
+            style: MemoryStyle::Dynamic,
+            ..module.memory_plans[MemoryIndex::new(0)].clone()
+        };
+
+        let mut handle = TestInstance::new(module)?;
+
+        let index = MemoryIndex::new(0);
+        assert!(handle.bulk_memory_init(index, 0, &[1, 2, 3]).is_ok());
+
+        let mut new_memory =
+            crate::memory::Memory::new_dynamic(&plan, &DefaultMemoryCreator, None)?;
+        assert_eq!(
+            unsafe { new_memory.grow(1, None) },
+            Some(WASM_PAGE_SIZE as usize)
+        );
+        let old = unsafe { handle.replace_memory(index, new_memory) };
+        assert_eq!(old.byte_size(), WASM_PAGE_SIZE as usize);
+
+        let memory = handle.instance().get_memory(index);
+        assert_eq!(memory.current_length, 2 * WASM_PAGE_SIZE as usize);
+        let contents = unsafe { slice::from_raw_parts(memory.base, memory.current_length) };
+        assert_eq!(&contents[..3], &[0, 0, 0]);
+
+        let old_memory = old.vmmemory();
+        let old_contents = unsafe { slice::from_raw_parts(old_memory.base, 3) };
+        assert_eq!(old_contents, &[1, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_import() -> Result<()> {
+        use crate::export::ExportFunction;
+        use crate::vmcontext::VMFunctionImport;
+        use wasmtime_environ::wasm::{EntityIndex, FuncIndex};
+        use wasmtime_environ::Initializer;
+
+        let mut module = Module::default();
+        module.functions.push(SignatureIndex::new(0));
+        module.num_imported_funcs = 1;
+        module.initializers.push(Initializer::Import {
+            name: "m".to_string(),
+            field: Some("f".to_string()),
+            index: EntityIndex::Function(FuncIndex::new(0)),
+        });
+
+        // The functions are never called, so any non-null pointers will do.
+        let original = NonNull::<VMFunctionBody>::dangling();
+        let replacement = NonNull::new(8 as *mut VMFunctionBody).unwrap();
+        let type_index = VMSharedSignatureIndex::new(1);
+
+        let mut handle = TestInstance::allocate(
+            OnDemandInstanceAllocator::default(),
+            InstanceAllocationRequest {
+                imports: Imports {
+                    functions: &[VMFunctionImport {
+                        body: original,
+                        vmctx: ptr::null_mut(),
+                    }],
+                    ..Imports::default()
+                },
+                shared_signatures: type_index.into(),
+                ..InstanceAllocationRequest::for_test(Arc::new(module), &PrimaryMap::new())
+            },
+        )?;
+
+        let current = |handle: &InstanceHandle| {
+            let anyfunc = handle
+                .instance()
+                .get_caller_checked_anyfunc(FuncIndex::new(0))
+                .unwrap();
+            let import = handle.instance().imported_function(FuncIndex::new(0));
+            assert_eq!(anyfunc.func_ptr, import.body);
+            anyfunc.func_ptr
+        };
+
+        let mut wrong_type = VMCallerCheckedAnyfunc {
+            func_ptr: replacement,
+            type_index: VMSharedSignatureIndex::new(2),
+            vmctx: ptr::null_mut(),
+        };
+        let wrong_type = ExportFunction {
+            anyfunc: NonNull::from(&mut wrong_type),
+        };
+        unsafe {
+            assert!(handle.replace_import("m", "f", wrong_type).is_err());
+            assert!(handle.replace_import("m", "g", wrong_type).is_err());
+        }
+        assert_eq!(current(&handle), original);
+
+        let mut new = VMCallerCheckedAnyfunc {
+            func_ptr: replacement,
+            type_index,
+            vmctx: ptr::null_mut(),
+        };
+        let old = unsafe {
+            handle
+                .replace_import(
+                    "m",
+                    "f",
+                    ExportFunction {
+                        anyfunc: NonNull::from(&mut new),
+                    },
+                )
+                .unwrap()
+        };
+        assert_eq!(old.func_ptr, original);
+        assert_eq!(old.type_index, type_index);
+        assert_eq!(current(&handle), replacement);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memory_map_file() -> Result<()> {
+        use std::fs::File;
+        use std::io::{Read, Seek, SeekFrom, Write};
+        use std::os::unix::io::FromRawFd;
+
+        let mut handle = TestInstance::new(one_page_memory_module())?;
+
+        let page_size = region::page::size();
+        let mut file = unsafe {
+            let fd = libc::memfd_create(b"test\0".as_ptr() as *const libc::c_char, 0);
+            assert!(fd >= 0);
+            File::from_raw_fd(fd)
+        };
+        file.write_all(&vec![7; page_size])?;
+        file.write_all(&vec![9; page_size])?;
+
+        let index = MemoryIndex::new(0);
+        let len = page_size as u64;
+        let memory = handle.instance().get_memory(index);
+        let contents = || unsafe { slice::from_raw_parts_mut(memory.base, memory.current_length) };
+
+        unsafe {
+            assert!(handle.memory_map_file(index, 1, len, &file, 0).is_err());
+            assert!(handle
+                .memory_map_file(index, WASM_PAGE_SIZE as u64, len, &file, 0)
+                .is_err());
+            assert!(handle.memory_map_file(index, len, 0, &file, 0).is_err());
+            assert!(handle.memory_map_file(index, len, len, &file, 1).is_err());
+            handle.memory_map_file(index, 0, len, &file, len)?;
+            handle.memory_map_file(index, len, len, &file, 0)?;
+        }
+
+        let memory = contents();
+        assert_eq!(memory[0], 9);
+        assert_eq!(memory[page_size - 1], 9);
+        assert_eq!(memory[page_size], 7);
+        assert_eq!(memory[2 * page_size - 1], 7);
+        assert_eq!(memory[2 * page_size], 0);
+
+        // Writes through the memory go to the file.
+        memory[page_size] = 1;
+        let mut byte = [0];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut byte)?;
+        assert_eq!(byte, [1]);
+
+        unsafe {
+            handle.memory_unmap_region(index, len, len)?;
+            handle.memory_unmap_region(index, 0, len)?;
+        }
+        let memory = contents();
+        assert!(memory[page_size..2 * page_size].iter().all(|b| *b == 0));
+        memory[page_size] = 2;
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut byte)?;
+        assert_eq!(byte, [1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_global_extern_ref() -> Result<()> {
+        use crate::externref::VMExternRef;
+        use wasmtime_environ::wasm::{Global, GlobalIndex, GlobalInit, WasmType};
+
+        let mut module = Module::default();
+        module.globals.push(Global {
+            wasm_ty: WasmType::ExternRef,
+            mutability: true,
+            initializer: GlobalInit::RefNullConst,
+        });
+        module.globals.push(Global {
+            wasm_ty: WasmType::I32,
+            mutability: true,
+            initializer: GlobalInit::I32Const(0),
+        });
+
+        let handle = TestInstance::new(Arc::new(module))?;
+
+        assert!(handle.read_global_extern_ref(GlobalIndex::new(1)).is_err());
+
+        let index = GlobalIndex::new(0);
+        assert!(handle.read_global_extern_ref(index)?.is_none());
+
+        let global = handle.instance().defined_or_imported_global_ptr(index);
+        unsafe {
+            *(*global).as_externref_mut() = Some(VMExternRef::new(42_u32));
+        }
+        let value = handle.read_global_extern_ref(index)?.unwrap();
+        assert_eq!(value.strong_count(), 2);
+
+        // Overwriting the global leaves the returned reference intact.
+        unsafe {
+            *(*global).as_externref_mut() = None;
+        }
+        assert_eq!(value.strong_count(), 1);
+        assert_eq!(value.downcast_ref::<u32>(), Some(&42));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_global_raw() -> Result<()> {
+        use crate::externref::VMExternRef;
+        use crate::vmcontext::VMGlobalDefinition;
+        use wasmtime_environ::wasm::{Global, GlobalIndex, GlobalInit, WasmType};
+
+        let mut module = Module::default();
+        module.globals.push(Global {
+            wasm_ty: WasmType::I64,
+            mutability: true,
+            initializer: GlobalInit::I64Const(0),
+        });
+        module.globals.push(Global {
+            wasm_ty: WasmType::I32,
+            mutability: false,
+            initializer: GlobalInit::I32Const(0),
+        });
+        module.globals.push(Global {
+            wasm_ty: WasmType::ExternRef,
+            mutability: true,
+            initializer: GlobalInit::RefNullConst,
+        });
+
+        let mut handle = TestInstance::new(Arc::new(module))?;
+
+        unsafe {
+            let mut val = VMGlobalDefinition::new();
+            *val.as_i64_mut() = -7;
+            handle.write_global_raw(GlobalIndex::new(0), val)?;
+            let global = handle
+                .instance()
+                .defined_or_imported_global_ptr(GlobalIndex::new(0));
+            assert_eq!(*(*global).as_i64(), -7);
+
+            assert!(handle
+                .write_global_raw(GlobalIndex::new(1), VMGlobalDefinition::new())
+                .is_err());
+
+            let first = VMExternRef::new(1_u32);
+            let mut val = VMGlobalDefinition::new();
+            *val.as_externref_mut() = Some(first.clone());
+            handle.write_global_raw(GlobalIndex::new(2), val)?;
+            assert_eq!(first.strong_count(), 2);
+
+            // Overwriting the reference releases the previous one.
+            let mut val = VMGlobalDefinition::new();
+            *val.as_externref_mut() = Some(VMExternRef::new(2_u32));
+            handle.write_global_raw(GlobalIndex::new(2), val)?;
+            assert_eq!(first.strong_count(), 1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_debug_assert_invariants() -> Result<()> {
+        use crate::vmcontext::VMGlobalDefinition;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use wasmtime_environ::wasm::{Global, GlobalIndex, GlobalInit, WasmType};
+
+        let mut module = Module::default();
+        module.globals.push(Global {
+            wasm_ty: WasmType::I32,
+            mutability: true,
+            initializer: GlobalInit::I32Const(-1),
+        });
+
+        let mut handle = TestInstance::new(Arc::new(module))?;
+        handle.debug_assert_invariants();
+
+        // An i32 global with bits set past its first 4 bytes is invalid.
+        let mut val = VMGlobalDefinition::new();
+        unsafe {
+            *val.as_u64_mut() = 1 << 40;
+            handle.write_global_raw(GlobalIndex::new(0), val)?;
+        }
+        assert!(catch_unwind(AssertUnwindSafe(|| handle.debug_assert_invariants())).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_state_access() -> Result<()> {
+        let mut handle = TestInstance::allocate(
+            OnDemandInstanceAllocator::default(),
+            InstanceAllocationRequest {
+                host_state: Box::new(String::from("hello")),
+                ..InstanceAllocationRequest::for_test(
+                    Arc::new(Module::default()),
+                    &PrimaryMap::new(),
+                )
+            },
+        )?;
+
+        assert_eq!(
+            handle.clone_host_state::<String>(),
+            Some("hello".to_string())
+        );
+        assert_eq!(handle.clone_host_state::<u32>(), None);
+
+        let old = unsafe { handle.replace_host_state(7_u32) }.unwrap();
+        assert_eq!(
+            old.downcast_ref::<String>().map(|s| s.as_str()),
+            Some("hello")
+        );
+        assert_eq!(handle.clone_host_state::<u32>(), Some(7));
+        assert_eq!(handle.clone_host_state::<String>(), None);
+
+        let old = unsafe { handle.replace_host_state(()) }.unwrap();
+        assert_eq!(old.downcast_ref::<u32>(), Some(&7));
+        let old = unsafe { handle.replace_host_state(8_u32) };
+        assert!(old.is_none());
+        assert_eq!(handle.clone_host_state::<u32>(), Some(8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_exported_func_raw() -> Result<()> {
+        use crate::externref::{ModuleInfo, ModuleInfoLookup, VMExternRefActivationsTable};
+        use crate::traphandlers::raise_lib_trap;
+        use crate::vmcontext::VMInterrupts;
+        use std::sync::atomic::{AtomicPtr, Ordering::SeqCst};
+        use wasmtime_environ::ir::TrapCode;
+        use wasmtime_environ::wasm::{DefinedFuncIndex, EntityIndex, FuncIndex, SignatureIndex};
+
+        struct NoModules;
+
+        impl ModuleInfoLookup for NoModules {
+            fn lookup(&self, _pc: usize) -> Option<Arc<dyn ModuleInfo>> {
+                None
+            }
+        }
+
+        struct TestStore {
+            interrupts: Box<VMInterrupts>,
+            externrefs: VMExternRefActivationsTable,
+        }
+
+        unsafe impl Store for TestStore {
+            fn vminterrupts(&self) -> *mut VMInterrupts {
+                &*self.interrupts as *const _ as *mut _
+            }
+            fn externref_activations_table(
+                &mut self,
+            ) -> (&mut VMExternRefActivationsTable, &dyn ModuleInfoLookup) {
+                (&mut self.externrefs, &NoModules)
+            }
+            fn limiter(&mut self) -> Option<&mut dyn ResourceLimiter> {
+                None
+            }
+            fn out_of_gas(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+                Ok(())
+            }
+        }
+
+        // The store's interrupts, which `add_one` interrupts when asked to.
+        static INTERRUPTS: AtomicPtr<VMInterrupts> = AtomicPtr::new(ptr::null_mut());
+
+        // Stand-ins for a compiled function and its trampoline: the function
+        // adds one to its argument, traps if it's zero, and requests an
+        // interrupt if it's `u32::MAX`.
+        extern "C" fn add_one(x: u32) -> u32 {
+            match x {
+                0 => unsafe { raise_lib_trap(Trap::wasm(TrapCode::UnreachableCodeReached)) },
+                u32::MAX => unsafe { (*INTERRUPTS.load(SeqCst)).interrupt() },
+                _ => {}
+            }
+            x.wrapping_add(1)
+        }
+        unsafe extern "C" fn trampoline(
+            _callee: *mut VMContext,
+            _caller: *mut VMContext,
+            func: *const VMFunctionBody,
+            values: *mut u128,
+        ) {
+            let func: extern "C" fn(u32) -> u32 = std::mem::transmute(func);
+            *values = u128::from(func(*values as u32));
+        }
+
+        let mut module = Module::default();
+        module.functions.push(SignatureIndex::new(0));
+        module
+            .exports
+            .insert("f".to_string(), EntityIndex::Function(FuncIndex::new(0)));
+
+        let mut functions = PrimaryMap::<DefinedFuncIndex, _>::new();
+        functions.push(std::ptr::slice_from_raw_parts_mut(
+            add_one as *mut VMFunctionBody,
+            0,
+        ));
+
+        let mut store = TestStore {
+            interrupts: Box::new(VMInterrupts::default()),
+            externrefs: VMExternRefActivationsTable::new(),
+        };
+        INTERRUPTS.store(&mut *store.interrupts, SeqCst);
+        let mut handle = TestInstance::allocate(
+            OnDemandInstanceAllocator::default(),
+            InstanceAllocationRequest {
+                store: Some(&mut store as &mut dyn Store as *mut _),
+                ..InstanceAllocationRequest::for_test(Arc::new(module), &functions)
+            },
+        )?;
+
+        let mut result = 0;
+        let call = |handle: &mut InstanceHandle, arg: u128, result: &mut u128| unsafe {
+            handle.call_exported_func_raw("f", trampoline, &arg, 1, result, 1)
+        };
+        assert!(call(&mut handle, 41, &mut result).is_ok());
+        assert_eq!(result, 42);
+
+        match call(&mut handle, 0, &mut result) {
+            Err(Trap::Wasm { trap_code, .. }) => {
+                assert_eq!(trap_code, TrapCode::UnreachableCodeReached)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(result, 42);
+
+        // The stack limit is only set for the duration of the call.
+        assert_eq!(
+            store.interrupts.stack_limit.load(SeqCst),
+            usize::max_value()
+        );
+
+        // An interrupt requested during the call isn't lost when the stack
+        // limit is reset.
+        assert!(call(&mut handle, u128::from(u32::MAX), &mut result).is_ok());
+        assert_eq!(
+            store.interrupts.stack_limit.load(SeqCst),
+            wasmtime_environ::INTERRUPTED
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_demand_builder() {
+        let mut builder = OnDemandInstanceAllocator::builder();
+        builder.stack_size(4096);
+        let allocator = builder.build();
+        assert_eq!(allocator.stack_size, 4096);
+        assert!(allocator.mem_creator.is_none());
+
+        builder.mem_creator(Arc::new(DefaultMemoryCreator));
+        assert!(builder.build().mem_creator.is_some());
+    }
+
+    #[test]
+    fn test_oom_handler() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let allocator = OnDemandInstanceAllocator::default()
+            .with_oom_handler(Box::new(|layout| panic::resume_unwind(Box::new(layout))));
+
+        // No allocator can satisfy this, so the handler must run.
+        let layout = alloc::Layout::from_size_align(isize::MAX as usize - 15, 16).unwrap();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            allocator.alloc_instance(layout)
+        }))
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<alloc::Layout>(), Some(&layout));
+    }
+
+    #[test]
+    fn test_pre_init_hook() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hook_calls = calls.clone();
+        let allocator =
+            OnDemandInstanceAllocator::default().with_pre_init_hook(Box::new(move |_handle| {
+                if hook_calls.fetch_add(1, Ordering::SeqCst) > 0 {
+                    anyhow::bail!("policy violation");
+                }
+                Ok(())
+            }));
+
+        let module = Arc::new(Module::default());
+        for expect_ok in [true, false].iter() {
+            let mut instance = TestInstance::allocate(
+                allocator.clone(),
+                InstanceAllocationRequest::for_test(module.clone(), &PrimaryMap::new()),
+            )?;
+
+            let TestInstance { allocator, handle } = &mut instance;
+            match unsafe { allocator.initialize(handle, &module, false) } {
+                Ok(()) => assert!(expect_ok),
+                Err(InstantiationError::Resource(e)) => {
+                    assert!(!expect_ok);
+                    assert_eq!(e.to_string(), "policy violation");
+                }
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+}
@@ -12,13 +12,29 @@ use super::{
     initialize_instance, initialize_vmcontext, InstanceAllocationRequest, InstanceAllocator,
     InstanceHandle, InstantiationError, ResourceLimiter,
 };
-use crate::{instance::Instance, Memory, Mmap, Table, VMContext};
+use crate::instance::{Instance, SlotVersion};
+use crate::{Memory, Mmap, Table, VMContext};
 use anyhow::{anyhow, bail, Context, Result};
 use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::convert::TryFrom;
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::io::{self, Write};
 use std::marker;
 use std::mem;
-use std::sync::{Arc, Mutex};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{self, AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+#[cfg(target_os = "linux")]
+use wasmtime_environ::{entity::EntityRef, wasm::MemoryIndex};
 use wasmtime_environ::{
     entity::{EntitySet, PrimaryMap},
     HostPtr, MemoryStyle, Module, Tunables, VMOffsets, VMOffsetsFields, WASM_PAGE_SIZE,
@@ -49,6 +65,32 @@ use imp::{commit_stack_pages, decommit_stack_pages};
 #[cfg(feature = "async")]
 use super::FiberStackError;
 
+/// Faults in the given linear memory pages, leaving them resident but
+/// inaccessible as they are after a decommit.
+#[cfg(not(all(feature = "uffd", target_os = "linux")))]
+fn pretouch_memory_pages(addr: *mut u8, len: usize) -> Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+
+    commit_memory_pages(addr, len)?;
+    for offset in (0..len).step_by(region::page::size()) {
+        unsafe { addr.add(offset).write_volatile(0) };
+    }
+
+    unsafe {
+        region::protect(addr, len, region::Protection::NONE)
+            .context("failed to protect memory pages")
+    }
+}
+
+#[cfg(all(feature = "uffd", target_os = "linux"))]
+fn pretouch_memory_pages(_addr: *mut u8, _len: usize) -> Result<()> {
+    // A no-op as memory pages must be populated by the fault handler so that
+    // they are initialized for the instance accessing them
+    Ok(())
+}
+
 fn round_up_to_pow2(n: usize, to: usize) -> usize {
     debug_assert!(to > 0);
     debug_assert!(to.is_power_of_two());
@@ -94,118 +136,224 @@ pub struct ModuleLimits {
 
 impl ModuleLimits {
     fn validate(&self, module: &Module) -> Result<()> {
-        if module.num_imported_funcs > self.imported_functions as usize {
-            bail!(
-                "imported function count of {} exceeds the limit of {}",
-                module.num_imported_funcs,
-                self.imported_functions
-            );
+        if let Some(violation) = self.validate_module(module).violations.into_iter().next() {
+            bail!("{}", violation);
         }
 
-        if module.num_imported_tables > self.imported_tables as usize {
-            bail!(
-                "imported tables count of {} exceeds the limit of {}",
-                module.num_imported_tables,
-                self.imported_tables
-            );
+        for (i, plan) in module.memory_plans.values().as_slice()[module.num_imported_memories..]
+            .iter()
+            .enumerate()
+        {
+            if let MemoryStyle::Dynamic = plan.style {
+                bail!(
+                    "memory index {} has an unsupported dynamic memory plan style",
+                    i,
+                );
+            }
         }
 
-        if module.num_imported_memories > self.imported_memories as usize {
-            bail!(
-                "imported memories count of {} exceeds the limit of {}",
-                module.num_imported_memories,
-                self.imported_memories
-            );
-        }
+        Ok(())
+    }
 
-        if module.num_imported_globals > self.imported_globals as usize {
-            bail!(
-                "imported globals count of {} exceeds the limit of {}",
-                module.num_imported_globals,
-                self.imported_globals
-            );
-        }
+    /// Checks `module` against every limit, reporting all of the limits it
+    /// exceeds rather than just the first one.
+    ///
+    /// The memory plan styles aren't checked here since they're determined
+    /// by the engine's configuration rather than by the module.
+    pub fn validate_module(&self, module: &Module) -> ModuleValidationReport {
+        let mut violations = Vec::new();
+        let mut check = |limit, index, allowed: u64, actual: u64| {
+            if actual > allowed {
+                violations.push(LimitViolation {
+                    limit,
+                    index,
+                    allowed,
+                    actual,
+                });
+            }
+        };
 
-        if module.types.len() > self.types as usize {
-            bail!(
-                "defined types count of {} exceeds the limit of {}",
-                module.types.len(),
-                self.types
-            );
-        }
+        check(
+            ModuleLimit::ImportedFunctions,
+            None,
+            self.imported_functions.into(),
+            module.num_imported_funcs as u64,
+        );
+        check(
+            ModuleLimit::ImportedTables,
+            None,
+            self.imported_tables.into(),
+            module.num_imported_tables as u64,
+        );
+        check(
+            ModuleLimit::ImportedMemories,
+            None,
+            self.imported_memories.into(),
+            module.num_imported_memories as u64,
+        );
+        check(
+            ModuleLimit::ImportedGlobals,
+            None,
+            self.imported_globals.into(),
+            module.num_imported_globals as u64,
+        );
+        check(
+            ModuleLimit::Types,
+            None,
+            self.types.into(),
+            module.types.len() as u64,
+        );
+        check(
+            ModuleLimit::Functions,
+            None,
+            self.functions.into(),
+            module
+                .functions
+                .len()
+                .saturating_sub(module.num_imported_funcs) as u64,
+        );
+        check(
+            ModuleLimit::Tables,
+            None,
+            self.tables.into(),
+            module
+                .table_plans
+                .len()
+                .saturating_sub(module.num_imported_tables) as u64,
+        );
+        check(
+            ModuleLimit::Memories,
+            None,
+            self.memories.into(),
+            module
+                .memory_plans
+                .len()
+                .saturating_sub(module.num_imported_memories) as u64,
+        );
+        check(
+            ModuleLimit::Globals,
+            None,
+            self.globals.into(),
+            module
+                .globals
+                .len()
+                .saturating_sub(module.num_imported_globals) as u64,
+        );
 
-        let functions = module.functions.len() - module.num_imported_funcs;
-        if functions > self.functions as usize {
-            bail!(
-                "defined functions count of {} exceeds the limit of {}",
-                functions,
-                self.functions
+        let tables = module.table_plans.values().skip(module.num_imported_tables);
+        for (i, plan) in tables.enumerate() {
+            check(
+                ModuleLimit::TableElements,
+                Some(i),
+                self.table_elements.into(),
+                plan.table.minimum.into(),
             );
         }
 
-        let tables = module.table_plans.len() - module.num_imported_tables;
-        if tables > self.tables as usize {
-            bail!(
-                "defined tables count of {} exceeds the limit of {}",
-                tables,
-                self.tables
+        let memories = module
+            .memory_plans
+            .values()
+            .skip(module.num_imported_memories);
+        for (i, plan) in memories.enumerate() {
+            check(
+                ModuleLimit::MemoryPages,
+                Some(i),
+                self.memory_pages,
+                plan.memory.minimum,
             );
         }
 
-        let memories = module.memory_plans.len() - module.num_imported_memories;
-        if memories > self.memories as usize {
-            bail!(
-                "defined memories count of {} exceeds the limit of {}",
-                memories,
-                self.memories
-            );
+        ModuleValidationReport {
+            passed: violations.is_empty(),
+            violations,
         }
+    }
+}
 
-        let globals = module.globals.len() - module.num_imported_globals;
-        if globals > self.globals as usize {
-            bail!(
-                "defined globals count of {} exceeds the limit of {}",
-                globals,
-                self.globals
-            );
-        }
+/// The result of checking a module against `ModuleLimits`, as returned by
+/// `ModuleLimits::validate_module`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleValidationReport {
+    /// Whether the module is within all of the limits.
+    pub passed: bool,
+    /// The limits exceeded by the module, in the order they were checked.
+    pub violations: Vec<LimitViolation>,
+}
 
-        for (i, plan) in module.table_plans.values().as_slice()[module.num_imported_tables..]
-            .iter()
-            .enumerate()
-        {
-            if plan.table.minimum > self.table_elements {
-                bail!(
+/// One of the limits in `ModuleLimits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleLimit {
+    /// `ModuleLimits::imported_functions`.
+    ImportedFunctions,
+    /// `ModuleLimits::imported_tables`.
+    ImportedTables,
+    /// `ModuleLimits::imported_memories`.
+    ImportedMemories,
+    /// `ModuleLimits::imported_globals`.
+    ImportedGlobals,
+    /// `ModuleLimits::types`.
+    Types,
+    /// `ModuleLimits::functions`.
+    Functions,
+    /// `ModuleLimits::tables`.
+    Tables,
+    /// `ModuleLimits::memories`.
+    Memories,
+    /// `ModuleLimits::globals`.
+    Globals,
+    /// `ModuleLimits::table_elements`.
+    TableElements,
+    /// `ModuleLimits::memory_pages`.
+    MemoryPages,
+}
+
+/// A single limit exceeded by a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitViolation {
+    /// The exceeded limit.
+    pub limit: ModuleLimit,
+    /// For the per-table and per-memory limits, the index of the offending
+    /// defined table or memory.
+    pub index: Option<usize>,
+    /// The value allowed by the limit.
+    pub allowed: u64,
+    /// The module's actual value.
+    pub actual: u64,
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let index = self.index.unwrap_or(0);
+        let what = match self.limit {
+            ModuleLimit::TableElements => {
+                return write!(
+                    f,
                     "table index {} has a minimum element size of {} which exceeds the limit of {}",
-                    i,
-                    plan.table.minimum,
-                    self.table_elements
-                );
+                    index, self.actual, self.allowed
+                )
             }
-        }
-
-        for (i, plan) in module.memory_plans.values().as_slice()[module.num_imported_memories..]
-            .iter()
-            .enumerate()
-        {
-            if plan.memory.minimum > self.memory_pages {
-                bail!(
+            ModuleLimit::MemoryPages => {
+                return write!(
+                    f,
                     "memory index {} has a minimum page size of {} which exceeds the limit of {}",
-                    i,
-                    plan.memory.minimum,
-                    self.memory_pages
-                );
-            }
-
-            if let MemoryStyle::Dynamic = plan.style {
-                bail!(
-                    "memory index {} has an unsupported dynamic memory plan style",
-                    i,
-                );
+                    index, self.actual, self.allowed
+                )
             }
-        }
-
-        Ok(())
+            ModuleLimit::ImportedFunctions => "imported function",
+            ModuleLimit::ImportedTables => "imported tables",
+            ModuleLimit::ImportedMemories => "imported memories",
+            ModuleLimit::ImportedGlobals => "imported globals",
+            ModuleLimit::Types => "defined types",
+            ModuleLimit::Functions => "defined functions",
+            ModuleLimit::Tables => "defined tables",
+            ModuleLimit::Memories => "defined memories",
+            ModuleLimit::Globals => "defined globals",
+        };
+        write!(
+            f,
+            "{} count of {} exceeds the limit of {}",
+            what, self.actual, self.allowed
+        )
     }
 }
 
@@ -242,6 +390,95 @@ impl Default for InstanceLimits {
     }
 }
 
+/// A class of modules that gets its own region of instance slots in the
+/// pooling instance allocator.
+///
+/// See `PoolingInstanceAllocator::with_module_slabs`.
+#[derive(Debug, Copy, Clone)]
+pub struct ModuleSlab {
+    /// The maximum number of pages for any linear memory defined in a module
+    /// placed in this slab.
+    pub max_memory_pages: u32,
+
+    /// The maximum table elements for any table defined in a module placed in
+    /// this slab.
+    pub max_table_elements: u32,
+
+    /// The maximum number of concurrent instances in this slab.
+    pub slot_count: u32,
+}
+
+/// Statistics about the instances allocated from a single instance slot.
+///
+/// See `PoolingInstanceAllocator::with_per_slot_statistics`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SlotStats {
+    /// The number of instances that have been allocated from this slot and
+    /// since deallocated.
+    pub allocated_count: u64,
+
+    /// The total time, in nanoseconds, that those instances were allocated.
+    pub total_uptime_ns: u64,
+
+    /// The largest combined size, in bytes, of the linear memories of any of
+    /// those instances when it was deallocated.
+    pub peak_memory_bytes: u64,
+}
+
+/// The statistics for a slot along with when its current instance, if any,
+/// was allocated.
+#[derive(Debug, Default)]
+struct SlotStatsState {
+    stats: SlotStats,
+    allocated_at: Option<Instant>,
+}
+
+/// Occupancy statistics of a `PoolingInstanceAllocator`.
+///
+/// See `PoolingInstanceAllocator::stats`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct PoolingStats {
+    /// The number of instances currently allocated, across the main pool and
+    /// all module slabs.
+    pub current_instances: u32,
+
+    /// The largest number of instances that were allocated at once.
+    pub peak_instances: u32,
+
+    /// The number of fiber stacks currently allocated.
+    pub current_fiber_stacks: u32,
+
+    /// The largest number of fiber stacks that were allocated at once.
+    pub peak_fiber_stacks: u32,
+}
+
+/// A current and peak count that can be read without locking.
+#[derive(Debug, Default)]
+struct Occupancy {
+    current: AtomicU32,
+    peak: AtomicU32,
+}
+
+impl Occupancy {
+    fn increment(&self) {
+        let current = self.current.fetch_add(1, Ordering::AcqRel) + 1;
+        self.peak.fetch_max(current, Ordering::AcqRel);
+    }
+
+    fn decrement(&self) {
+        self.current.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    fn current(&self) -> u32 {
+        self.current.load(Ordering::Acquire)
+    }
+
+    fn peak(&self) -> u32 {
+        self.peak.load(Ordering::Acquire)
+    }
+}
+
 /// The allocation strategy to use for the pooling instance allocator.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PoolingAllocationStrategy {
@@ -249,6 +486,23 @@ pub enum PoolingAllocationStrategy {
     NextAvailable,
     /// Allocate from a random available instance.
     Random,
+    /// Allocate from the free list of the current thread, stealing from the
+    /// free lists of other threads when it is empty.
+    ///
+    /// Instance slots are split into several free lists, each with its own
+    /// lock, and each thread prefers one of them. This avoids contention on a
+    /// single lock when many threads allocate instances at once. Other pooled
+    /// resources, such as fiber stacks, allocate as with `NextAvailable`.
+    WorkStealing,
+    /// Allocate from the pool whose slots fit the module most tightly.
+    ///
+    /// When module slabs are configured, every pool that can hold the module
+    /// is ranked by the space its slots would leave unused: the spare linear
+    /// memory pages and the spare room for the `VMContext`. The tightest pool
+    /// with a free slot is used, so a slab that is full spills over into the
+    /// next best one rather than failing. Without slabs there's only one
+    /// pool and this allocates as with `NextAvailable`.
+    BestFit,
 }
 
 impl PoolingAllocationStrategy {
@@ -256,12 +510,70 @@ impl PoolingAllocationStrategy {
         debug_assert!(free_count > 0);
 
         match self {
-            Self::NextAvailable => free_count - 1,
+            Self::NextAvailable | Self::WorkStealing | Self::BestFit => free_count - 1,
             Self::Random => rand::thread_rng().gen_range(0..free_count),
         }
     }
 }
 
+/// The maximum number of free lists used by the `WorkStealing` strategy.
+const MAX_WORK_STEALING_SHARDS: usize = 16;
+
+/// Free instance slots for the `WorkStealing` strategy, split into shards that
+/// each thread visits starting from its own "home" shard.
+#[derive(Debug)]
+struct WorkStealingSlots {
+    shards: Box<[Mutex<Vec<usize>>]>,
+}
+
+impl WorkStealingSlots {
+    fn new(slots: Vec<usize>) -> Self {
+        let count = slots.len().min(MAX_WORK_STEALING_SHARDS).max(1);
+        let mut shards = vec![Vec::new(); count];
+        for slot in slots {
+            shards[slot % count].push(slot);
+        }
+        Self {
+            shards: shards.into_iter().map(Mutex::new).collect(),
+        }
+    }
+
+    fn home(&self) -> usize {
+        static NEXT_HOME: AtomicUsize = AtomicUsize::new(0);
+        thread_local!(static HOME: usize = NEXT_HOME.fetch_add(1, Ordering::Relaxed));
+        HOME.with(|home| *home) % self.shards.len()
+    }
+
+    fn pop(&self) -> Option<usize> {
+        let home = self.home();
+        (0..self.shards.len())
+            .map(|i| (home + i) % self.shards.len())
+            .find_map(|i| self.shards[i].lock().unwrap().pop())
+    }
+
+    fn push(&self, slot: usize) {
+        self.shards[self.home()].lock().unwrap().push(slot);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards
+            .iter()
+            .all(|shard| shard.lock().unwrap().is_empty())
+    }
+
+    /// Returns up to `count` free slots, in the order this thread would pop
+    /// them.
+    fn peek(&self, count: usize) -> Vec<usize> {
+        let home = self.home();
+        let mut slots = Vec::with_capacity(count);
+        for i in 0..self.shards.len() {
+            let shard = self.shards[(home + i) % self.shards.len()].lock().unwrap();
+            slots.extend(shard.iter().rev().take(count - slots.len()));
+        }
+        slots
+    }
+}
+
 impl Default for PoolingAllocationStrategy {
     fn default() -> Self {
         Self::NextAvailable
@@ -283,9 +595,23 @@ struct InstancePool {
     instance_size: usize,
     max_instances: usize,
     free_list: Mutex<Vec<usize>>,
+    // Replaces `free_list` when the `WorkStealing` strategy is in use.
+    work_stealing: Option<WorkStealingSlots>,
+    // Whether or not each slot is currently claimed by an instance. Slots are
+    // claimed with a compare-and-swap so that a slot handed out twice is
+    // detected rather than silently shared.
+    in_use: Box<[AtomicBool]>,
+    // The version of each slot, bumped every time the slot is deallocated,
+    // when slot versions are being tracked.
+    versions: Option<Box<[AtomicU64]>>,
+    // Usage statistics for each slot, when they're being tracked.
+    stats: Option<Box<[Mutex<SlotStatsState>]>>,
     memories: MemoryPool,
     tables: TablePool,
     empty_module: Arc<Module>,
+    // Set once `release` has dropped the instances and released the pool's
+    // memory.
+    released: AtomicBool,
 }
 
 impl InstancePool {
@@ -331,9 +657,14 @@ impl InstancePool {
             instance_size,
             max_instances,
             free_list: Mutex::new((0..max_instances).collect()),
+            work_stealing: None,
+            in_use: (0..max_instances).map(|_| AtomicBool::new(false)).collect(),
+            versions: None,
+            stats: None,
             memories: MemoryPool::new(module_limits, instance_limits, tunables)?,
             tables: TablePool::new(module_limits, instance_limits)?,
             empty_module: Arc::new(Module::default()),
+            released: AtomicBool::new(false),
         };
 
         // Use a default module to initialize the instances to start
@@ -344,11 +675,109 @@ impl InstancePool {
         Ok(pool)
     }
 
+    /// Moves the free slots into per-thread free lists for the `WorkStealing`
+    /// strategy.
+    fn enable_work_stealing(&mut self) {
+        let slots = mem::take(&mut *self.free_list.get_mut().unwrap());
+        self.work_stealing = Some(WorkStealingSlots::new(slots));
+    }
+
+    /// Starts tracking a version for each slot.
+    fn enable_slot_versions(&mut self) {
+        self.versions = Some((0..self.max_instances).map(|_| AtomicU64::new(0)).collect());
+    }
+
+    /// Starts tracking usage statistics for each slot.
+    fn enable_slot_stats(&mut self) {
+        self.stats = Some(
+            (0..self.max_instances)
+                .map(|_| Mutex::new(SlotStatsState::default()))
+                .collect(),
+        );
+    }
+
+    fn slot_stats(&self) -> Vec<SlotStats> {
+        match &self.stats {
+            Some(stats) => stats.iter().map(|s| s.lock().unwrap().stats).collect(),
+            None => Vec::new(),
+        }
+    }
+
     unsafe fn instance(&self, index: usize) -> &mut Instance {
         debug_assert!(index < self.max_instances);
         &mut *(self.mapping.as_mut_ptr().add(index * self.instance_size) as *mut Instance)
     }
 
+    fn contains(&self, instance: *mut Instance) -> bool {
+        let addr = instance as usize;
+        let base = self.mapping.as_ptr() as usize;
+        addr >= base && addr < base + self.mapping.len()
+    }
+
+    fn has_free_slot(&self) -> bool {
+        match &self.work_stealing {
+            Some(slots) => !slots.is_empty(),
+            None => !self.free_list.lock().unwrap().is_empty(),
+        }
+    }
+
+    /// Returns up to `count` free slots, starting with the ones that will be
+    /// allocated next.
+    fn next_free_slots(&self, count: usize) -> Vec<usize> {
+        match &self.work_stealing {
+            Some(slots) => slots.peek(count),
+            None => {
+                let free_list = self.free_list.lock().unwrap();
+                free_list.iter().rev().take(count).copied().collect()
+            }
+        }
+    }
+
+    fn num_in_use(&self) -> usize {
+        self.in_use
+            .iter()
+            .filter(|in_use| in_use.load(Ordering::Acquire))
+            .count()
+    }
+
+    /// Drops every instance in the pool and releases the memory backing the
+    /// instances, linear memories and tables.
+    ///
+    /// The address space stays reserved until the pool is dropped, so any
+    /// stray access faults instead of reaching another mapping.
+    ///
+    /// # Safety
+    ///
+    /// No slot may be in use, and the pool mustn't be allocated from again.
+    unsafe fn release(&self) {
+        if self.released.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        for i in 0..self.max_instances {
+            std::ptr::drop_in_place(self.instance(i) as *mut Instance);
+        }
+
+        for mapping in &[&self.mapping, &self.memories.mapping, &self.tables.mapping] {
+            decommit_memory_pages(mapping.as_mut_ptr(), mapping.len()).unwrap();
+        }
+    }
+
+    fn interrupt_all(&self) {
+        for (index, in_use) in self.in_use.iter().enumerate() {
+            if !in_use.load(Ordering::Acquire) {
+                continue;
+            }
+
+            unsafe {
+                let interrupts = *self.instance(index).interrupts();
+                if !interrupts.is_null() {
+                    (*interrupts).interrupt();
+                }
+            }
+        }
+    }
+
     fn initialize(&self, limits: &ModuleLimits, index: usize) {
         unsafe {
             let instance = self.instance(index);
@@ -364,6 +793,7 @@ impl InstancePool {
                     dropped_elements: EntitySet::new(),
                     dropped_data: EntitySet::new(),
                     host_state: Box::new(()),
+                    code_range: 0..0,
                     vmctx: VMContext {
                         _marker: marker::PhantomPinned,
                     },
@@ -400,9 +830,15 @@ impl InstancePool {
 
         initialize_vmcontext(instance, req);
 
-        Ok(InstanceHandle {
-            instance: instance as _,
-        })
+        if let Some(stats) = &self.stats {
+            stats[index].lock().unwrap().allocated_at = Some(Instant::now());
+        }
+
+        let version = self.versions.as_ref().map(|versions| SlotVersion {
+            counter: &versions[index],
+            version: versions[index].load(Ordering::Acquire),
+        });
+        Ok(InstanceHandle::new(instance as _, version))
     }
 
     fn allocate(
@@ -410,28 +846,41 @@ impl InstancePool {
         strategy: PoolingAllocationStrategy,
         req: InstanceAllocationRequest,
     ) -> Result<InstanceHandle, InstantiationError> {
-        let index = {
-            let mut free_list = self.free_list.lock().unwrap();
-            if free_list.is_empty() {
-                return Err(InstantiationError::Limit(self.max_instances as u32));
+        let index = match &self.work_stealing {
+            Some(slots) => slots
+                .pop()
+                .ok_or(InstantiationError::Limit(self.max_instances as u32))?,
+            None => {
+                let mut free_list = self.free_list.lock().unwrap();
+                if free_list.is_empty() {
+                    return Err(InstantiationError::Limit(self.max_instances as u32));
+                }
+                let free_index = strategy.next(free_list.len());
+                free_list.swap_remove(free_index)
             }
-            let free_index = strategy.next(free_list.len());
-            free_list.swap_remove(free_index)
         };
 
+        if self.in_use[index]
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(InstantiationError::Concurrent(index as u32));
+        }
+
         unsafe {
             self.setup_instance(index, req).or_else(|e| {
                 // Deallocate the allocated instance on error
                 let instance = self.instance(index);
-                self.deallocate(&InstanceHandle {
-                    instance: instance as _,
-                });
+                self.deallocate(&InstanceHandle::new(instance as _, None));
                 Err(e)
             })
         }
     }
 
     fn deallocate(&self, handle: &InstanceHandle) {
+        // Catches deallocating the same handle twice.
+        handle.check_version();
+
         let addr = handle.instance as usize;
         let base = self.mapping.as_ptr() as usize;
 
@@ -443,6 +892,24 @@ impl InstancePool {
 
         let instance = unsafe { &mut *handle.instance };
 
+        if let Some(stats) = &self.stats {
+            let mut state = stats[index].lock().unwrap();
+            // Slots whose setup failed never held a live instance.
+            if let Some(allocated_at) = state.allocated_at.take() {
+                let uptime =
+                    u64::try_from(allocated_at.elapsed().as_nanos()).unwrap_or(u64::max_value());
+                let memory_bytes = instance
+                    .memories
+                    .values()
+                    .map(|memory| memory.byte_size() as u64)
+                    .sum();
+                let stats = &mut state.stats;
+                stats.allocated_count += 1;
+                stats.total_uptime_ns = stats.total_uptime_ns.saturating_add(uptime);
+                stats.peak_memory_bytes = stats.peak_memory_bytes.max(memory_bytes);
+            }
+        }
+
         // Decommit any linear memories that were used
         for (memory, base) in instance.memories.values_mut().zip(self.memories.get(index)) {
             let mut memory = mem::take(memory);
@@ -493,7 +960,17 @@ impl InstancePool {
         instance.module = self.empty_module.clone();
         instance.offsets = VMOffsets::new(HostPtr, &self.empty_module);
 
-        self.free_list.lock().unwrap().push(index);
+        if let Some(versions) = &self.versions {
+            versions[index].fetch_add(1, Ordering::AcqRel);
+        }
+
+        let was_in_use = self.in_use[index].swap(false, Ordering::AcqRel);
+        debug_assert!(was_in_use, "instance slot {} was not in use", index);
+
+        match &self.work_stealing {
+            Some(slots) => slots.push(index),
+            None => self.free_list.lock().unwrap().push(index),
+        }
     }
 
     fn set_instance_memories(
@@ -569,6 +1046,10 @@ impl InstancePool {
 
 impl Drop for InstancePool {
     fn drop(&mut self) {
+        if *self.released.get_mut() {
+            return;
+        }
+
         unsafe {
             for i in 0..self.max_instances {
                 let ptr = self.mapping.as_mut_ptr().add(i * self.instance_size) as *mut Instance;
@@ -671,6 +1152,10 @@ impl MemoryPool {
             })?;
 
         // Create a completely inaccessible region to start
+        #[cfg(windows)]
+        let mapping = imp::reserve_memory_pool(allocation_size)
+            .context("failed to create memory pool mapping")?;
+        #[cfg(not(windows))]
         let mapping = Mmap::accessible_reserved(0, allocation_size)
             .context("failed to create memory pool mapping")?;
 
@@ -896,6 +1381,76 @@ impl StackPool {
     }
 }
 
+/// An error returned by `PoolingInstanceAllocator::drain_and_shutdown` when
+/// instances are still allocated after the timeout.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("timed out waiting for {0} instance(s) to be deallocated")]
+pub struct ShutdownTimeout(pub usize);
+
+/// A callback invoked with the number of references held by a store's
+/// `VMExternRefActivationsTable` when it exceeds the configured threshold.
+///
+/// See `PoolingInstanceAllocator::with_gc_pressure_callback`.
+pub type GcPressureCallback = Box<dyn Fn(u32) + Send + Sync>;
+
+struct GcPressure {
+    threshold: u32,
+    callback: GcPressureCallback,
+}
+
+impl fmt::Debug for GcPressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcPressure")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+/// The instance pool of a `ModuleSlab`, along with the limits its modules are
+/// checked against.
+#[derive(Debug)]
+struct Slab {
+    limits: ModuleLimits,
+    // The size of the guard region after each linear memory reservation.
+    offset_guard_size: u64,
+    // This is manually drop for the same reason as the allocator's instance pool.
+    instances: mem::ManuallyDrop<InstancePool>,
+    #[cfg(all(feature = "uffd", target_os = "linux"))]
+    _fault_handler: imp::PageFaultHandler,
+}
+
+impl Drop for Slab {
+    fn drop(&mut self) {
+        unsafe {
+            mem::ManuallyDrop::drop(&mut self.instances);
+        }
+    }
+}
+
+impl Slab {
+    /// Returns whether instances of `module` can be allocated from this slab.
+    ///
+    /// Every memory defined by the module needs a maximum size, and must have
+    /// been compiled for a static bound and guard region that fit within the
+    /// slab's reservations. Compiled code elides bounds checks up to that
+    /// bound, so a larger one could access the next memory in the pool.
+    fn fits(&self, module: &Module) -> bool {
+        let memories_fit = module
+            .memory_plans
+            .values()
+            .skip(module.num_imported_memories)
+            .all(|plan| match (&plan.style, plan.memory.maximum) {
+                (MemoryStyle::Static { bound }, Some(maximum)) => {
+                    maximum <= self.limits.memory_pages
+                        && *bound <= self.limits.memory_pages
+                        && plan.offset_guard_size <= self.offset_guard_size
+                }
+                _ => false,
+            });
+        memories_fit && self.limits.validate(module).is_ok()
+    }
+}
+
 /// Implements the pooling instance allocator.
 ///
 /// This allocator internally maintains pools of instances, memories, tables, and stacks.
@@ -908,9 +1463,26 @@ pub struct PoolingInstanceAllocator {
     instance_limits: InstanceLimits,
     // This is manually drop so that the pools unmap their memory before the page fault handler drops.
     instances: mem::ManuallyDrop<InstancePool>,
+    // Additional pools for modules routed to a `ModuleSlab`, sorted by
+    // increasing memory page limit.
+    slabs: Vec<Slab>,
     #[cfg(all(feature = "async", unix))]
     stacks: StackPool,
     stack_size: usize,
+    gc_pressure: Option<GcPressure>,
+    // The thread started by `pre_touch_memories`, which allocations wait for.
+    warm_up: Mutex<Option<thread::JoinHandle<()>>>,
+    warm_up_pending: AtomicBool,
+    // Set by `drain_and_shutdown` to reject further allocations.
+    draining: AtomicBool,
+    // The number of calls to `allocate` in progress, which
+    // `drain_and_shutdown` waits for along with the allocated instances.
+    allocating: AtomicUsize,
+    // Notified whenever an instance is deallocated, so that
+    // `drain_and_shutdown` can wait for the pools to empty.
+    drained: (Mutex<()>, Condvar),
+    instance_occupancy: Occupancy,
+    fiber_stack_occupancy: Occupancy,
     #[cfg(all(feature = "uffd", target_os = "linux"))]
     _fault_handler: imp::PageFaultHandler,
 }
@@ -928,7 +1500,10 @@ impl PoolingInstanceAllocator {
             bail!("the instance count limit cannot be zero");
         }
 
-        let instances = InstancePool::new(&module_limits, &instance_limits, tunables)?;
+        let mut instances = InstancePool::new(&module_limits, &instance_limits, tunables)?;
+        if strategy == PoolingAllocationStrategy::WorkStealing {
+            instances.enable_work_stealing();
+        }
 
         #[cfg(all(feature = "uffd", target_os = "linux"))]
         let _fault_handler = imp::PageFaultHandler::new(&instances)?;
@@ -938,47 +1513,503 @@ impl PoolingInstanceAllocator {
             module_limits,
             instance_limits,
             instances: mem::ManuallyDrop::new(instances),
+            slabs: Vec::new(),
             #[cfg(all(feature = "async", unix))]
             stacks: StackPool::new(&instance_limits, stack_size)?,
             stack_size,
+            gc_pressure: None,
+            warm_up: Mutex::new(None),
+            warm_up_pending: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+            allocating: AtomicUsize::new(0),
+            drained: (Mutex::new(()), Condvar::new()),
+            instance_occupancy: Occupancy::default(),
+            fiber_stack_occupancy: Occupancy::default(),
             #[cfg(all(feature = "uffd", target_os = "linux"))]
             _fault_handler,
         })
     }
-}
 
-impl Drop for PoolingInstanceAllocator {
-    fn drop(&mut self) {
-        // Manually drop the pools before the fault handler (if uffd is enabled)
-        // This ensures that any fault handler thread monitoring the pool memory terminates
-        unsafe {
-            mem::ManuallyDrop::drop(&mut self.instances);
+    /// Stops allocating instances, interrupts all live instances, waits up to
+    /// `timeout` for them to be deallocated, and then unmaps the pool.
+    ///
+    /// Once this has been called, every further allocation fails. Interrupting
+    /// only stops wasm that was compiled to be interruptable; the embedder is
+    /// still responsible for deallocating the instances, possibly from other
+    /// threads, once their calls have returned.
+    ///
+    /// Unmapping releases the memory of the pool's instances, linear memories
+    /// and tables, while the address space they occupied stays reserved until
+    /// the allocator is dropped. If this returns an error, some instances may
+    /// still be using the pool, so it's left mapped and the allocator must not
+    /// be dropped until they have been deallocated.
+    pub fn drain_and_shutdown(&self, timeout: Duration) -> Result<(), ShutdownTimeout> {
+        self.draining.store(true, Ordering::SeqCst);
+        // Pairs with the fence in `deallocate` and the `allocating` increment
+        // in `allocate`: either they see `draining` and notify, or the counts
+        // below see their effect.
+        atomic::fence(Ordering::SeqCst);
+
+        for pool in self.pools() {
+            pool.interrupt_all();
+        }
+
+        let deadline = Instant::now() + timeout;
+        let (lock, cvar) = &self.drained;
+        let mut guard = lock.lock().unwrap();
+        loop {
+            // `deallocate` and `allocate` finish before taking the lock to
+            // notify, so counting under the lock can't miss a wakeup.
+            let live = self.pools().map(InstancePool::num_in_use).sum::<usize>()
+                + self.allocating.load(Ordering::SeqCst);
+            if live == 0 {
+                self.finish_warm_up();
+                for pool in self.pools() {
+                    unsafe {
+                        pool.release();
+                    }
+                }
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ShutdownTimeout(live));
+            }
+            guard = cvar.wait_timeout(guard, deadline - now).unwrap().0;
         }
     }
-}
 
-unsafe impl InstanceAllocator for PoolingInstanceAllocator {
-    fn validate(&self, module: &Module) -> Result<()> {
-        self.module_limits.validate(module)
+    /// Sets a callback to be invoked when a store's externref activations
+    /// table holds more than `threshold` references.
+    ///
+    /// The table is checked whenever an instance is allocated for a store, so
+    /// the callback runs outside of wasm execution. This lets embedders
+    /// schedule a GC cycle at a convenient time rather than having it happen
+    /// synchronously inside wasm once the table fills up.
+    pub fn with_gc_pressure_callback(
+        mut self,
+        threshold: u32,
+        callback: GcPressureCallback,
+    ) -> Self {
+        self.gc_pressure = Some(GcPressure {
+            threshold,
+            callback,
+        });
+        self
     }
 
-    fn adjust_tunables(&self, tunables: &mut Tunables) {
-        // Treat the static memory bound as the maximum for unbounded Wasm memories
-        // Because we guarantee a module cannot compile unless it fits in the limits of
-        // the pool allocator, this ensures all memories are treated as static (i.e. immovable).
-        tunables.static_memory_bound_is_maximum = true;
+    /// Enables a version counter for each instance slot, which is bumped
+    /// every time the slot is deallocated.
+    ///
+    /// Instance handles returned by `allocate` then record the version of
+    /// their slot, and using or deallocating a handle whose slot has already
+    /// been deallocated (and possibly reused for another instance) panics,
+    /// catching use-after-free bugs in embedder code. This is checked in
+    /// release builds too, at the cost of an atomic load on every access to
+    /// the instance through a handle. Handles created from a raw `VMContext`
+    /// pointer are not checked.
+    pub fn with_slot_version_counter(mut self) -> Self {
+        self.instances.enable_slot_versions();
+        for slab in &mut self.slabs {
+            slab.instances.enable_slot_versions();
+        }
+        self
     }
 
-    unsafe fn allocate(
-        &self,
-        req: InstanceAllocationRequest,
-    ) -> Result<InstanceHandle, InstantiationError> {
-        self.instances.allocate(self.strategy, req)
+    /// Enables usage statistics for each instance slot, which are updated
+    /// every time an instance is deallocated from the slot.
+    ///
+    /// See `slot_statistics` for reading them.
+    pub fn with_per_slot_statistics(mut self) -> Self {
+        self.instances.enable_slot_stats();
+        for slab in &mut self.slabs {
+            slab.instances.enable_slot_stats();
+        }
+        self
     }
 
-    unsafe fn initialize(
-        &self,
-        handle: &mut InstanceHandle,
+    /// Returns a snapshot of the usage statistics of every instance slot.
+    ///
+    /// The slots of the main pool come first, followed by the slots of each
+    /// module slab in order of increasing memory limit. Instances that are
+    /// still allocated aren't counted until they're deallocated. The result
+    /// is empty unless `with_per_slot_statistics` was used.
+    pub fn slot_statistics(&self) -> Vec<SlotStats> {
+        self.pools().flat_map(|pool| pool.slot_stats()).collect()
+    }
+
+    /// Returns the current and peak number of allocated instances and fiber
+    /// stacks.
+    ///
+    /// The counters are maintained atomically, so this can be called from a
+    /// monitoring thread without contending with allocations.
+    pub fn stats(&self) -> PoolingStats {
+        PoolingStats {
+            current_instances: self.instance_occupancy.current(),
+            peak_instances: self.instance_occupancy.peak(),
+            current_fiber_stacks: self.fiber_stack_occupancy.current(),
+            peak_fiber_stacks: self.fiber_stack_occupancy.peak(),
+        }
+    }
+
+    /// Adds a separate region of instance slots for each of `slabs`.
+    ///
+    /// Each slab reserves `max_memory_pages` pages, plus the guard region
+    /// from `tunables`, for each linear memory. A module is placed in the
+    /// slab with the smallest `max_memory_pages` that it fits: each of its
+    /// memories must declare a maximum size no larger than that, and must
+    /// have been compiled with a static memory bound no larger than that
+    /// either, for example by setting `Tunables::static_memory_bound` to
+    /// it. Modules with a memory that has no maximum are never placed in a
+    /// slab. The module must also satisfy the allocator's other module
+    /// limits.
+    ///
+    /// Modules that fit no slab use the allocator's main pool, and get an
+    /// error if that slab or pool has no free slots; they don't spill over to
+    /// another one. The exception is the `BestFit` allocation strategy, which
+    /// moves on to the next tightest slab, or the main pool, that can hold the
+    /// module when its own slab is full. Pre-touching memories only applies to
+    /// the main pool.
+    pub fn with_module_slabs(
+        mut self,
+        slabs: Vec<ModuleSlab>,
+        tunables: &Tunables,
+    ) -> Result<Self> {
+        for slab in slabs {
+            if slab.slot_count == 0 {
+                bail!("the slot count of a module slab cannot be zero");
+            }
+
+            let limits = ModuleLimits {
+                memory_pages: u64::from(slab.max_memory_pages),
+                table_elements: slab.max_table_elements,
+                ..self.module_limits
+            };
+            let tunables = Tunables {
+                static_memory_bound: u64::from(slab.max_memory_pages),
+                ..tunables.clone()
+            };
+            let mut instances = InstancePool::new(
+                &limits,
+                &InstanceLimits {
+                    count: slab.slot_count,
+                },
+                &tunables,
+            )?;
+            if self.strategy == PoolingAllocationStrategy::WorkStealing {
+                instances.enable_work_stealing();
+            }
+            if self.instances.versions.is_some() {
+                instances.enable_slot_versions();
+            }
+            if self.instances.stats.is_some() {
+                instances.enable_slot_stats();
+            }
+
+            #[cfg(all(feature = "uffd", target_os = "linux"))]
+            let _fault_handler = imp::PageFaultHandler::new(&instances)?;
+
+            self.slabs.push(Slab {
+                limits,
+                offset_guard_size: tunables.static_memory_offset_guard_size,
+                instances: mem::ManuallyDrop::new(instances),
+                #[cfg(all(feature = "uffd", target_os = "linux"))]
+                _fault_handler,
+            });
+        }
+
+        self.slabs.sort_by_key(|slab| slab.limits.memory_pages);
+        Ok(self)
+    }
+
+    /// Returns the slab that `module` is placed in, if any.
+    fn slab_for(&self, module: &Module) -> Option<&Slab> {
+        if self.slabs.is_empty() {
+            return None;
+        }
+
+        self.slabs.iter().find(|slab| slab.fits(module))
+    }
+
+    /// Returns the pool that instances of `module` are allocated from.
+    fn pool_for(&self, module: &Module) -> &InstancePool {
+        match self.slab_for(module) {
+            Some(slab) => &slab.instances,
+            None => &self.instances,
+        }
+    }
+
+    /// Returns the pool that the next instance of `module` should be allocated
+    /// from according to the allocation strategy.
+    ///
+    /// Only the `BestFit` strategy looks past the pool chosen by `pool_for`.
+    fn pool_to_allocate_from(&self, module: &Module) -> &InstancePool {
+        if self.strategy != PoolingAllocationStrategy::BestFit || self.slabs.is_empty() {
+            return self.pool_for(module);
+        }
+
+        let mut candidates = self.best_fit_candidates(module);
+        let tightest = match candidates.peek() {
+            Some(Reverse((_, index))) => *index,
+            None => return self.pool_for(module),
+        };
+        while let Some(Reverse((_, index))) = candidates.pop() {
+            if self.pool_at(index).has_free_slot() {
+                return self.pool_at(index);
+            }
+        }
+        // Every pool is full, so report the limit of the tightest one.
+        self.pool_at(tightest)
+    }
+
+    /// Ranks every pool that can hold `module` by the number of bytes its
+    /// slots would leave unused, smallest first.
+    ///
+    /// Pools are identified by their index in `pools()`.
+    fn best_fit_candidates(&self, module: &Module) -> BinaryHeap<Reverse<(u64, usize)>> {
+        let vmctx_size = mem::size_of::<Instance>() as u64
+            + u64::from(VMOffsets::new(HostPtr, module).size_of_vmctx());
+        let limits = std::iter::once((
+            &self.module_limits,
+            self.module_limits.validate(module).is_ok(),
+        ))
+        .chain(
+            self.slabs
+                .iter()
+                .map(|slab| (&slab.limits, slab.fits(module))),
+        );
+
+        limits
+            .zip(self.pools())
+            .enumerate()
+            .filter(|(_, ((_, fits), _))| *fits)
+            .map(|(index, ((limits, _), pool))| {
+                let spare_pages: u64 = module
+                    .memory_plans
+                    .values()
+                    .skip(module.num_imported_memories)
+                    .map(|plan| {
+                        let pages = plan.memory.maximum.unwrap_or(plan.memory.minimum);
+                        limits.memory_pages.saturating_sub(pages)
+                    })
+                    .sum();
+                let spare_vmctx = (pool.instance_size as u64).saturating_sub(vmctx_size);
+                let waste = spare_pages * u64::from(WASM_PAGE_SIZE) + spare_vmctx;
+                Reverse((waste, index))
+            })
+            .collect()
+    }
+
+    fn pool_at(&self, index: usize) -> &InstancePool {
+        match index {
+            0 => &self.instances,
+            _ => &self.slabs[index - 1].instances,
+        }
+    }
+
+    fn pools(&self) -> impl Iterator<Item = &InstancePool> {
+        std::iter::once(&*self.instances).chain(self.slabs.iter().map(|slab| &*slab.instances))
+    }
+
+    /// Pre-touches the linear memories of the next `num_slots` free instance
+    /// slots when the allocator is created; see `pre_touch_memories`.
+    pub fn with_warm_memory_slots(mut self, num_slots: usize) -> Self {
+        self.pre_touch_memories(num_slots);
+        self
+    }
+
+    /// Faults in the first page of each linear memory in the next `num_slots`
+    /// free instance slots on a dedicated warm-up thread.
+    ///
+    /// The pool's memory is mapped lazily, so the first instance to use a slot
+    /// otherwise pays for faulting in its pages. Warming up slots during
+    /// startup takes that cost off the first requests. Allocations made while
+    /// the warm-up is still running wait for it to finish.
+    ///
+    /// The touched pages stay resident until their slot is deallocated. This
+    /// does nothing with the `uffd` feature, where the fault handler has to
+    /// populate pages so that they're initialized.
+    pub fn pre_touch_memories(&mut self, num_slots: usize) {
+        self.finish_warm_up();
+
+        let pool = &self.instances;
+        if pool.memories.memory_size == 0 {
+            return;
+        }
+
+        let page_size = region::page::size();
+        let bases = pool
+            .next_free_slots(num_slots)
+            .into_iter()
+            .flat_map(|index| pool.memories.get(index))
+            .map(|base| base as usize)
+            .collect::<Vec<_>>();
+
+        let handle = thread::spawn(move || {
+            for base in bases {
+                pretouch_memory_pages(base as *mut u8, page_size)
+                    .expect("failed to pre-touch linear memory pages");
+            }
+        });
+        *self.warm_up.get_mut().unwrap() = Some(handle);
+        *self.warm_up_pending.get_mut() = true;
+    }
+
+    /// Allocates an instance unless `drain_and_shutdown` has been called.
+    unsafe fn allocate_unless_draining(
+        &self,
+        req: InstanceAllocationRequest,
+    ) -> Result<InstanceHandle, InstantiationError> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(InstantiationError::Resource(anyhow!(
+                "the pooling instance allocator is shutting down"
+            )));
+        }
+
+        // Memory slots have a fixed size, so there is no room to grow their
+        // guard regions per allocation.
+        if req.extra_memory_guard_pages != 0 {
+            return Err(InstantiationError::Resource(anyhow!(
+                "extra memory guard pages are not supported by the pooling instance allocator"
+            )));
+        }
+
+        if let (Some(gc_pressure), Some(store)) = (&self.gc_pressure, req.store) {
+            let occupancy = (*store).externref_activations_table().0.occupancy();
+            let occupancy = u32::try_from(occupancy).unwrap_or(u32::max_value());
+            if occupancy > gc_pressure.threshold {
+                (gc_pressure.callback)(occupancy);
+            }
+        }
+
+        self.finish_warm_up();
+        let handle = self
+            .pool_to_allocate_from(&req.module)
+            .allocate(self.strategy, req)?;
+        self.instance_occupancy.increment();
+        Ok(handle)
+    }
+
+    /// Wakes up `drain_and_shutdown` after an instance was deallocated or an
+    /// allocation finished, if it's waiting.
+    fn notify_if_draining(&self) {
+        if self.draining.load(Ordering::SeqCst) {
+            let (lock, cvar) = &self.drained;
+            let _guard = lock.lock().unwrap();
+            cvar.notify_all();
+        }
+    }
+
+    /// Waits for the thread started by `pre_touch_memories`, if any.
+    fn finish_warm_up(&self) {
+        if !self.warm_up_pending.load(Ordering::Acquire) {
+            return;
+        }
+
+        // The lock is held while joining so that concurrent allocations all
+        // wait for the warm-up, not just the first one.
+        let mut warm_up = self.warm_up.lock().unwrap();
+        if let Some(handle) = warm_up.take() {
+            if let Err(e) = handle.join() {
+                std::panic::resume_unwind(e);
+            }
+        }
+        self.warm_up_pending.store(false, Ordering::Release);
+    }
+
+    /// Copies the current contents of the memory at `memory_index` in `handle`
+    /// into a new anonymous file and returns it.
+    ///
+    /// The file can be handed to another process (e.g. over a Unix domain
+    /// socket) and mapped there. It is a snapshot of the memory at the time of
+    /// the call: later writes on either side are not visible to the other.
+    #[cfg(target_os = "linux")]
+    pub fn export_memory_fd(
+        &self,
+        handle: &InstanceHandle,
+        memory_index: MemoryIndex,
+    ) -> Result<File> {
+        let instance = handle.instance();
+        if memory_index.index() >= instance.module.memory_plans.len() {
+            bail!("memory index {} is out of bounds", memory_index.index());
+        }
+
+        let memory = instance.get_memory(memory_index);
+        let len = memory.current_length;
+
+        let fd = unsafe {
+            libc::memfd_create(
+                b"wasm-memory\0".as_ptr() as *const libc::c_char,
+                libc::MFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            bail!("memfd_create failed: {}", io::Error::last_os_error());
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+
+        if len > 0 {
+            if unsafe { libc::fallocate(fd, 0, 0, len as libc::off_t) } != 0 {
+                bail!("fallocate failed: {}", io::Error::last_os_error());
+            }
+
+            let contents = unsafe { std::slice::from_raw_parts(memory.base, len) };
+            file.write_all(contents)
+                .context("failed to copy linear memory contents")?;
+        }
+
+        Ok(file)
+    }
+}
+
+impl Drop for PoolingInstanceAllocator {
+    fn drop(&mut self) {
+        self.finish_warm_up();
+
+        // Manually drop the pools before the fault handler (if uffd is enabled)
+        // This ensures that any fault handler thread monitoring the pool memory terminates
+        unsafe {
+            mem::ManuallyDrop::drop(&mut self.instances);
+        }
+    }
+}
+
+unsafe impl InstanceAllocator for PoolingInstanceAllocator {
+    fn validate(&self, module: &Module) -> Result<()> {
+        if self.slab_for(module).is_some() {
+            return Ok(());
+        }
+        self.module_limits.validate(module)
+    }
+
+    fn adjust_tunables(&self, tunables: &mut Tunables) {
+        // Treat the static memory bound as the maximum for unbounded Wasm memories
+        // Because we guarantee a module cannot compile unless it fits in the limits of
+        // the pool allocator, this ensures all memories are treated as static (i.e. immovable).
+        tunables.static_memory_bound_is_maximum = true;
+    }
+
+    fn can_allocate(&self, req: &InstanceAllocationRequest) -> bool {
+        !self.draining.load(Ordering::Acquire)
+            && req.extra_memory_guard_pages == 0
+            && self.validate(&req.module).is_ok()
+            && self.pool_to_allocate_from(&req.module).has_free_slot()
+    }
+
+    unsafe fn allocate(
+        &self,
+        req: InstanceAllocationRequest,
+    ) -> Result<InstanceHandle, InstantiationError> {
+        self.allocating.fetch_add(1, Ordering::SeqCst);
+        let result = self.allocate_unless_draining(req);
+        self.allocating.fetch_sub(1, Ordering::SeqCst);
+        self.notify_if_draining();
+        result
+    }
+
+    unsafe fn initialize(
+        &self,
+        handle: &mut InstanceHandle,
         module: &Module,
         is_bulk_memory: bool,
     ) -> Result<(), InstantiationError> {
@@ -1015,17 +2046,33 @@ unsafe impl InstanceAllocator for PoolingInstanceAllocator {
     }
 
     unsafe fn deallocate(&self, handle: &InstanceHandle) {
-        self.instances.deallocate(handle);
+        let pool = self
+            .pools()
+            .find(|pool| pool.contains(handle.instance))
+            .expect("instance was not allocated from this allocator");
+        pool.deallocate(handle);
+        self.instance_occupancy.decrement();
+
+        // Pairs with the fence in `drain_and_shutdown`.
+        atomic::fence(Ordering::SeqCst);
+        self.notify_if_draining();
+    }
+
+    fn drain_and_shutdown(&self, timeout: Duration) -> Result<(), ShutdownTimeout> {
+        PoolingInstanceAllocator::drain_and_shutdown(self, timeout)
     }
 
     #[cfg(all(feature = "async", unix))]
     fn allocate_fiber_stack(&self) -> Result<wasmtime_fiber::FiberStack, FiberStackError> {
-        self.stacks.allocate(self.strategy)
+        let stack = self.stacks.allocate(self.strategy)?;
+        self.fiber_stack_occupancy.increment();
+        Ok(stack)
     }
 
     #[cfg(all(feature = "async", unix))]
     unsafe fn deallocate_fiber_stack(&self, stack: &wasmtime_fiber::FiberStack) {
         self.stacks.deallocate(stack);
+        self.fiber_stack_occupancy.decrement();
     }
 
     #[cfg(all(feature = "async", windows))]
@@ -1035,13 +2082,17 @@ unsafe impl InstanceAllocator for PoolingInstanceAllocator {
         }
 
         // On windows, we don't use a stack pool as we use the native fiber implementation
-        wasmtime_fiber::FiberStack::new(self.stack_size)
-            .map_err(|e| FiberStackError::Resource(e.into()))
+        let stack = wasmtime_fiber::FiberStack::new(self.stack_size)
+            .map_err(|e| FiberStackError::Resource(e.into()))?;
+        self.fiber_stack_occupancy.increment();
+        Ok(stack)
     }
 
     #[cfg(all(feature = "async", windows))]
     unsafe fn deallocate_fiber_stack(&self, _stack: &wasmtime_fiber::FiberStack) {
-        // A no-op as we don't own the fiber stack on Windows
+        // We don't own the fiber stack on Windows, so there's only the count
+        // to update.
+        self.fiber_stack_occupancy.decrement();
     }
 }
 
@@ -1315,6 +2366,69 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_module_validation_report() {
+        let limits = ModuleLimits {
+            imported_functions: 0,
+            tables: 1,
+            table_elements: 10,
+            ..Default::default()
+        };
+
+        let mut module = Module::default();
+        assert_eq!(
+            limits.validate_module(&module),
+            ModuleValidationReport {
+                passed: true,
+                violations: Vec::new(),
+            }
+        );
+
+        module.num_imported_funcs = 2;
+        module.functions.push(SignatureIndex::new(0));
+        module.functions.push(SignatureIndex::new(0));
+        for minimum in [5, 11].iter() {
+            module.table_plans.push(TablePlan {
+                style: TableStyle::CallerChecksSignature,
+                table: Table {
+                    wasm_ty: WasmType::FuncRef,
+                    minimum: *minimum,
+                    maximum: None,
+                },
+            });
+        }
+
+        let report = limits.validate_module(&module);
+        assert!(!report.passed);
+        assert_eq!(
+            report.violations,
+            [
+                LimitViolation {
+                    limit: ModuleLimit::ImportedFunctions,
+                    index: None,
+                    allowed: 0,
+                    actual: 2,
+                },
+                LimitViolation {
+                    limit: ModuleLimit::Tables,
+                    index: None,
+                    allowed: 1,
+                    actual: 2,
+                },
+                LimitViolation {
+                    limit: ModuleLimit::TableElements,
+                    index: Some(1),
+                    allowed: 10,
+                    actual: 11,
+                },
+            ]
+        );
+        assert_eq!(
+            limits.validate(&module).map_err(|e| e.to_string()),
+            Err("imported function count of 2 exceeds the limit of 0".into())
+        );
+    }
+
     #[test]
     fn test_module_with_dynamic_memory_style() {
         let limits = ModuleLimits {
@@ -1356,6 +2470,528 @@ mod test {
         assert_eq!(strat.next(1), 0);
     }
 
+    #[test]
+    fn test_work_stealing_slots() {
+        let slots = WorkStealingSlots::new((0..40).collect());
+        assert_eq!(slots.shards.len(), MAX_WORK_STEALING_SHARDS);
+
+        // The current thread drains every shard, starting with its own
+        let mut popped = Vec::new();
+        while let Some(slot) = slots.pop() {
+            popped.push(slot);
+        }
+        popped.sort();
+        assert_eq!(popped, (0..40).collect::<Vec<_>>());
+
+        // Freed slots go back to the current thread's shard
+        slots.push(7);
+        assert_eq!(&*slots.shards[slots.home()].lock().unwrap(), &[7]);
+        assert_eq!(slots.pop(), Some(7));
+        assert_eq!(slots.pop(), None);
+
+        assert_eq!(WorkStealingSlots::new(vec![0, 1]).shards.len(), 2);
+        assert_eq!(WorkStealingSlots::new(Vec::new()).shards.len(), 1);
+    }
+
+    #[test]
+    fn test_work_stealing_allocation_strategy() -> Result<()> {
+        use std::sync::Barrier;
+
+        const THREADS: usize = 4;
+        const COUNT: u32 = 8;
+
+        fn allocate(
+            allocator: &PoolingInstanceAllocator,
+        ) -> Result<InstanceHandle, InstantiationError> {
+            unsafe {
+                allocator.allocate(InstanceAllocationRequest::for_test(
+                    Arc::new(Module::default()),
+                    &PrimaryMap::new(),
+                ))
+            }
+        }
+
+        let allocator = Arc::new(PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::WorkStealing,
+            ModuleLimits {
+                memory_pages: 0,
+                ..Default::default()
+            },
+            InstanceLimits { count: COUNT },
+            4096,
+            &Tunables::default(),
+        )?);
+        let barrier = Arc::new(Barrier::new(THREADS + 1));
+
+        // Each thread allocates more instances than its own free list holds,
+        // so the rest have to be stolen from other threads' free lists.
+        let threads = (0..THREADS)
+            .map(|_| {
+                let allocator = allocator.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let handles = (0..COUNT as usize / THREADS)
+                        .map(|_| allocate(&allocator).expect("allocation should succeed"))
+                        .collect::<Vec<_>>();
+                    barrier.wait();
+                    barrier.wait();
+                    for handle in &handles {
+                        unsafe {
+                            allocator.deallocate(handle);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        barrier.wait();
+        assert_eq!(allocator.instances.num_in_use(), COUNT as usize);
+        match allocate(&allocator) {
+            Err(InstantiationError::Limit(COUNT)) => {}
+            _ => panic!("unexpected result"),
+        }
+        barrier.wait();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(allocator.instances.num_in_use(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_slot_statistics() -> Result<()> {
+        let functions = PrimaryMap::new();
+        let request = |pages| {
+            let mut module = Module::default();
+            module.memory_plans.push(MemoryPlan {
+                style: MemoryStyle::Static { bound: 0 },
+                memory: Memory {
+                    minimum: pages,
+                    maximum: None,
+                    shared: false,
+                    memory64: false,
+                },
+                pre_guard_size: 0,
+                offset_guard_size: 0,
+            });
+            InstanceAllocationRequest::for_test(Arc::new(module), &functions)
+        };
+
+        let tunables = Tunables {
+            static_memory_bound: 2,
+            static_memory_offset_guard_size: 0,
+            ..Tunables::default()
+        };
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memories: 1,
+                memory_pages: 2,
+                ..Default::default()
+            },
+            InstanceLimits { count: 2 },
+            4096,
+            &tunables,
+        )?;
+        assert!(allocator.slot_statistics().is_empty());
+        let allocator = allocator.with_per_slot_statistics();
+        assert_eq!(allocator.slot_statistics(), [SlotStats::default(); 2]);
+
+        for pages in [2, 1].iter() {
+            let handle = unsafe { allocator.allocate(request(*pages))? };
+            unsafe {
+                allocator.deallocate(&handle);
+            }
+        }
+        // A live instance isn't counted until it's deallocated.
+        let live = unsafe { allocator.allocate(request(1))? };
+
+        let stats = allocator.slot_statistics();
+        assert_eq!(stats[0], SlotStats::default());
+        assert_eq!(stats[1].allocated_count, 2);
+        assert_eq!(stats[1].peak_memory_bytes, 2 * u64::from(WASM_PAGE_SIZE));
+
+        unsafe {
+            allocator.deallocate(&live);
+        }
+        assert_eq!(allocator.slot_statistics()[1].allocated_count, 3);
+        Ok(())
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_slot_version_counter() -> Result<()> {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memory_pages: 0,
+                ..Default::default()
+            },
+            InstanceLimits { count: 1 },
+            4096,
+            &Tunables::default(),
+        )?
+        .with_slot_version_counter();
+
+        let functions = PrimaryMap::new();
+        let allocate = || unsafe {
+            allocator.allocate(InstanceAllocationRequest::for_test(
+                Arc::new(Module::default()),
+                &functions,
+            ))
+        };
+
+        let old = allocate()?;
+        let stale = unsafe { old.clone() };
+        stale.module();
+        unsafe {
+            allocator.deallocate(&old);
+        }
+
+        // The slot is reused for the new instance, which works fine, while
+        // the stale handle to the old instance is caught.
+        let new = allocate()?;
+        assert_eq!(new.instance, stale.instance);
+        new.module();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            stale.module();
+        }));
+        assert!(result.is_err());
+
+        // Handles compare by instance regardless of their slot version.
+        assert!(new == stale);
+        assert!(new == unsafe { InstanceHandle::from_vmctx(new.vmctx_ptr()) });
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            allocator.deallocate(&stale);
+        }));
+        assert!(result.is_err());
+
+        unsafe {
+            allocator.deallocate(&new);
+        }
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", not(feature = "uffd")))]
+    #[test]
+    fn test_pre_touch_memories() -> Result<()> {
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memories: 1,
+                memory_pages: 1,
+                ..Default::default()
+            },
+            InstanceLimits { count: 3 },
+            4096,
+            &Tunables {
+                static_memory_bound: 1,
+                static_memory_offset_guard_size: 0,
+                ..Tunables::default()
+            },
+        )?
+        .with_warm_memory_slots(2);
+        allocator.finish_warm_up();
+
+        let page_size = region::page::size();
+        let base = |slot| allocator.instances.memories.get(slot).next().unwrap();
+        let resident = |slot| {
+            let mut vec = 0u8;
+            assert_eq!(
+                unsafe { libc::mincore(base(slot) as _, page_size, &mut vec) },
+                0
+            );
+            vec & 1 == 1
+        };
+
+        // `NextAvailable` hands out slots from the end of the free list.
+        assert!(!resident(0));
+        assert!(resident(1));
+        assert!(resident(2));
+
+        // The touched pages are inaccessible until an instance uses the slot.
+        let protection = region::query(base(2))?.protection;
+        assert_eq!(protection, region::Protection::NONE);
+
+        Ok(())
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_can_allocate() -> Result<()> {
+        let functions = PrimaryMap::new();
+        let request = |module| InstanceAllocationRequest::for_test(module, &functions);
+
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memory_pages: 0,
+                ..Default::default()
+            },
+            InstanceLimits { count: 1 },
+            4096,
+            &Tunables::default(),
+        )?;
+
+        let module = Arc::new(Module::default());
+        let mut too_many_functions = Module::default();
+        too_many_functions.num_imported_funcs = 100_000;
+        let too_many_functions = Arc::new(too_many_functions);
+
+        assert!(allocator.can_allocate(&request(module.clone())));
+        assert!(!allocator.can_allocate(&request(too_many_functions.clone())));
+
+        let handle = unsafe { allocator.allocate(request(module.clone()))? };
+        assert!(!allocator.can_allocate(&request(module.clone())));
+        // Checking doesn't claim a slot or otherwise change the pool.
+        assert_eq!(allocator.instances.num_in_use(), 1);
+
+        unsafe {
+            allocator.deallocate(&handle);
+        }
+        assert!(allocator.can_allocate(&request(module.clone())));
+        assert!(!allocator.can_allocate(&request(too_many_functions)));
+
+        // Slots have a fixed layout, so extra guard pages can't be honored.
+        let request = request(module).with_extra_memory_guard_pages(1);
+        assert!(!allocator.can_allocate(&request));
+        match unsafe { allocator.allocate(request) } {
+            Err(InstantiationError::Resource(e)) => assert_eq!(
+                e.to_string(),
+                "extra memory guard pages are not supported by the pooling instance allocator"
+            ),
+            _ => panic!("unexpected allocation result"),
+        }
+        assert_eq!(allocator.instances.num_in_use(), 0);
+
+        Ok(())
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_module_slabs() -> Result<()> {
+        let functions = PrimaryMap::new();
+        let request = |module| InstanceAllocationRequest::for_test(module, &functions);
+        let module = |minimum, maximum, bound| {
+            let mut module = Module::default();
+            module.memory_plans.push(MemoryPlan {
+                style: MemoryStyle::Static { bound },
+                memory: Memory {
+                    minimum,
+                    maximum,
+                    shared: false,
+                    memory64: false,
+                },
+                pre_guard_size: 0,
+                offset_guard_size: 0,
+            });
+            Arc::new(module)
+        };
+
+        let tunables = Tunables {
+            static_memory_bound: 10,
+            static_memory_offset_guard_size: 0,
+            ..Tunables::default()
+        };
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memories: 1,
+                memory_pages: 10,
+                ..Default::default()
+            },
+            InstanceLimits { count: 1 },
+            4096,
+            &tunables,
+        )?
+        .with_module_slabs(
+            vec![
+                ModuleSlab {
+                    max_memory_pages: 4,
+                    max_table_elements: 10,
+                    slot_count: 2,
+                },
+                ModuleSlab {
+                    max_memory_pages: 1,
+                    max_table_elements: 10,
+                    slot_count: 1,
+                },
+            ],
+            &tunables,
+        )?;
+
+        // Slabs are sorted so that modules land in the smallest one they fit.
+        assert_eq!(allocator.slabs[0].limits.memory_pages, 1);
+        assert_eq!(allocator.slabs[1].limits.memory_pages, 4);
+
+        // Each slab's reservations are sized from its own page limit.
+        assert_eq!(allocator.slabs[0].instances.memories.memory_size, 65536);
+        assert_eq!(allocator.slabs[1].instances.memories.memory_size, 4 * 65536);
+        assert_eq!(allocator.instances.memories.memory_size, 10 * 65536);
+
+        let small = module(1, Some(1), 1);
+        let medium = module(1, Some(3), 4);
+        let large = module(5, None, 10);
+        let too_large = module(11, None, 10);
+        let slab_of = |module: &Module| {
+            allocator
+                .slab_for(module)
+                .map(|slab| slab.limits.memory_pages)
+        };
+        assert_eq!(slab_of(&small), Some(1));
+        assert_eq!(slab_of(&medium), Some(4));
+        assert_eq!(slab_of(&large), None);
+
+        // Memories compiled for a larger bound than the slab's reservation,
+        // or without a maximum, only fit the main pool.
+        assert_eq!(slab_of(&module(1, Some(1), 10)), None);
+        assert_eq!(slab_of(&module(1, None, 1)), None);
+        assert!(allocator.validate(&large).is_ok());
+        assert!(allocator.validate(&too_large).is_err());
+
+        let handle = unsafe { allocator.allocate(request(small.clone()))? };
+        assert!(allocator.slabs[0].instances.contains(handle.instance));
+        assert_eq!(allocator.slabs[0].instances.num_in_use(), 1);
+        assert_eq!(allocator.instances.num_in_use(), 0);
+
+        // A full slab doesn't spill over into the main pool.
+        assert!(!allocator.can_allocate(&request(small.clone())));
+        match unsafe { allocator.allocate(request(small.clone())) } {
+            Err(InstantiationError::Limit(1)) => {}
+            _ => panic!("unexpected error"),
+        }
+        assert!(allocator.can_allocate(&request(large.clone())));
+
+        unsafe {
+            allocator.deallocate(&handle);
+        }
+        assert_eq!(allocator.slabs[0].instances.num_in_use(), 0);
+        assert!(allocator.can_allocate(&request(small)));
+
+        let handle = unsafe { allocator.allocate(request(large))? };
+        assert!(allocator.instances.contains(handle.instance));
+        unsafe {
+            allocator.deallocate(&handle);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_fit_strategy() -> Result<()> {
+        let functions = PrimaryMap::new();
+        let request = |module| InstanceAllocationRequest::for_test(module, &functions);
+        let mut module = Module::default();
+        module.memory_plans.push(MemoryPlan {
+            style: MemoryStyle::Static { bound: 1 },
+            memory: Memory {
+                minimum: 1,
+                maximum: Some(1),
+                shared: false,
+                memory64: false,
+            },
+            pre_guard_size: 0,
+            offset_guard_size: 0,
+        });
+        let module = Arc::new(module);
+
+        let tunables = Tunables {
+            static_memory_bound: 10,
+            static_memory_offset_guard_size: 0,
+            ..Tunables::default()
+        };
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::BestFit,
+            ModuleLimits {
+                memories: 1,
+                memory_pages: 10,
+                ..Default::default()
+            },
+            InstanceLimits { count: 1 },
+            4096,
+            &tunables,
+        )?
+        .with_module_slabs(
+            vec![
+                ModuleSlab {
+                    max_memory_pages: 4,
+                    max_table_elements: 10,
+                    slot_count: 1,
+                },
+                ModuleSlab {
+                    max_memory_pages: 1,
+                    max_table_elements: 10,
+                    slot_count: 1,
+                },
+            ],
+            &tunables,
+        )?;
+
+        // The module fits every pool, so the tightest slab is used first and
+        // each full pool spills over into the next best one.
+        let expected: [&InstancePool; 3] = [
+            &allocator.slabs[0].instances,
+            &allocator.slabs[1].instances,
+            &allocator.instances,
+        ];
+        let mut handles = Vec::new();
+        for pool in expected.iter() {
+            let handle = unsafe { allocator.allocate(request(module.clone()))? };
+            assert!(pool.contains(handle.instance));
+            handles.push(handle);
+        }
+
+        assert!(!allocator.can_allocate(&request(module.clone())));
+        match unsafe { allocator.allocate(request(module.clone())) } {
+            Err(InstantiationError::Limit(1)) => {}
+            _ => panic!("unexpected error"),
+        }
+
+        unsafe {
+            allocator.deallocate(&handles[1]);
+        }
+        let handle = unsafe { allocator.allocate(request(module))? };
+        assert!(allocator.slabs[1].instances.contains(handle.instance));
+        handles[1] = handle;
+
+        for handle in handles {
+            unsafe {
+                allocator.deallocate(&handle);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_slab_with_zero_slots() {
+        let result = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits::default(),
+            InstanceLimits { count: 1 },
+            4096,
+            &Tunables::default(),
+        )
+        .and_then(|allocator| {
+            allocator.with_module_slabs(
+                vec![ModuleSlab {
+                    max_memory_pages: 1,
+                    max_table_elements: 1,
+                    slot_count: 0,
+                }],
+                &Tunables::default(),
+            )
+        });
+        assert_eq!(
+            result.map(|_| ()).map_err(|e| e.to_string()),
+            Err("the slot count of a module slab cannot be zero".into())
+        );
+    }
+
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_instance_pool() -> Result<()> {
@@ -1411,6 +3047,7 @@ mod test {
                             shared_signatures: VMSharedSignatureIndex::default().into(),
                             host_state: Box::new(()),
                             store: None,
+                            extra_memory_guard_pages: 0,
                         },
                     )
                     .expect("allocation should succeed"),
@@ -1433,6 +3070,7 @@ mod test {
                 shared_signatures: VMSharedSignatureIndex::default().into(),
                 host_state: Box::new(()),
                 store: None,
+                extra_memory_guard_pages: 0,
             },
         ) {
             Err(InstantiationError::Limit(3)) => {}
@@ -1448,6 +3086,49 @@ mod test {
         Ok(())
     }
 
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_instance_pool_concurrent_slot() -> Result<()> {
+        let module_limits = ModuleLimits {
+            imported_functions: 0,
+            imported_tables: 0,
+            imported_memories: 0,
+            imported_globals: 0,
+            types: 0,
+            functions: 0,
+            tables: 0,
+            memories: 0,
+            globals: 0,
+            table_elements: 0,
+            memory_pages: 0,
+        };
+        let instance_limits = InstanceLimits { count: 1 };
+
+        let instances = InstancePool::new(&module_limits, &instance_limits, &Tunables::default())?;
+
+        let module = Arc::new(Module::default());
+        let finished_functions = &PrimaryMap::new();
+        let request = || InstanceAllocationRequest::for_test(module.clone(), finished_functions);
+
+        let handle = instances
+            .allocate(PoolingAllocationStrategy::NextAvailable, request())
+            .expect("allocation should succeed");
+
+        // Simulate a slot assignment bug handing out the same slot twice
+        instances.free_list.lock().unwrap().push(0);
+
+        match instances.allocate(PoolingAllocationStrategy::NextAvailable, request()) {
+            Err(InstantiationError::Concurrent(0)) => {}
+            _ => panic!("unexpected result"),
+        };
+
+        instances.deallocate(&handle);
+
+        assert_eq!(&*instances.free_list.lock().unwrap(), &[0]);
+
+        Ok(())
+    }
+
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_memory_pool() -> Result<()> {
@@ -1612,6 +3293,122 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_pooling_allocator_drain_and_shutdown() -> Result<()> {
+        let new_allocator = || {
+            PoolingInstanceAllocator::new(
+                PoolingAllocationStrategy::NextAvailable,
+                ModuleLimits {
+                    memory_pages: 0,
+                    ..Default::default()
+                },
+                InstanceLimits { count: 1 },
+                4096,
+                &Tunables::default(),
+            )
+        };
+        let allocate = |allocator: &PoolingInstanceAllocator| unsafe {
+            allocator.allocate(InstanceAllocationRequest::for_test(
+                Arc::new(Module::default()),
+                &PrimaryMap::new(),
+            ))
+        };
+
+        // With nothing allocated, draining returns right away and the
+        // allocator stops handing out instances.
+        let allocator = new_allocator()?;
+        allocator.drain_and_shutdown(Duration::from_secs(0))?;
+        assert!(matches!(
+            allocate(&allocator),
+            Err(InstantiationError::Resource(_))
+        ));
+
+        let allocator = Arc::new(new_allocator()?);
+        let handle = allocate(&allocator)?;
+        assert_eq!(
+            allocator.drain_and_shutdown(Duration::from_millis(1)),
+            Err(ShutdownTimeout(1))
+        );
+
+        // Another thread deallocates the instance while draining waits for it.
+        let thread = {
+            let allocator = allocator.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                unsafe {
+                    allocator.deallocate(&handle);
+                }
+            })
+        };
+        allocator.drain_and_shutdown(Duration::from_secs(60))?;
+        thread.join().unwrap();
+        assert_eq!(allocator.instances.num_in_use(), 0);
+        assert!(allocator.instances.released.load(Ordering::Acquire));
+
+        // Draining again once the pool is unmapped is fine too.
+        allocator.drain_and_shutdown(Duration::from_secs(0))?;
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pooling_allocator_export_memory_fd() -> Result<()> {
+        use super::super::test::one_page_memory_module;
+        use std::io::{Read, Seek, SeekFrom};
+
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memories: 1,
+                memory_pages: 1,
+                ..Default::default()
+            },
+            InstanceLimits { count: 1 },
+            4096,
+            &Tunables::default(),
+        )?;
+
+        let mut handle = unsafe {
+            allocator.allocate(InstanceAllocationRequest::for_test(
+                one_page_memory_module(),
+                &PrimaryMap::new(),
+            ))?
+        };
+
+        let memory = handle.instance().get_memory(MemoryIndex::new(0));
+        unsafe {
+            *memory.base = 0x2a;
+            *memory.base.add(memory.current_length - 1) = 0xff;
+        }
+
+        let mut file = allocator.export_memory_fd(&handle, MemoryIndex::new(0))?;
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut contents)?;
+
+        assert_eq!(contents.len(), WASM_PAGE_SIZE as usize);
+        assert_eq!(contents[0], 0x2a);
+        assert_eq!(contents[contents.len() - 1], 0xff);
+        assert!(contents[1..contents.len() - 1].iter().all(|b| *b == 0));
+
+        assert!(allocator
+            .export_memory_fd(&handle, MemoryIndex::new(1))
+            .is_err());
+
+        // Pooled memories can't be remapped through their instance.
+        assert!(unsafe {
+            handle.memory_unmap_region(MemoryIndex::new(0), 0, WASM_PAGE_SIZE as u64)
+        }
+        .is_err());
+
+        unsafe {
+            allocator.deallocate(&handle);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_pooling_allocator_with_memory_pages_exceeded() {
         assert_eq!(
@@ -1699,4 +3496,57 @@ mod test {
 
         Ok(())
     }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_stats() -> Result<()> {
+        let allocator = PoolingInstanceAllocator::new(
+            PoolingAllocationStrategy::NextAvailable,
+            ModuleLimits {
+                memory_pages: 0,
+                ..Default::default()
+            },
+            InstanceLimits { count: 2 },
+            4096,
+            &Tunables::default(),
+        )?;
+        assert_eq!(allocator.stats(), PoolingStats::default());
+
+        let functions = PrimaryMap::new();
+        let module = Arc::new(Module::default());
+        let mut handles = Vec::new();
+        for _ in 0..2 {
+            handles.push(unsafe {
+                allocator.allocate(InstanceAllocationRequest::for_test(
+                    module.clone(),
+                    &functions,
+                ))?
+            });
+        }
+        unsafe {
+            allocator.deallocate(&handles.pop().unwrap());
+        }
+
+        #[cfg(all(feature = "async", unix))]
+        unsafe {
+            let stack = allocator.allocate_fiber_stack()?;
+            allocator.deallocate_fiber_stack(&stack);
+        }
+
+        let stats = allocator.stats();
+        assert_eq!(stats.current_instances, 1);
+        assert_eq!(stats.peak_instances, 2);
+        #[cfg(all(feature = "async", unix))]
+        {
+            assert_eq!(stats.current_fiber_stacks, 0);
+            assert_eq!(stats.peak_fiber_stacks, 1);
+        }
+
+        unsafe {
+            allocator.deallocate(&handles.pop().unwrap());
+        }
+        assert_eq!(allocator.stats().current_instances, 0);
+
+        Ok(())
+    }
 }
@@ -532,6 +532,7 @@ mod test {
                                 shared_signatures: VMSharedSignatureIndex::default().into(),
                                 host_state: Box::new(()),
                                 store: None,
+                                extra_memory_guard_pages: 0,
                             },
                         )
                         .expect("instance should allocate"),
@@ -1,6 +1,109 @@
+use crate::Mmap;
 use anyhow::{bail, Result};
+use std::mem;
+use std::ptr;
+use winapi::ctypes::c_void;
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::ULONG;
+use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
 use winapi::um::memoryapi::{VirtualAlloc, VirtualFree};
-use winapi::um::winnt::{MEM_COMMIT, MEM_DECOMMIT, PAGE_READWRITE};
+use winapi::um::processthreadsapi::GetCurrentProcess;
+use winapi::um::winnt::{
+    HANDLE, MEM_COMMIT, MEM_DECOMMIT, MEM_RESERVE, PAGE_NOACCESS, PAGE_READWRITE,
+};
+
+// `VirtualAlloc2` and its parameter types are only available on Windows 10
+// 1803 and later, so they're declared here and the function is looked up at
+// runtime.
+
+#[repr(C)]
+struct MemAddressRequirements {
+    lowest_starting_address: *mut c_void,
+    highest_ending_address: *mut c_void,
+    alignment: SIZE_T,
+}
+
+#[repr(C, align(8))]
+struct MemExtendedParameter {
+    // The low 8 bits hold the parameter type, the rest is reserved.
+    ty: u64,
+    value: u64,
+}
+
+const MEM_EXTENDED_PARAMETER_ADDRESS_REQUIREMENTS: u64 = 1;
+
+type VirtualAlloc2 = unsafe extern "system" fn(
+    process: HANDLE,
+    base_address: *mut c_void,
+    size: SIZE_T,
+    allocation_type: ULONG,
+    page_protection: ULONG,
+    extended_parameters: *mut MemExtendedParameter,
+    parameter_count: ULONG,
+) -> *mut c_void;
+
+fn virtual_alloc2() -> Option<VirtualAlloc2> {
+    unsafe {
+        let kernelbase = GetModuleHandleA(b"kernelbase.dll\0".as_ptr() as _);
+        if kernelbase.is_null() {
+            return None;
+        }
+        let f = GetProcAddress(kernelbase, b"VirtualAlloc2\0".as_ptr() as _);
+        if f.is_null() {
+            None
+        } else {
+            Some(mem::transmute::<_, VirtualAlloc2>(f))
+        }
+    }
+}
+
+/// Reserves the inaccessible address space for the memory pool.
+///
+/// Where `VirtualAlloc2` is available the reservation is aligned to its size
+/// rounded up to a power of two, so an address can be tested for being in the
+/// pool with a single mask. Otherwise this falls back to a plain reservation.
+pub fn reserve_memory_pool(size: usize) -> Result<Mmap> {
+    if size == 0 {
+        return Ok(Mmap::new());
+    }
+
+    let alignment = match size.checked_next_power_of_two() {
+        Some(alignment) => alignment,
+        None => return Mmap::accessible_reserved(0, size),
+    };
+    let virtual_alloc2 = match virtual_alloc2() {
+        Some(f) => f,
+        None => return Mmap::accessible_reserved(0, size),
+    };
+
+    let mut requirements = MemAddressRequirements {
+        lowest_starting_address: ptr::null_mut(),
+        highest_ending_address: ptr::null_mut(),
+        alignment,
+    };
+    let mut parameter = MemExtendedParameter {
+        ty: MEM_EXTENDED_PARAMETER_ADDRESS_REQUIREMENTS,
+        value: &mut requirements as *mut MemAddressRequirements as usize as u64,
+    };
+
+    let ptr = unsafe {
+        virtual_alloc2(
+            GetCurrentProcess(),
+            ptr::null_mut(),
+            size,
+            MEM_RESERVE,
+            PAGE_NOACCESS,
+            &mut parameter,
+            1,
+        )
+    };
+    if ptr.is_null() {
+        // The address space may be too fragmented to satisfy the alignment.
+        return Mmap::accessible_reserved(0, size);
+    }
+
+    Ok(unsafe { Mmap::from_raw(ptr as usize, size) })
+}
 
 pub fn commit(addr: *mut u8, len: usize) -> Result<()> {
     if len == 0 {
@@ -40,23 +40,25 @@ pub use crate::export::*;
 pub use crate::externref::*;
 pub use crate::imports::Imports;
 pub use crate::instance::{
-    InstanceAllocationRequest, InstanceAllocator, InstanceHandle, InstanceLimits,
-    InstantiationError, LinkError, ModuleLimits, OnDemandInstanceAllocator,
-    PoolingAllocationStrategy, PoolingInstanceAllocator, ResourceLimiter, DEFAULT_INSTANCE_LIMIT,
+    GcPressureCallback, InstanceAllocationRequest, InstanceAllocator, InstanceHandle,
+    InstanceLimits, InstantiationError, LimitViolation, LinkError, ModuleLimit, ModuleLimits,
+    ModuleSlab, ModuleValidationReport, OnDemandInstanceAllocator,
+    OnDemandInstanceAllocatorBuilder, PoolingAllocationStrategy, PoolingInstanceAllocator,
+    PoolingStats, ResourceLimiter, ShutdownTimeout, SlotStats, DEFAULT_INSTANCE_LIMIT,
     DEFAULT_MEMORY_LIMIT, DEFAULT_TABLE_LIMIT,
 };
 pub use crate::jit_int::GdbJitImageRegistration;
 pub use crate::memory::{Memory, RuntimeLinearMemory, RuntimeMemoryCreator};
 pub use crate::mmap::Mmap;
-pub use crate::table::{Table, TableElement};
+pub use crate::table::{GrowError, Table, TableElement, WrongTableType};
 pub use crate::traphandlers::{
     catch_traps, init_traps, raise_lib_trap, raise_user_trap, resume_panic, tls_eager_initialize,
-    SignalHandler, TlsRestore, Trap,
+    wasm_call_stack_depth, SignalHandler, TlsRestore, Trap,
 };
 pub use crate::vmcontext::{
     VMCallerCheckedAnyfunc, VMContext, VMFunctionBody, VMFunctionImport, VMGlobalDefinition,
     VMGlobalImport, VMInterrupts, VMInvokeArgument, VMMemoryDefinition, VMMemoryImport,
-    VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline,
+    VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline, WatchdogGuard,
 };
 
 /// Version number of this crate.
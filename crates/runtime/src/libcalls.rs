@@ -60,10 +60,11 @@ use crate::externref::VMExternRef;
 use crate::instance::Instance;
 use crate::table::{Table, TableElementType};
 use crate::traphandlers::{raise_lib_trap, Trap};
-use crate::vmcontext::{VMCallerCheckedAnyfunc, VMContext};
+use crate::vmcontext::{VMCallerCheckedAnyfunc, VMContext, VMInvokeArgument};
 use backtrace::Backtrace;
 use std::mem;
 use std::ptr::{self, NonNull};
+use thiserror::Error;
 use wasmtime_environ::ir::TrapCode;
 use wasmtime_environ::wasm::{DataIndex, ElemIndex, GlobalIndex, MemoryIndex, TableIndex};
 
@@ -199,6 +200,27 @@ pub unsafe extern "C" fn wasmtime_memory32_grow(
     }
 }
 
+/// Implementation of the notification emitted after `memory.grow` when
+/// `Tunables::monitor_memory_grow` is enabled.
+///
+/// `result` is the value `memory.grow` returned: the previous size of the
+/// memory in pages, or `-1` if the memory couldn't be grown.
+pub unsafe extern "C" fn wasmtime_memory_grow_notify(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    delta: u64,
+    result: usize,
+) {
+    let result = if result == usize::max_value() {
+        None
+    } else {
+        Some(result as u64)
+    };
+    if let Some(limiter) = (*(*vmctx).instance().store()).limiter() {
+        limiter.memory_grew(memory_index, delta, result);
+    }
+}
+
 /// Implementation of `table.grow`.
 pub unsafe extern "C" fn wasmtime_table_grow(
     vmctx: *mut VMContext,
@@ -347,6 +369,26 @@ pub unsafe extern "C" fn wasmtime_memory_fill(
     }
 }
 
+/// Implementation of `memory.fill` when `Tunables::skip_bounds_checks` is
+/// enabled.
+///
+/// `dst` is the host address to fill, which compiled code has already
+/// computed from the memory's base without checking it against the memory's
+/// length.
+pub unsafe extern "C" fn wasmtime_memory_fill_unchecked(dst: *mut u8, val: u32, len: u64) {
+    ptr::write_bytes(dst, val as u8, len as usize);
+}
+
+/// Implementation of `memory.copy` when `Tunables::skip_bounds_checks` is
+/// enabled.
+///
+/// `dst` and `src` are host addresses, which compiled code has already
+/// computed from the memories' bases without checking them against the
+/// memories' lengths. The ranges may overlap.
+pub unsafe extern "C" fn wasmtime_memory_copy_unchecked(dst: *mut u8, src: *const u8, len: u64) {
+    ptr::copy(src, dst, len as usize);
+}
+
 /// Implementation of `memory.init`.
 pub unsafe extern "C" fn wasmtime_memory_init(
     vmctx: *mut VMContext,
@@ -547,6 +589,39 @@ unsafe fn validate_atomic_addr(
     Ok(())
 }
 
+/// An exception raised by wasm's `throw` instruction.
+///
+/// Exceptions can't currently be caught in wasm, so they always unwind to
+/// the host, where they show up as the source of the resulting trap.
+#[derive(Debug, Error)]
+#[error("uncaught wasm exception with tag {tag}")]
+pub struct WasmException {
+    /// The index of the exception's tag within the throwing module.
+    pub tag: u32,
+    /// The values carried by the exception, in the order of the tag's
+    /// parameters, each stored in the low bits of a `u128` whose remaining
+    /// bits are zero.
+    pub values: Vec<u128>,
+}
+
+/// Implementation of wasm's `throw` instruction.
+pub unsafe extern "C" fn wasmtime_throw(
+    _vmctx: *mut VMContext,
+    tag: u32,
+    values: *const VMInvokeArgument,
+    len: u32,
+) {
+    let values = if len == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(values, len as usize)
+            .iter()
+            .map(|v| ptr::read(v as *const VMInvokeArgument as *const u128))
+            .collect()
+    };
+    crate::traphandlers::raise_user_trap(Box::new(WasmException { tag, values }))
+}
+
 /// Hook for when an instance runs out of fuel.
 pub unsafe extern "C" fn wasmtime_out_of_gas(vmctx: *mut VMContext) {
     match (*(*vmctx).instance().store()).out_of_gas() {
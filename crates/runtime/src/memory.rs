@@ -355,6 +355,26 @@ impl Memory {
         }
     }
 
+    /// Returns whether growing this memory by `delta_pages` wasm pages is
+    /// likely to succeed.
+    ///
+    /// This only compares the new size against the memory's maximum, which
+    /// for static memories is also the size of their reservation, so it's a
+    /// best-effort hint rather than a guarantee: the `ResourceLimiter` isn't
+    /// consulted and the OS may still fail to allocate the new pages when
+    /// growing.
+    pub fn can_grow(&self, delta_pages: u64) -> bool {
+        let new_byte_size = usize::try_from(delta_pages)
+            .ok()
+            .and_then(|delta| delta.checked_mul(WASM_PAGE_SIZE))
+            .and_then(|delta| delta.checked_add(self.byte_size()));
+        match (new_byte_size, self.maximum_byte_size()) {
+            (Some(new_byte_size), Some(max)) => new_byte_size <= max,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
     /// Grow memory by the specified amount of wasm pages.
     ///
     /// Returns `None` if memory can't be grown by the specified amount
@@ -8,6 +8,7 @@ use anyhow::{bail, Result};
 use std::convert::{TryFrom, TryInto};
 use std::ops::Range;
 use std::ptr;
+use thiserror::Error;
 use wasmtime_environ::wasm::WasmType;
 use wasmtime_environ::{ir, TablePlan};
 
@@ -22,6 +23,33 @@ pub enum TableElement {
     ExternRef(Option<VMExternRef>),
 }
 
+/// An error returned by `Table::grow_to`.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrowError {
+    /// The target size is smaller than the table's current size; tables
+    /// cannot shrink.
+    #[error("cannot shrink table from {current} to {target} elements")]
+    Shrink {
+        /// The current size of the table.
+        current: u32,
+        /// The requested size of the table.
+        target: u32,
+    },
+
+    /// The table could not be grown to the target size, either because it
+    /// would exceed the table's maximum or because the resource limiter
+    /// denied the growth.
+    #[error("failed to grow table to {0} elements")]
+    Failed(u32),
+}
+
+/// An error returned by `Table::externref_iter` and
+/// `Table::compact_funcref_table` when the table's element type isn't the one
+/// the operation works on.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("table has the wrong element type")]
+pub struct WrongTableType;
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum TableElementType {
     Func,
@@ -331,6 +359,85 @@ impl Table {
         Some(old_size)
     }
 
+    /// Grow the table so that it holds exactly `target` elements, filling any
+    /// new slots with null references.
+    ///
+    /// Returns the previous size of the table. Growing to the current size is
+    /// a no-op that succeeds without consulting the limiter, which makes this
+    /// safe to call repeatedly with the same target.
+    ///
+    /// # Unsafety
+    ///
+    /// Same as `Table::grow`.
+    pub unsafe fn grow_to(
+        &mut self,
+        target: u32,
+        limiter: Option<&mut dyn ResourceLimiter>,
+    ) -> Result<u32, GrowError> {
+        let current = self.size();
+        if target < current {
+            return Err(GrowError::Shrink { current, target });
+        }
+        if target == current {
+            return Ok(current);
+        }
+
+        let init_value = match self.element_type() {
+            TableElementType::Func => TableElement::FuncRef(ptr::null_mut()),
+            TableElementType::Extern => TableElement::ExternRef(None),
+        };
+
+        self.grow(target - current, init_value, limiter)
+            .ok_or(GrowError::Failed(target))
+    }
+
+    /// Moves all non-null elements of a `funcref` table to the front of the
+    /// table, keeping their relative order, and shrinks the table to just
+    /// those elements, but never below `minimum` elements.
+    ///
+    /// Returns the new size of the table and the number of null elements that
+    /// were removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongTableType` if this is an `externref` table.
+    ///
+    /// # Unsafety
+    ///
+    /// Shrinking the table changes its `VMTableDefinition`, which needs to be
+    /// fixed up in the table's instance's `VMContext` before we call into Wasm
+    /// again. Failure to do so lets Wasm access elements past the new end of
+    /// the table.
+    pub unsafe fn compact_funcref_table(
+        &mut self,
+        minimum: u32,
+    ) -> Result<(u32, u32), WrongTableType> {
+        if self.element_type() != TableElementType::Func {
+            return Err(WrongTableType);
+        }
+
+        let elements = self.elements_mut();
+        let mut len = 0;
+        for i in 0..elements.len() {
+            // Null `funcref`s are represented by zero, and everything in
+            // `elements[len..i]` is null, so swapping moves the element to the
+            // front and leaves a null in its place.
+            if elements[i] != 0 {
+                elements.swap(len, i);
+                len += 1;
+            }
+        }
+        let old_len = elements.len();
+        let new_len = len.max(minimum as usize).min(old_len);
+
+        match self {
+            Table::Static { size, .. } => *size = new_len as u32,
+            Table::Dynamic { elements, .. } => elements.truncate(new_len),
+        }
+
+        Ok((new_len as u32, (old_len - new_len) as u32))
+    }
+
     /// Get reference to the specified element.
     ///
     /// Returns `None` if the index is out of bounds.
@@ -340,6 +447,28 @@ impl Table {
             .map(|p| unsafe { TableElement::clone_from_raw(self.element_type(), *p) })
     }
 
+    /// Returns an iterator over the elements of an `externref` table, without
+    /// cloning (and so without touching the reference counts of) the
+    /// references.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongTableType` if this is a `funcref` table.
+    pub fn externref_iter(
+        &self,
+    ) -> Result<impl Iterator<Item = Option<&VMExternRef>> + '_, WrongTableType> {
+        if self.element_type() != TableElementType::Extern {
+            return Err(WrongTableType);
+        }
+        Ok(self.elements().iter().map(|elem| {
+            // Elements of `externref` tables are either null or a pointer
+            // owned by the table, and `VMExternRef` is a transparent non-null
+            // pointer, so the two have the same representation.
+            let elem = elem as *const usize as *const Option<VMExternRef>;
+            unsafe { (*elem).as_ref() }
+        }))
+    }
+
     /// Set reference to the specified element.
     ///
     /// # Errors
@@ -357,6 +486,46 @@ impl Table {
         Ok(())
     }
 
+    /// Set reference to the specified element, first checking that both
+    /// `expected_ty` and `elem` match this table's element type.
+    ///
+    /// # Errors
+    ///
+    /// Returns a trap if `index` is out of bounds or if either type check
+    /// fails.
+    pub fn set_with_type_check(
+        &mut self,
+        index: u32,
+        elem: TableElement,
+        expected_ty: &WasmType,
+    ) -> Result<(), Trap> {
+        let table_ty = match self.element_type() {
+            TableElementType::Func => WasmType::FuncRef,
+            TableElementType::Extern => WasmType::ExternRef,
+        };
+        if *expected_ty != table_ty {
+            return Err(Trap::User(
+                format!(
+                    "table element type mismatch: expected {:?}, but the table holds {:?}",
+                    expected_ty, table_ty
+                )
+                .into(),
+            ));
+        }
+        if !self.type_matches(&elem) {
+            return Err(Trap::User(
+                format!(
+                    "table element type mismatch: element is not a {:?}",
+                    table_ty
+                )
+                .into(),
+            ));
+        }
+
+        self.set(index, elem)
+            .map_err(|()| Trap::wasm(ir::TrapCode::TableOutOfBounds))
+    }
+
     /// Copy `len` elements from `src_table[src_index..]` into `dst_table[dst_index..]`.
     ///
     /// # Errors
@@ -528,3 +697,131 @@ impl Default for Table {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasmtime_environ::{wasm, TableStyle};
+
+    fn plan(wasm_ty: WasmType, minimum: u32, maximum: Option<u32>) -> TablePlan {
+        TablePlan {
+            table: wasm::Table {
+                wasm_ty,
+                minimum,
+                maximum,
+            },
+            style: TableStyle::CallerChecksSignature,
+        }
+    }
+
+    #[test]
+    fn test_grow_to() {
+        let mut table = Table::new_dynamic(&plan(WasmType::ExternRef, 1, Some(4)), None)
+            .expect("table should be created");
+
+        unsafe {
+            assert_eq!(table.grow_to(3, None), Ok(1));
+            assert_eq!(table.size(), 3);
+            assert!(matches!(table.get(2), Some(TableElement::ExternRef(None))));
+
+            // Growing to the current size is idempotent.
+            assert_eq!(table.grow_to(3, None), Ok(3));
+            assert_eq!(table.size(), 3);
+
+            assert_eq!(
+                table.grow_to(2, None),
+                Err(GrowError::Shrink {
+                    current: 3,
+                    target: 2
+                })
+            );
+            assert_eq!(table.grow_to(5, None), Err(GrowError::Failed(5)));
+            assert_eq!(table.size(), 3);
+        }
+    }
+
+    #[test]
+    fn test_compact_funcref_table() {
+        let mut table = Table::new_dynamic(&plan(WasmType::FuncRef, 1, None), None)
+            .expect("table should be created");
+        unsafe {
+            table.grow_to(6, None).unwrap();
+        }
+        // Funcref tables never dereference their elements, so any non-null
+        // pointer will do.
+        let a = 0x1000 as *mut VMCallerCheckedAnyfunc;
+        let b = 0x2000 as *mut VMCallerCheckedAnyfunc;
+        table.set(1, TableElement::FuncRef(a)).unwrap();
+        table.set(4, TableElement::FuncRef(b)).unwrap();
+
+        assert_eq!(unsafe { table.compact_funcref_table(1) }, Ok((2, 4)));
+        assert_eq!(table.size(), 2);
+        assert_eq!(table.vmtable().current_elements, 2);
+        assert!(matches!(table.get(0), Some(TableElement::FuncRef(f)) if f == a));
+        assert!(matches!(table.get(1), Some(TableElement::FuncRef(f)) if f == b));
+
+        // Compacting a table without nulls does nothing.
+        assert_eq!(unsafe { table.compact_funcref_table(1) }, Ok((2, 0)));
+
+        // The table never shrinks below the minimum of its type.
+        table
+            .set(0, TableElement::FuncRef(ptr::null_mut()))
+            .unwrap();
+        assert_eq!(unsafe { table.compact_funcref_table(2) }, Ok((2, 0)));
+        assert_eq!(table.size(), 2);
+        assert!(matches!(table.get(0), Some(TableElement::FuncRef(f)) if f == b));
+        assert!(matches!(table.get(1), Some(TableElement::FuncRef(f)) if f.is_null()));
+
+        let mut externs = Table::new_dynamic(&plan(WasmType::ExternRef, 1, None), None)
+            .expect("table should be created");
+        assert_eq!(
+            unsafe { externs.compact_funcref_table(0) },
+            Err(WrongTableType)
+        );
+    }
+
+    #[test]
+    fn test_externref_iter() {
+        let mut table = Table::new_dynamic(&plan(WasmType::ExternRef, 3, None), None)
+            .expect("table should be created");
+        let a = VMExternRef::new(1u32);
+        table.set(1, a.clone().into()).unwrap();
+
+        let elems = table.externref_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(elems.len(), 3);
+        assert!(elems[0].is_none());
+        assert!(VMExternRef::eq(elems[1].unwrap(), &a));
+        assert!(elems[2].is_none());
+        assert_eq!(a.strong_count(), 2);
+
+        let funcs = Table::new_dynamic(&plan(WasmType::FuncRef, 1, None), None)
+            .expect("table should be created");
+        assert!(matches!(funcs.externref_iter(), Err(WrongTableType)));
+    }
+
+    #[test]
+    fn test_set_with_type_check() {
+        let mut table = Table::new_dynamic(&plan(WasmType::FuncRef, 1, None), None)
+            .expect("table should be created");
+        let null_func = || TableElement::FuncRef(ptr::null_mut());
+
+        assert!(table
+            .set_with_type_check(0, null_func(), &WasmType::FuncRef)
+            .is_ok());
+        assert!(matches!(
+            table.set_with_type_check(0, null_func(), &WasmType::ExternRef),
+            Err(Trap::User(_))
+        ));
+        assert!(matches!(
+            table.set_with_type_check(0, TableElement::ExternRef(None), &WasmType::FuncRef),
+            Err(Trap::User(_))
+        ));
+        assert!(matches!(
+            table.set_with_type_check(1, null_func(), &WasmType::FuncRef),
+            Err(Trap::Wasm {
+                trap_code: ir::TrapCode::TableOutOfBounds,
+                ..
+            })
+        ));
+    }
+}
@@ -69,6 +69,34 @@ pub fn init_traps(is_wasm_pc: fn(usize) -> bool) {
     });
 }
 
+/// Returns the number of wasm frames on the current thread's stack.
+///
+/// This walks the native stack with the system unwinder, which knows about
+/// JIT code through the unwind information registered for each module, and
+/// counts the frames whose pc is within wasm code. If wasm hasn't been entered
+/// on this thread then no stack walk is performed and 0 is returned.
+pub fn wasm_call_stack_depth() -> usize {
+    count_wasm_frames(|_| true)
+}
+
+/// Like `wasm_call_stack_depth`, but only counts the wasm frames whose pc
+/// satisfies `filter`.
+pub(crate) fn count_wasm_frames(filter: impl Fn(usize) -> bool) -> usize {
+    if tls::with(|state| state.is_none()) {
+        return 0;
+    }
+
+    let mut depth = 0;
+    backtrace::trace(|frame| {
+        let pc = frame.ip() as usize;
+        if unsafe { IS_WASM_PC(pc) } && filter(pc) {
+            depth += 1;
+        }
+        true
+    });
+    depth
+}
+
 /// Raises a user-defined trap immediately.
 ///
 /// This function performs as-if a wasm trap was just executed, only the trap
@@ -3,11 +3,18 @@
 
 use crate::externref::VMExternRef;
 use crate::instance::Instance;
+use lazy_static::lazy_static;
 use std::any::Any;
 use std::cell::UnsafeCell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::marker;
+use std::mem;
 use std::ptr::NonNull;
-use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use std::u32;
 use wasmtime_environ::BuiltinFunctionIndex;
 
@@ -60,6 +67,14 @@ mod test_vmfunction_import {
 #[repr(C)]
 pub struct VMFunctionBody(u8);
 
+// Pointers to function bodies are stored in `VMFunctionImport` and
+// `VMCallerCheckedAnyfunc`, whose layouts in `VMOffsets` are computed with the
+// target's pointer size, and JIT code loads them as pointer-sized integers.
+// `VMFunctionBody` is a sized type so pointers to it are thin, but assert at
+// compile time that they're exactly `usize`-sized so a target where that isn't
+// the case fails to build rather than silently miscomputing offsets.
+const _: [(); mem::size_of::<usize>()] = [(); mem::size_of::<*mut VMFunctionBody>()];
+
 #[cfg(test)]
 mod test_vmfunction_body {
     use super::VMFunctionBody;
@@ -68,6 +83,7 @@ mod test_vmfunction_body {
     #[test]
     fn check_vmfunction_body_offsets() {
         assert_eq!(size_of::<VMFunctionBody>(), 1);
+        assert_eq!(size_of::<*mut VMFunctionBody>(), size_of::<usize>());
     }
 }
 
@@ -329,6 +345,17 @@ mod test_vmglobal_definition {
     fn check_vmglobal_can_contain_externref() {
         assert!(size_of::<VMExternRef>() <= size_of::<VMGlobalDefinition>());
     }
+
+    #[test]
+    fn check_vmglobal_f32_preserves_nan_bits() {
+        let nan = f32::from_bits(0x7fc0_0001);
+        let mut global = VMGlobalDefinition::new();
+        unsafe {
+            global.host_write_f32(nan);
+            assert_eq!(global.host_read_f32().to_bits(), 0x7fc0_0001);
+            assert_eq!(*global.as_u32(), 0x7fc0_0001);
+        }
+    }
 }
 
 impl VMGlobalDefinition {
@@ -398,6 +425,7 @@ impl VMGlobalDefinition {
     }
 
     /// Return a reference to the value as f32 bits.
+    #[deprecated(note = "use `host_read_f32` to read an `f32` global's value")]
     #[allow(clippy::cast_ptr_alignment)]
     pub unsafe fn as_f32_bits(&self) -> &u32 {
         &*(self.storage.as_ref().as_ptr() as *const u32)
@@ -409,6 +437,22 @@ impl VMGlobalDefinition {
         &mut *(self.storage.as_mut().as_mut_ptr() as *mut u32)
     }
 
+    /// Read the value of an `f32` global.
+    ///
+    /// The value is stored as its raw bits so that NaN payloads are preserved,
+    /// and the result here is reconstructed from those bits.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn host_read_f32(&self) -> f32 {
+        f32::from_bits(*(self.storage.as_ref().as_ptr() as *const u32))
+    }
+
+    /// Write the value of an `f32` global, storing its raw bits so that NaN
+    /// payloads are preserved.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn host_write_f32(&mut self, val: f32) {
+        *(self.storage.as_mut().as_mut_ptr() as *mut u32) = val.to_bits();
+    }
+
     /// Return a reference to the value as an f64.
     #[allow(clippy::cast_ptr_alignment)]
     pub unsafe fn as_f64(&self) -> &f64 {
@@ -631,6 +675,13 @@ impl VMBuiltinFunctionsArray {
         ptrs[BuiltinFunctionIndex::memory_atomic_wait64().index() as usize] =
             wasmtime_memory_atomic_wait64 as usize;
         ptrs[BuiltinFunctionIndex::out_of_gas().index() as usize] = wasmtime_out_of_gas as usize;
+        ptrs[BuiltinFunctionIndex::throw().index() as usize] = wasmtime_throw as usize;
+        ptrs[BuiltinFunctionIndex::memory_grow_notify().index() as usize] =
+            wasmtime_memory_grow_notify as usize;
+        ptrs[BuiltinFunctionIndex::memory_fill_unchecked().index() as usize] =
+            wasmtime_memory_fill_unchecked as usize;
+        ptrs[BuiltinFunctionIndex::memory_copy_unchecked().index() as usize] =
+            wasmtime_memory_copy_unchecked as usize;
 
         if cfg!(debug_assertions) {
             for i in 0..ptrs.len() {
@@ -712,6 +763,141 @@ impl VMInterrupts {
         self.stack_limit
             .store(wasmtime_environ::INTERRUPTED, SeqCst);
     }
+
+    /// Installs a watchdog that limits wasm to executing `max_instructions`
+    /// units of fuel and interrupts it once `timeout` has elapsed, whichever
+    /// comes first.
+    ///
+    /// Dropping the returned guard cancels the timer and restores the fuel
+    /// that was available before the watchdog was installed, less the fuel
+    /// consumed while it was installed. The fuel limit only has an effect if
+    /// wasm was compiled to consume fuel, and the timer only if it was
+    /// compiled to be interruptable.
+    ///
+    /// The timers of all watchdogs are served by a single shared thread. If the
+    /// timer fired, dropping the guard also clears the interrupt it raised, so
+    /// that it doesn't interrupt the next call into wasm; an interrupt that was
+    /// requested through `interrupt` is cleared along with it.
+    ///
+    /// # Safety
+    ///
+    /// Like any access to `fuel_consumed`, this must not be called while wasm
+    /// using these interrupts is running, and the guard must be dropped on the
+    /// same thread.
+    pub unsafe fn install_watchdog(
+        self: &Arc<Self>,
+        max_instructions: u64,
+        timeout: Duration,
+    ) -> WatchdogGuard {
+        let prev_fuel_consumed = *self.fuel_consumed.get();
+        let fuel_limit = -i64::try_from(max_instructions).unwrap_or(i64::max_value());
+        *self.fuel_consumed.get() = fuel_limit;
+
+        // The timer owns a reference to the interrupts, so they stay alive even
+        // if the guard is leaked instead of dropped.
+        let deadline = WATCHDOG_TIMER.arm(Instant::now() + timeout, self.clone());
+
+        WatchdogGuard {
+            interrupts: self.clone(),
+            prev_fuel_consumed,
+            fuel_limit,
+            deadline,
+        }
+    }
+}
+
+lazy_static! {
+    static ref WATCHDOG_TIMER: WatchdogTimer = {
+        thread::Builder::new()
+            .name("wasmtime-watchdog".to_string())
+            .spawn(|| WATCHDOG_TIMER.run())
+            .expect("failed to spawn the watchdog timer thread");
+        WatchdogTimer {
+            deadlines: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(0),
+            changed: Condvar::new(),
+        }
+    };
+}
+
+/// The timer shared by all watchdogs, which interrupts wasm once a watchdog's
+/// deadline has passed.
+struct WatchdogTimer {
+    // The interrupts to raise for each pending deadline, keyed by the deadline
+    // and a unique id so that the first entry is the next one to expire.
+    deadlines: Mutex<BTreeMap<(Instant, u64), Arc<VMInterrupts>>>,
+    next_id: AtomicU64,
+    changed: Condvar,
+}
+
+impl WatchdogTimer {
+    fn arm(&self, deadline: Instant, interrupts: Arc<VMInterrupts>) -> (Instant, u64) {
+        let key = (deadline, self.next_id.fetch_add(1, SeqCst));
+        self.deadlines.lock().unwrap().insert(key, interrupts);
+        self.changed.notify_one();
+        key
+    }
+
+    /// Cancels the deadline `key`, returning whether it had already fired.
+    fn cancel(&self, key: (Instant, u64)) -> bool {
+        // Deadlines fire with the lock held, so one that's gone has already
+        // raised its interrupt.
+        self.deadlines.lock().unwrap().remove(&key).is_none()
+    }
+
+    fn run(&self) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while let Some(&key) = deadlines.keys().next() {
+                if key.0 > now {
+                    break;
+                }
+                deadlines.remove(&key).unwrap().interrupt();
+            }
+            deadlines = match deadlines.keys().next() {
+                Some(&(deadline, _)) => {
+                    self.changed
+                        .wait_timeout(deadlines, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.changed.wait(deadlines).unwrap(),
+            };
+        }
+    }
+}
+
+/// A watchdog installed with `VMInterrupts::install_watchdog`, which is
+/// cancelled when dropped.
+#[derive(Debug)]
+pub struct WatchdogGuard {
+    interrupts: Arc<VMInterrupts>,
+    prev_fuel_consumed: i64,
+    fuel_limit: i64,
+    deadline: (Instant, u64),
+}
+
+impl Drop for WatchdogGuard {
+    fn drop(&mut self) {
+        if WATCHDOG_TIMER.cancel(self.deadline) {
+            // Wasm isn't running, so there's nothing left for the interrupt
+            // to stop. Only clear it if it's still pending.
+            let _ = self.interrupts.stack_limit.compare_exchange(
+                wasmtime_environ::INTERRUPTED,
+                usize::max_value(),
+                SeqCst,
+                SeqCst,
+            );
+        }
+        // Only undo the watchdog's own adjustment of the fuel, keeping the
+        // fuel that wasm consumed in the meantime.
+        unsafe {
+            let fuel_consumed = self.interrupts.fuel_consumed.get();
+            let consumed = (*fuel_consumed).saturating_sub(self.fuel_limit);
+            *fuel_consumed = self.prev_fuel_consumed.saturating_add(consumed);
+        }
+    }
 }
 
 impl Default for VMInterrupts {
@@ -728,7 +914,11 @@ mod test_vminterrupts {
     use super::VMInterrupts;
     use memoffset::offset_of;
     use std::mem::size_of;
-    use wasmtime_environ::{Module, VMOffsets};
+    use std::sync::atomic::Ordering::SeqCst;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use wasmtime_environ::{Module, VMOffsets, INTERRUPTED};
 
     #[test]
     fn check_vminterrupts_interrupted_offset() {
@@ -739,6 +929,75 @@ mod test_vminterrupts {
             usize::from(offsets.vminterrupts_stack_limit())
         );
     }
+
+    #[test]
+    fn watchdog_sets_and_restores_fuel() {
+        let interrupts = Arc::new(VMInterrupts::default());
+        unsafe {
+            *interrupts.fuel_consumed.get() = -5;
+            let guard = interrupts.install_watchdog(100, Duration::from_secs(60));
+            assert_eq!(*interrupts.fuel_consumed.get(), -100);
+            drop(guard);
+            assert_eq!(*interrupts.fuel_consumed.get(), -5);
+
+            // Fuel consumed under the watchdog is still accounted for
+            // afterwards.
+            let guard = interrupts.install_watchdog(100, Duration::from_secs(60));
+            *interrupts.fuel_consumed.get() += 40;
+            drop(guard);
+            assert_eq!(*interrupts.fuel_consumed.get(), 35);
+        }
+        assert_eq!(interrupts.stack_limit.load(SeqCst), usize::max_value());
+    }
+
+    #[test]
+    fn watchdog_interrupts_after_timeout() {
+        let interrupts = Arc::new(VMInterrupts::default());
+        let guard = unsafe { interrupts.install_watchdog(100, Duration::from_millis(1)) };
+        while interrupts.stack_limit.load(SeqCst) != INTERRUPTED {
+            thread::yield_now();
+        }
+        // The guard clears the interrupt that its own timer raised.
+        drop(guard);
+        assert_eq!(interrupts.stack_limit.load(SeqCst), usize::max_value());
+    }
+
+    #[test]
+    fn watchdogs_share_a_timer() {
+        let interrupts = (0..3)
+            .map(|_| Arc::new(VMInterrupts::default()))
+            .collect::<Vec<_>>();
+        // Deadlines are served in order regardless of the order they were
+        // installed in.
+        let guards = unsafe {
+            [
+                interrupts[0].install_watchdog(100, Duration::from_secs(60)),
+                interrupts[1].install_watchdog(100, Duration::from_millis(1)),
+                interrupts[2].install_watchdog(100, Duration::from_millis(5)),
+            ]
+        };
+        for interrupts in &interrupts[1..] {
+            while interrupts.stack_limit.load(SeqCst) != INTERRUPTED {
+                thread::yield_now();
+            }
+        }
+        assert_eq!(interrupts[0].stack_limit.load(SeqCst), usize::max_value());
+        drop(guards);
+    }
+
+    #[test]
+    fn watchdog_outlives_leaked_guard() {
+        let interrupts = Arc::new(VMInterrupts::default());
+        std::mem::forget(unsafe { interrupts.install_watchdog(100, Duration::from_millis(1)) });
+        let weak = Arc::downgrade(&interrupts);
+        drop(interrupts);
+        // The timer thread still holds the interrupts and can safely fire.
+        if let Some(interrupts) = weak.upgrade() {
+            while interrupts.stack_limit.load(SeqCst) != INTERRUPTED {
+                thread::yield_now();
+            }
+        }
+    }
 }
 
 /// The VM "context", which is pointed to by the `vmctx` arg in Cranelift.
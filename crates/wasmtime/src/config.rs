@@ -6,11 +6,12 @@ use std::cmp;
 use std::fmt;
 #[cfg(feature = "cache")]
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use wasmparser::WasmFeatures;
 #[cfg(feature = "cache")]
 use wasmtime_cache::CacheConfig;
-use wasmtime_environ::{CompilerBuilder, Tunables};
+use wasmtime_environ::{CompilerBuilder, GcType, Tunables};
 use wasmtime_jit::{CompilationStrategy, Compiler};
 use wasmtime_profiling::{JitDumpAgent, NullProfilerAgent, ProfilingAgent, VTuneAgent};
 use wasmtime_runtime::{
@@ -266,6 +267,16 @@ pub enum PoolingAllocationStrategy {
     NextAvailable,
     /// Allocate from a random available instance.
     Random,
+    /// Allocate from a free list owned by the current thread, stealing from
+    /// other threads' free lists when it is empty.
+    ///
+    /// This avoids contention on a single lock when many threads instantiate
+    /// modules at the same time.
+    WorkStealing,
+    /// Allocate from the pool whose instance slots fit the module most
+    /// tightly, which reduces wasted space when pools of several slot sizes
+    /// are in use.
+    BestFit,
 }
 
 impl Default for PoolingAllocationStrategy {
@@ -273,6 +284,8 @@ impl Default for PoolingAllocationStrategy {
         match wasmtime_runtime::PoolingAllocationStrategy::default() {
             wasmtime_runtime::PoolingAllocationStrategy::NextAvailable => Self::NextAvailable,
             wasmtime_runtime::PoolingAllocationStrategy::Random => Self::Random,
+            wasmtime_runtime::PoolingAllocationStrategy::WorkStealing => Self::WorkStealing,
+            wasmtime_runtime::PoolingAllocationStrategy::BestFit => Self::BestFit,
         }
     }
 }
@@ -285,6 +298,8 @@ impl Into<wasmtime_runtime::PoolingAllocationStrategy> for PoolingAllocationStra
         match self {
             Self::NextAvailable => wasmtime_runtime::PoolingAllocationStrategy::NextAvailable,
             Self::Random => wasmtime_runtime::PoolingAllocationStrategy::Random,
+            Self::WorkStealing => wasmtime_runtime::PoolingAllocationStrategy::WorkStealing,
+            Self::BestFit => wasmtime_runtime::PoolingAllocationStrategy::BestFit,
         }
     }
 }
@@ -352,6 +367,7 @@ pub struct Config {
     pub(crate) async_support: bool,
     pub(crate) deserialize_check_wasmtime_version: bool,
     pub(crate) parallel_compilation: bool,
+    pub(crate) extra_memory_guard_pages: u32,
 }
 
 impl Config {
@@ -374,6 +390,7 @@ impl Config {
             async_support: false,
             deserialize_check_wasmtime_version: true,
             parallel_compilation: true,
+            extra_memory_guard_pages: 0,
         };
         ret.cranelift_debug_verifier(false);
         ret.cranelift_opt_level(OptLevel::Speed);
@@ -555,6 +572,43 @@ impl Config {
         self
     }
 
+    /// Configures whether generated code notifies the store's
+    /// [`ResourceLimiter`](crate::ResourceLimiter) every time a `memory.grow`
+    /// instruction executes.
+    ///
+    /// When enabled, [`ResourceLimiter::memory_grew`] is invoked with the
+    /// outcome of each `memory.grow`, which is useful for billing or
+    /// debugging memory usage. Growth of memories performed by the host isn't
+    /// reported.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// [`ResourceLimiter::memory_grew`]: crate::ResourceLimiter::memory_grew
+    pub fn monitor_memory_grow(&mut self, enable: bool) -> &mut Self {
+        self.tunables.monitor_memory_grow = enable;
+        self
+    }
+
+    /// Configures whether `memory.fill` and `memory.copy` instructions skip
+    /// their bounds checks.
+    ///
+    /// When enabled, these instructions access the memory's base plus the
+    /// given offsets directly, without checking the ranges against the
+    /// memories' lengths or trapping if they're out of bounds.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// # Safety
+    ///
+    /// An out-of-bounds `memory.fill` or `memory.copy` reads or corrupts host
+    /// memory when this is enabled, so it must only be used with modules
+    /// already known to stay within bounds, for example because they were
+    /// verified by other means.
+    pub unsafe fn skip_bulk_memory_bounds_checks(&mut self, enable: bool) -> &mut Self {
+        self.tunables.skip_bounds_checks = enable;
+        self
+    }
+
     /// Configures the maximum amount of stack space available for
     /// executing WebAssembly code.
     ///
@@ -654,6 +708,11 @@ impl Config {
     /// [proposal]: https://github.com/webassembly/reference-types
     pub fn wasm_reference_types(&mut self, enable: bool) -> &mut Self {
         self.features.reference_types = enable;
+        self.tunables.gc_type = if enable {
+            GcType::DeferredReferenceCounting
+        } else {
+            GcType::None
+        };
 
         self.compiler
             .set("enable_safepoints", if enable { "true" } else { "false" })
@@ -832,13 +891,13 @@ impl Config {
     ///
     /// The default value for this is `OptLevel::None`.
     pub fn cranelift_opt_level(&mut self, level: OptLevel) -> &mut Self {
-        let val = match level {
-            OptLevel::None => "none",
-            OptLevel::Speed => "speed",
-            OptLevel::SpeedAndSize => "speed_and_size",
+        let level = match level {
+            OptLevel::None => wasmtime_environ::OptLevel::None,
+            OptLevel::Speed => wasmtime_environ::OptLevel::Speed,
+            OptLevel::SpeedAndSize => wasmtime_environ::OptLevel::SpeedAndSize,
         };
         self.compiler
-            .set("opt_level", val)
+            .optimization_level(level)
             .expect("should be valid flag");
         self
     }
@@ -859,6 +918,24 @@ impl Config {
         self
     }
 
+    /// Configures a directory that Cranelift writes the CLIF of every
+    /// compiled function to.
+    ///
+    /// For each function, the CLIF before and after compilation is written to
+    /// `<dir>/<module fingerprint>/<module name>.<defined func index>.<stage>.clif`,
+    /// which can be passed to `clif-util` to reproduce code generation issues.
+    /// The `<module fingerprint>` is the hex form of [`Module::fingerprint`].
+    /// This is largely only useful for the developers of wasmtime itself.
+    ///
+    /// [`Module::fingerprint`]: crate::Module::fingerprint
+    ///
+    /// The default value for this is `None`. The `wasmtime` CLI sets it from
+    /// its `--dump-clif` flag or the `WASMTIME_CLIF_DIR` environment variable.
+    pub fn cranelift_dump_clif(&mut self, dir: Option<PathBuf>) -> &mut Self {
+        self.compiler.dump_clif(dir);
+        self
+    }
+
     /// Allows setting a Cranelift boolean flag or preset. This allows
     /// fine-tuning of Cranelift settings.
     ///
@@ -1177,6 +1254,26 @@ impl Config {
         self
     }
 
+    /// Configures the number of additional host pages of inaccessible guard
+    /// region placed after each linear memory, on top of the guard configured
+    /// by [`Config::static_memory_guard_size`] and
+    /// [`Config::dynamic_memory_guard_size`].
+    ///
+    /// Unlike those settings this doesn't affect compiled code, so modules
+    /// compiled with different values can be mixed in one process.
+    ///
+    /// This isn't supported by the pooling instance allocator, whose memory
+    /// slots have a fixed size; [`Engine::new`](crate::Engine::new) fails if
+    /// both are configured.
+    ///
+    /// ## Default
+    ///
+    /// This value defaults to `0`.
+    pub fn extra_memory_guard_pages(&mut self, count: u32) -> &mut Self {
+        self.extra_memory_guard_pages = count;
+        self
+    }
+
     /// Configure whether deserialized modules should validate version
     /// information. This only effects [`crate::Module::deserialize()`], which is
     /// used to load compiled code from trusted sources.  When true,
@@ -1222,10 +1319,19 @@ impl Config {
         let stack_size = 0;
 
         match self.allocation_strategy {
-            InstanceAllocationStrategy::OnDemand => Ok(Box::new(OnDemandInstanceAllocator::new(
-                self.mem_creator.clone(),
-                stack_size,
-            ))),
+            InstanceAllocationStrategy::OnDemand => {
+                let mut builder = OnDemandInstanceAllocator::builder();
+                builder.stack_size(stack_size);
+                if let Some(mem_creator) = &self.mem_creator {
+                    builder.mem_creator(mem_creator.clone());
+                }
+                Ok(Box::new(builder.build()))
+            }
+            InstanceAllocationStrategy::Pooling { .. } if self.extra_memory_guard_pages != 0 => {
+                bail!(
+                    "extra memory guard pages are not supported by the pooling instance allocator"
+                )
+            }
             InstanceAllocationStrategy::Pooling {
                 strategy,
                 module_limits,
@@ -1273,6 +1379,7 @@ impl Clone for Config {
             async_stack_size: self.async_stack_size,
             deserialize_check_wasmtime_version: self.deserialize_check_wasmtime_version,
             parallel_compilation: self.parallel_compilation,
+            extra_memory_guard_pages: self.extra_memory_guard_pages,
         }
     }
 }
@@ -1305,6 +1412,7 @@ impl fmt::Debug for Config {
                 "guard_before_linear_memory",
                 &self.tunables.guard_before_linear_memory,
             )
+            .field("extra_memory_guard_pages", &self.extra_memory_guard_pages)
             .field("parallel_compilation", &self.parallel_compilation)
             .field("compiler", &self.compiler)
             .finish()
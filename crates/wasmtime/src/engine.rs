@@ -2,6 +2,7 @@ use crate::signatures::SignatureRegistry;
 use crate::{Config, Trap};
 use anyhow::Result;
 use std::sync::Arc;
+use std::time::Duration;
 #[cfg(feature = "cache")]
 use wasmtime_cache::CacheConfig;
 use wasmtime_jit::Compiler;
@@ -98,6 +99,19 @@ impl Engine {
         self.inner.allocator.as_ref()
     }
 
+    /// Stops instantiating modules and waits up to `timeout` for the
+    /// instances allocated by this engine to be deallocated.
+    ///
+    /// With the pooling allocation strategy, all live instances are
+    /// interrupted and every later instantiation with this engine fails. Once
+    /// they're all deallocated the pool is unmapped. An error is returned if
+    /// instances are still alive when `timeout` expires.
+    /// With the on-demand allocation strategy this returns immediately.
+    pub fn drain_and_shutdown(&self, timeout: Duration) -> Result<()> {
+        self.allocator().drain_and_shutdown(timeout)?;
+        Ok(())
+    }
+
     #[cfg(feature = "cache")]
     pub(crate) fn cache_config(&self) -> &CacheConfig {
         &self.config().cache_config
@@ -1,6 +1,6 @@
 use crate::store::{StoreData, StoreOpaque, Stored};
 use crate::trampoline::{generate_global_export, generate_table_export};
-use crate::values::{from_checked_anyfunc, into_checked_anyfunc};
+use crate::values::{from_checked_anyfunc, from_global_definition, into_checked_anyfunc};
 use crate::{
     AsContext, AsContextMut, ExternRef, ExternType, Func, GlobalType, Instance, Memory, Module,
     Mutability, TableType, Trap, Val, ValType,
@@ -295,22 +295,8 @@ impl Global {
         unsafe {
             let store = store.as_context_mut();
             let definition = &*store[self.0].definition;
-            match self.ty(&store).content() {
-                ValType::I32 => Val::from(*definition.as_i32()),
-                ValType::I64 => Val::from(*definition.as_i64()),
-                ValType::F32 => Val::F32(*definition.as_u32()),
-                ValType::F64 => Val::F64(*definition.as_u64()),
-                ValType::ExternRef => Val::ExternRef(
-                    definition
-                        .as_externref()
-                        .clone()
-                        .map(|inner| ExternRef { inner }),
-                ),
-                ValType::FuncRef => {
-                    from_checked_anyfunc(definition.as_anyfunc() as *mut _, &mut store.opaque())
-                }
-                ValType::V128 => Val::V128(*definition.as_u128()),
-            }
+            let ty = self.ty(&store).content().clone();
+            from_global_definition(definition, &ty, &mut store.opaque())
         }
     }
 
@@ -581,6 +567,36 @@ impl Table {
         }
     }
 
+    /// Moves all non-null elements of this `funcref` table to its front,
+    /// keeping their relative order, and shrinks the table to just those
+    /// elements, but never below the minimum size of its type.
+    ///
+    /// This is intended for large, sparse tables, such as those built up by a
+    /// dynamic linker, where scanning the null slots is pure overhead. Note
+    /// that this changes the indices of the remaining elements.
+    ///
+    /// Returns the new size of the table and the number of null elements that
+    /// were removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this isn't a `funcref` table.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this table.
+    pub fn compact_funcref_table(&self, mut store: impl AsContextMut) -> Result<(u32, u32)> {
+        let minimum = self.ty(&store).minimum();
+        let table = self.wasmtime_table(&mut store.as_context_mut().opaque());
+        let store = store.as_context_mut();
+        unsafe {
+            let compacted = (*table).compact_funcref_table(minimum)?;
+            let vm = (*table).vmtable();
+            *store[self.0].definition = vm;
+            Ok(compacted)
+        }
+    }
+
     /// Copy `len` elements from `src_table[src_index..]` into
     /// `dst_table[dst_index..]`.
     ///
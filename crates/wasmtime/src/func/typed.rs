@@ -1,11 +1,11 @@
 use super::{invoke_wasm_and_catch_traps, HostAbi};
 use crate::store::StoreOpaque;
 use crate::{AsContextMut, ExternRef, Func, StoreContextMut, Trap, ValType};
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use std::marker;
 use std::mem::{self, MaybeUninit};
 use std::ptr;
-use wasmtime_runtime::{VMContext, VMFunctionBody};
+use wasmtime_runtime::{Export, InstanceHandle, VMCallerCheckedAnyfunc, VMContext, VMFunctionBody};
 
 /// A statically typed WebAssembly function.
 ///
@@ -53,6 +53,55 @@ where
         }
     }
 
+    /// Creates a [`TypedFunc`] from a raw `VMCallerCheckedAnyfunc`, such as
+    /// one read out of a funcref table or global by runtime-level code.
+    ///
+    /// The function's signature is checked against `Params` and `Results` in
+    /// the same way as [`Func::typed`]. An error is returned if `anyfunc` is
+    /// null or if the signature doesn't match.
+    ///
+    /// # Unsafety
+    ///
+    /// If non-null, `anyfunc` must point to a valid `VMCallerCheckedAnyfunc`
+    /// that belongs to an instance or host function within `store`.
+    pub unsafe fn from_caller_checked_anyfunc(
+        mut store: impl AsContextMut,
+        anyfunc: *mut VMCallerCheckedAnyfunc,
+    ) -> Result<TypedFunc<Params, Results>> {
+        let func = Func::from_caller_checked_anyfunc(&mut store.as_context_mut().opaque(), anyfunc)
+            .ok_or_else(|| anyhow!("cannot create a typed function from a null funcref"))?;
+        func.typed(&store)
+    }
+
+    /// Creates a [`TypedFunc`] for the function exported as `name` by a raw
+    /// `InstanceHandle`, for embedders working with runtime-level instances.
+    ///
+    /// The export's signature is checked against `Params` and `Results` in
+    /// the same way as [`Func::typed`]. An error is returned if there's no
+    /// such export, if it isn't a function, or if the signature doesn't
+    /// match.
+    ///
+    /// # Unsafety
+    ///
+    /// `handle` must refer to a live instance that belongs to `store`.
+    pub unsafe fn from_instance_handle_export(
+        mut store: impl AsContextMut,
+        handle: &InstanceHandle,
+        name: &str,
+    ) -> Result<TypedFunc<Params, Results>> {
+        let index = handle
+            .module()
+            .exports
+            .get(name)
+            .ok_or_else(|| anyhow!("no export named `{}`", name))?;
+        let export = match handle.lookup_by_declaration(index) {
+            Export::Function(f) => f,
+            _ => bail!("export `{}` is not a function", name),
+        };
+        let func = Func::from_wasmtime_function(export, &mut store.as_context_mut().opaque());
+        func.typed(&store)
+    }
+
     /// Returns the underlying [`Func`] that this is wrapping, losing the static
     /// type information in the process.
     pub fn func(&self) -> &Func {
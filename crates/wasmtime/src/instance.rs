@@ -17,8 +17,8 @@ use wasmtime_environ::wasm::{
 use wasmtime_environ::Initializer;
 use wasmtime_jit::TypeTables;
 use wasmtime_runtime::{
-    Imports, InstanceAllocationRequest, InstantiationError, VMContext, VMFunctionBody,
-    VMFunctionImport, VMGlobalImport, VMMemoryImport, VMTableImport,
+    ExportFunction, Imports, InstanceAllocationRequest, InstantiationError, LinkError, VMContext,
+    VMFunctionBody, VMFunctionImport, VMGlobalImport, VMMemoryImport, VMTableImport,
 };
 
 /// An instantiated WebAssembly module.
@@ -412,6 +412,77 @@ impl Instance {
     pub fn get_global(&self, store: impl AsContextMut, name: &str) -> Option<Global> {
         self.get_export(store, name)?.into_global()
     }
+
+    /// Returns the number of frames of this instance's wasm code currently on
+    /// the calling thread's stack, for example when called from a host
+    /// function.
+    ///
+    /// Instances of the same [`Module`] share their compiled code and so
+    /// can't be told apart: frames of all of them are counted. Returns 0 if
+    /// none of that code is executing on this thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance.
+    pub fn wasm_call_stack_depth(&self, store: impl AsContext) -> usize {
+        let store = store.as_context();
+        match &store[self.0] {
+            InstanceData::Instantiated { id, .. } => store.0.instance(*id).wasm_call_stack_depth(),
+            InstanceData::Synthetic(_) => 0,
+        }
+    }
+
+    /// Replaces the function this instance imports as `module`/`field` with
+    /// `new_import`, returning the function that was imported until now.
+    ///
+    /// From then on the instance's code calls `new_import` wherever it called
+    /// the import, including through tables and through exports of the
+    /// import. Calls to the old function which are already running, for
+    /// example if this is called from a host function, carry on as usual.
+    ///
+    /// Returns an error if this instance has no such import, if it isn't a
+    /// function import, if `new_import` isn't a function of the same type, or
+    /// if this instance wasn't created by instantiating a module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance or `new_import`.
+    pub fn replace_import(
+        &self,
+        mut store: impl AsContextMut,
+        module: &str,
+        field: &str,
+        new_import: Extern,
+    ) -> Result<Extern> {
+        let mut store = store.as_context_mut().opaque();
+        let new_import = match new_import {
+            Extern::Func(f) => f,
+            _ => bail!("only function imports can be replaced"),
+        };
+        assert!(
+            new_import.comes_from_same_store(&store),
+            "cross-`Store` values are not supported"
+        );
+        let id = match &store[self.0] {
+            InstanceData::Instantiated { id, .. } => *id,
+            InstanceData::Synthetic(_) => bail!("instance has no imports to replace"),
+        };
+        let export = ExportFunction {
+            anyfunc: new_import.caller_checked_anyfunc(&store),
+        };
+        unsafe {
+            // Wasm code in this store can't be running on another thread
+            // while we hold the store, and the store keeps both `new_import`
+            // and the old function alive.
+            let old = store
+                .instance_mut(id)
+                .replace_import(module, field, export)?;
+            let old = store.keep_replaced_import(old);
+            Ok(Func::from_caller_checked_anyfunc(&mut store, old)
+                .unwrap()
+                .into())
+        }
+    }
 }
 
 struct Instantiator<'a> {
@@ -727,18 +798,17 @@ impl<'a> Instantiator<'a> {
             // this instance, so we determine what the ID is and then assert
             // it's the same later when we do actually insert it.
             let instance_to_be = store.store_data().next_id::<InstanceData>();
-            let mut instance_handle =
-                store
-                    .engine()
-                    .allocator()
-                    .allocate(InstanceAllocationRequest {
-                        module: compiled_module.module().clone(),
-                        finished_functions: compiled_module.finished_functions(),
-                        imports: self.cur.build(),
-                        shared_signatures: self.cur.module.signatures().as_module_map().into(),
-                        host_state: Box::new(Instance(instance_to_be)),
-                        store: Some(store.traitobj),
-                    })?;
+            let mut instance_handle = store.engine().allocator().allocate(
+                InstanceAllocationRequest::new(
+                    compiled_module.module().clone(),
+                    compiled_module.finished_functions(),
+                    self.cur.build(),
+                    self.cur.module.signatures().as_module_map().into(),
+                    Box::new(Instance(instance_to_be)),
+                    Some(store.traitobj),
+                )
+                .with_extra_memory_guard_pages(store.engine().config().extra_memory_guard_pages),
+            )?;
 
             // The instance still has lots of setup, for example
             // data/elements/start/etc. This can all fail, but even on failure
@@ -1053,13 +1123,23 @@ fn typecheck<I>(
         engine: store.engine(),
     };
     for ((name, field, expected_ty), actual) in env_module.imports().zip(imports) {
-        check(&cx, &expected_ty, actual).with_context(|| {
-            let extra = match field {
-                Some(name) => format!("::{}", name),
-                None => String::new(),
-            };
-            format!("incompatible import type for `{}{}`", name, extra)
-        })?;
+        check(&cx, &expected_ty, actual)
+            .map_err(|e| match e.downcast::<matching::FuncTypeMismatch>() {
+                Ok(mismatch) => Error::new(LinkError::SignatureMismatch {
+                    import_module: name.to_string(),
+                    import_name: field.unwrap_or("").to_string(),
+                    expected: mismatch.expected,
+                    actual: mismatch.actual,
+                }),
+                Err(e) => e,
+            })
+            .with_context(|| {
+                let extra = match field {
+                    Some(name) => format!("::{}", name),
+                    None => String::new(),
+                };
+                format!("incompatible import type for `{}{}`", name, extra)
+            })?;
     }
     Ok(())
 }
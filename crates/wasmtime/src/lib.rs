@@ -391,7 +391,7 @@ pub use crate::instance::{Instance, InstancePre};
 pub use crate::limits::*;
 pub use crate::linker::*;
 pub use crate::memory::*;
-pub use crate::module::{FrameInfo, FrameSymbol, Module};
+pub use crate::module::{FrameInfo, FrameSymbol, Module, NamesSection};
 pub use crate::r#ref::ExternRef;
 pub use crate::store::{
     AsContext, AsContextMut, InterruptHandle, Store, StoreContext, StoreContextMut,
@@ -464,6 +464,101 @@ impl Memory {
         }
     }
 
+    /// Hints to the host OS that the byte range `start..start + len` of this
+    /// memory will be accessed sequentially.
+    ///
+    /// This is a performance hint only and doesn't change the semantics of
+    /// the memory. On Unix this uses `madvise(MADV_SEQUENTIAL)` on the pages
+    /// covering the range; on other platforms it does nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range is out of bounds of the current length of
+    /// this memory, or if the host OS rejects the hint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory doesn't belong to `store`.
+    pub fn advise_sequential(&self, store: impl AsContext, start: u64, len: u64) -> Result<()> {
+        self.advise(store, start, len, MemoryAdvice::Sequential)
+    }
+
+    /// Hints to the host OS that the byte range `start..start + len` of this
+    /// memory will be accessed in random order.
+    ///
+    /// This is a performance hint only and doesn't change the semantics of
+    /// the memory. On Unix this uses `madvise(MADV_RANDOM)` on the pages
+    /// covering the range; on other platforms it does nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range is out of bounds of the current length of
+    /// this memory, or if the host OS rejects the hint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory doesn't belong to `store`.
+    pub fn advise_random(&self, store: impl AsContext, start: u64, len: u64) -> Result<()> {
+        self.advise(store, start, len, MemoryAdvice::Random)
+    }
+
+    fn advise(
+        &self,
+        store: impl AsContext,
+        start: u64,
+        len: u64,
+        advice: MemoryAdvice,
+    ) -> Result<()> {
+        let store = store.as_context();
+        let end = match start.checked_add(len) {
+            Some(end) => end,
+            None => bail!("memory advice range overflows"),
+        };
+        let size = self.data_size(&store) as u64;
+        if end > size {
+            bail!(
+                "memory advice range {:#x}..{:#x} is out of bounds of memory of size {:#x}",
+                start,
+                end,
+                size
+            );
+        }
+        if len == 0 {
+            return Ok(());
+        }
+
+        // `madvise` requires a page-aligned address, so round the start down
+        // and extend the length to cover the same bytes.
+        let page_size = region::page::size() as u64;
+        let aligned_start = start & !(page_size - 1);
+        let aligned_len = end - aligned_start;
+        unsafe {
+            let addr = self.data_ptr(&store).add(aligned_start as usize);
+            advise_range(addr, aligned_len as usize, advice)
+        }
+    }
+
+    /// Returns whether growing this memory by `additional_pages` WebAssembly
+    /// pages is likely to succeed, without growing it.
+    ///
+    /// This is a best-effort hint, not a guarantee. It only checks the
+    /// memory's maximum size, or the size of its slot for memories of the
+    /// pooling allocator, without allocating anything. The store's
+    /// [`ResourceLimiter`](crate::ResourceLimiter) isn't consulted, and the
+    /// host may still run out of memory when [`Memory::grow`] is called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory doesn't belong to `store`.
+    pub fn can_grow(&self, store: impl AsContext, additional_pages: u32) -> bool {
+        unsafe {
+            let export = &store.as_context()[self.0];
+            let mut handle = wasmtime_runtime::InstanceHandle::from_vmctx(export.vmctx);
+            let idx = handle.memory_index(&*export.definition);
+            (*handle.get_defined_memory(idx)).can_grow(u64::from(additional_pages))
+        }
+    }
+
     fn wasmtime_memory(&self, store: &mut StoreOpaque<'_>) -> *mut wasmtime_runtime::Memory {
         unsafe {
             let export = &store[self.0];
@@ -587,6 +682,29 @@ pub unsafe trait MemoryCreator: Send + Sync {
     ) -> Result<Box<dyn LinearMemory>, String>;
 }
 
+#[derive(Copy, Clone)]
+enum MemoryAdvice {
+    Sequential,
+    Random,
+}
+
+#[cfg(unix)]
+unsafe fn advise_range(addr: *mut u8, len: usize, advice: MemoryAdvice) -> Result<()> {
+    let advice = match advice {
+        MemoryAdvice::Sequential => libc::MADV_SEQUENTIAL,
+        MemoryAdvice::Random => libc::MADV_RANDOM,
+    };
+    if libc::madvise(addr.cast(), len, advice) != 0 {
+        bail!("madvise failed: {}", std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+unsafe fn advise_range(_addr: *mut u8, _len: usize, _advice: MemoryAdvice) -> Result<()> {
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -3,7 +3,7 @@ use crate::{
     types::{ExportType, ExternType, ImportType},
 };
 use crate::{Engine, ModuleType};
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
@@ -12,8 +12,11 @@ use wasmparser::Validator;
 use wasmtime_cache::ModuleCacheEntry;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::ModuleIndex;
+use wasmtime_environ::{HostPtr, VMOffsets};
 use wasmtime_jit::{CompilationArtifacts, CompiledModule, TypeTables};
 
+pub use wasmtime_environ::NamesSection;
+
 mod registry;
 mod serialization;
 
@@ -278,6 +281,61 @@ impl Module {
     /// # }
     /// ```
     pub fn from_binary(engine: &Engine, binary: &[u8]) -> Result<Module> {
+        Self::from_binary_with_roots(engine, binary, None)
+    }
+
+    /// Creates a new WebAssembly `Module` in which only the functions that can
+    /// be reached from the function exports named in `roots` are compiled.
+    ///
+    /// Every other function keeps its index and signature, so the module
+    /// links and instantiates as before, but its body is replaced with an
+    /// `unreachable` instruction which traps if it's ever called. Functions
+    /// which the module can reference itself, such as those in element
+    /// segments or its start function, are always compiled. This saves
+    /// compiling large modules when only a few of their exports are needed.
+    /// The [`Module::fingerprint`] is still that of the whole module.
+    ///
+    /// An error is returned if one of `roots` isn't the name of a function
+    /// export. See [`Module::new`] for other details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new_with_roots(
+    ///     &engine,
+    ///     r#"(module
+    ///         (func (export "used") (result i32) i32.const 1)
+    ///         (func (export "unused") (result i32) i32.const 2))"#,
+    ///     &["used"],
+    /// )?;
+    /// let mut store = Store::new(&engine, ());
+    /// let instance = Instance::new(&mut store, &module, &[])?;
+    /// let used = instance.get_typed_func::<(), i32, _>(&mut store, "used")?;
+    /// assert_eq!(used.call(&mut store, ())?, 1);
+    /// let unused = instance.get_typed_func::<(), i32, _>(&mut store, "unused")?;
+    /// assert!(unused.call(&mut store, ()).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_roots(
+        engine: &Engine,
+        bytes: impl AsRef<[u8]>,
+        roots: &[&str],
+    ) -> Result<Module> {
+        let bytes = bytes.as_ref();
+        #[cfg(feature = "wat")]
+        let bytes = wat::parse_bytes(bytes)?;
+        Self::from_binary_with_roots(engine, &bytes, Some(roots))
+    }
+
+    fn from_binary_with_roots(
+        engine: &Engine,
+        binary: &[u8],
+        roots: Option<&[&str]>,
+    ) -> Result<Module> {
         // Check to see that the config's target matches the host
         let target = engine.compiler().compiler().triple();
         if *target != target_lexicon::Triple::host() {
@@ -299,19 +357,21 @@ impl Module {
                     "wasmtime",
                     engine.cache_config(),
                 )
-                .get_data((engine.compiler(), binary), |(compiler, binary)| {
-                    CompilationArtifacts::build(
+                .get_data((engine.compiler(), binary, roots), |(compiler, binary, roots)| {
+                    CompilationArtifacts::build_with_roots(
                         compiler,
                         binary,
                         USE_PAGED_MEM_INIT,
+                        roots,
                     )
                 })?;
             } else {
                 let (main_module, artifacts, types) =
-                    CompilationArtifacts::build(
+                    CompilationArtifacts::build_with_roots(
                         engine.compiler(),
                         binary,
                         USE_PAGED_MEM_INIT,
+                        roots,
                     )?;
             }
         };
@@ -371,6 +431,27 @@ impl Module {
         module.into_module(engine)
     }
 
+    /// Same as [`Module::deserialize`], except that `bytes` are first
+    /// decompressed with Zstandard.
+    ///
+    /// This is intended to receive the output of
+    /// [`Module::serialize_compressed`].
+    ///
+    /// # Unsafety
+    ///
+    /// This function has the same safety requirements as
+    /// [`Module::deserialize`] for the decompressed bytes.
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(nightlydoc, doc(cfg(feature = "zstd")))]
+    pub unsafe fn deserialize_compressed(
+        engine: &Engine,
+        bytes: impl AsRef<[u8]>,
+    ) -> Result<Module> {
+        let bytes =
+            zstd::decode_all(bytes.as_ref()).context("failed to decompress serialized module")?;
+        Module::deserialize(engine, bytes)
+    }
+
     fn from_parts(
         engine: &Engine,
         mut modules: Vec<Arc<CompiledModule>>,
@@ -504,6 +585,19 @@ impl Module {
         SerializedModule::new(self).to_bytes()
     }
 
+    /// Serialize the module to a vector of bytes compressed with Zstandard
+    /// at the given compression `level`.
+    ///
+    /// Use [`Module::deserialize_compressed`] to create the module from the
+    /// bytes.
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(nightlydoc, doc(cfg(feature = "zstd")))]
+    pub fn serialize_compressed(&self, level: u8) -> Result<Vec<u8>> {
+        let bytes = self.serialize()?;
+        zstd::encode_all(&bytes[..], i32::from(level))
+            .context("failed to compress serialized module")
+    }
+
     /// Creates a submodule `Module` value from the specified parameters.
     ///
     /// This is used for creating submodules as part of module instantiation.
@@ -608,6 +702,36 @@ impl Module {
         self.compiled_module().module().name.as_deref()
     }
 
+    /// Removes the module and function names recorded from this module's name
+    /// section, returning them so that they can later be put back with
+    /// [`Module::restore_names_section`].
+    ///
+    /// The names only show up in [`Module::name`] and in the frames of trap
+    /// backtraces, so stripping them doesn't affect how the module runs.
+    ///
+    /// Returns an error if this module is shared, that is if it has been
+    /// cloned or instantiated, since instances may be reading its names.
+    pub fn strip_names_section(&mut self) -> Result<NamesSection> {
+        Ok(self.env_module_mut()?.strip_names_section())
+    }
+
+    /// Restores names previously removed with
+    /// [`Module::strip_names_section`].
+    ///
+    /// Returns an error under the same conditions as
+    /// [`Module::strip_names_section`].
+    pub fn restore_names_section(&mut self, names: NamesSection) -> Result<()> {
+        self.env_module_mut()?.restore_names_section(names);
+        Ok(())
+    }
+
+    fn env_module_mut(&mut self) -> Result<&mut wasmtime_environ::Module> {
+        Arc::get_mut(&mut self.inner)
+            .and_then(|inner| Arc::get_mut(&mut inner.module))
+            .and_then(|module| module.module_mut())
+            .ok_or_else(|| anyhow!("cannot modify a module which is shared"))
+    }
+
     /// Returns the list of imports that this [`Module`] has and must be
     /// satisfied.
     ///
@@ -789,6 +913,75 @@ impl Module {
     pub fn engine(&self) -> &Engine {
         &self.inner.engine
     }
+
+    /// Returns a stable hash of the contents of this [`Module`], suitable for
+    /// use as a key in compilation caches or content-addressed registries.
+    ///
+    /// The fingerprint is computed from the module's types, imports, exports,
+    /// definitions, data and element segments, and the locals and
+    /// instructions of its function bodies. None of these are hashed in their
+    /// binary encoding, so two modules which differ only in custom sections
+    /// like the name section, in how their sections and function bodies are
+    /// encoded, or in the order of their exports have the same fingerprint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::default();
+    /// let a = Module::new(&engine, "(module (func $a (export \"f\")))")?;
+    /// let b = Module::new(&engine, "(module (func $b (export \"f\")))")?;
+    /// let c = Module::new(&engine, "(module (func (export \"g\")))")?;
+    /// assert_eq!(a.fingerprint(), b.fingerprint());
+    /// assert_ne!(a.fingerprint(), c.fingerprint());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fingerprint(&self) -> [u8; 32] {
+        self.compiled_module().module().fingerprint
+    }
+
+    /// Returns a 64-bit hash identifying the class of modules that are
+    /// semantically equivalent to this one.
+    ///
+    /// This is an `FxHasher` hash of the same view of the module that
+    /// [`Module::fingerprint`] is computed from. Modules produced by different
+    /// toolchains hash identically as long as they differ only in how they're
+    /// encoded, in the order of their exports, or in custom sections such as
+    /// the name section. The hash doesn't depend on the rest of the
+    /// [`Engine`]'s configuration either.
+    ///
+    /// Being only 64 bits, distinct modules may collide, so a matching
+    /// equivalence class should be confirmed with [`Module::fingerprint`]
+    /// where that matters. `FxHasher` also hashes differently depending on
+    /// the host's pointer width, so equivalence classes shouldn't be compared
+    /// across hosts.
+    pub fn equivalence_class(&self) -> u64 {
+        self.compiled_module().module().equivalence_class
+    }
+
+    /// Estimates the maximum size, in bytes, of the native stack used by wasm
+    /// calls within this module.
+    ///
+    /// This is the largest sum of the stack frame sizes of the compiled
+    /// functions along a chain of direct calls between functions defined in
+    /// this module. Indirect calls and calls to imported functions aren't
+    /// followed, so this is a heuristic to help size stacks rather than a
+    /// guarantee. If the functions can call each other recursively the depth
+    /// is unbounded and `u32::max_value()` is returned.
+    pub fn max_stack_depth_estimate(&self) -> u32 {
+        self.compiled_module().max_stack_depth_estimate()
+    }
+
+    /// Returns a human-readable map of the `VMContext` layout used by
+    /// instances of this module on the host.
+    ///
+    /// Each line gives the byte range and name of a `VMContext` field. This is
+    /// only intended for debugging and the format isn't stable.
+    pub fn vmctx_layout(&self) -> String {
+        VMOffsets::new(HostPtr, self.env_module()).dump_layout()
+    }
 }
 
 fn _assert_send_sync() {
@@ -417,6 +417,9 @@ impl<'a> SerializedModule<'a> {
             consume_fuel,
             static_memory_bound_is_maximum,
             guard_before_linear_memory,
+            gc_type,
+            monitor_memory_grow,
+            skip_bounds_checks,
         } = self.tunables;
 
         let other = compiler.tunables();
@@ -458,6 +461,24 @@ impl<'a> SerializedModule<'a> {
             other.guard_before_linear_memory,
             "guard before linear memory",
         )?;
+        Self::check_bool(
+            monitor_memory_grow,
+            other.monitor_memory_grow,
+            "memory.grow monitoring",
+        )?;
+        Self::check_bool(
+            skip_bounds_checks,
+            other.skip_bounds_checks,
+            "unchecked bulk memory operations",
+        )?;
+
+        if gc_type != other.gc_type {
+            bail!(
+                "Module was compiled with the {:?} garbage collector but the host is configured for {:?}",
+                gc_type,
+                other.gc_type
+            );
+        }
 
         Ok(())
     }
@@ -724,4 +745,22 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_compressed_roundtrip() -> Result<()> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, "(module (func (export \"f\")))")?;
+
+        let bytes = module.serialize()?;
+        let compressed = module.serialize_compressed(3)?;
+        assert!(compressed.len() < bytes.len());
+
+        let deserialized = unsafe { Module::deserialize_compressed(&engine, &compressed)? };
+        assert_eq!(deserialized.fingerprint(), module.fingerprint());
+
+        assert!(unsafe { Module::deserialize_compressed(&engine, &bytes) }.is_err());
+
+        Ok(())
+    }
 }
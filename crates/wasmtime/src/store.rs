@@ -148,6 +148,9 @@ pub struct StoreInnermost {
     out_of_gas_behavior: OutOfGas,
     store_data: StoreData,
     default_callee: InstanceHandle,
+    /// Functions which instances imported before they were replaced with
+    /// `Instance::replace_import`, kept for the `Func`s referring to them.
+    replaced_imports: Vec<Box<VMCallerCheckedAnyfunc>>,
 }
 
 #[cfg(feature = "async")]
@@ -204,14 +207,14 @@ impl<T> Store<T> {
         // is never null.
         let default_callee = unsafe {
             OnDemandInstanceAllocator::default()
-                .allocate(InstanceAllocationRequest {
-                    host_state: Box::new(()),
+                .allocate(InstanceAllocationRequest::new(
+                    Arc::new(wasmtime_environ::Module::default()),
                     finished_functions,
-                    shared_signatures: None.into(),
-                    imports: Default::default(),
-                    module: Arc::new(wasmtime_environ::Module::default()),
-                    store: None,
-                })
+                    Default::default(),
+                    None.into(),
+                    Box::new(()),
+                    None,
+                ))
                 .expect("failed to allocate default callee")
         };
         let mut inner = Box::new(StoreInner {
@@ -239,6 +242,7 @@ impl<T> Store<T> {
                 out_of_gas_behavior: OutOfGas::Trap,
                 store_data: StoreData::new(),
                 default_callee,
+                replaced_imports: Vec::new(),
             },
             limiter: None,
             entering_native_hook: None,
@@ -781,6 +785,16 @@ impl StoreInnermost {
         &mut self.instances[id.0].handle
     }
 
+    /// Keeps `anyfunc`, which an instance imported until it was replaced, for
+    /// as long as this store, returning a pointer to it.
+    pub(crate) fn keep_replaced_import(
+        &mut self,
+        anyfunc: VMCallerCheckedAnyfunc,
+    ) -> *mut VMCallerCheckedAnyfunc {
+        self.replaced_imports.push(Box::new(anyfunc));
+        &mut **self.replaced_imports.last_mut().unwrap()
+    }
+
     #[cfg_attr(not(target_os = "linux"), allow(dead_code))] // not used on all platforms
     pub fn set_signal_handler(&mut self, handler: Option<Box<SignalHandler<'static>>>) {
         self.signal_handler = handler;
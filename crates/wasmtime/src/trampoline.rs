@@ -39,14 +39,15 @@ fn create_handle(
         // The configured instance allocator should only be used when creating module instances
         // as we don't want host objects to count towards instance limits.
         let handle = OnDemandInstanceAllocator::new(config.mem_creator.clone(), 0).allocate(
-            InstanceAllocationRequest {
-                module: Arc::new(module),
-                finished_functions: &finished_functions,
+            InstanceAllocationRequest::new(
+                Arc::new(module),
+                &finished_functions,
                 imports,
-                shared_signatures: shared_signature_id.into(),
+                shared_signature_id.into(),
                 host_state,
-                store: Some(store.traitobj),
-            },
+                Some(store.traitobj),
+            )
+            .with_extra_memory_guard_pages(config.extra_memory_guard_pages),
         )?;
 
         Ok(store.add_instance(handle, true))
@@ -127,13 +127,13 @@ pub unsafe fn create_raw_function(
     finished_functions.push(func);
 
     Ok(
-        OnDemandInstanceAllocator::default().allocate(InstanceAllocationRequest {
-            module: Arc::new(module),
-            finished_functions: &finished_functions,
-            imports: Imports::default(),
-            shared_signatures: sig.into(),
+        OnDemandInstanceAllocator::default().allocate(InstanceAllocationRequest::new(
+            Arc::new(module),
+            &finished_functions,
+            Imports::default(),
+            sig.into(),
             host_state,
-            store: None,
-        })?,
+            None,
+        ))?,
     )
 }
@@ -3,12 +3,31 @@ use crate::linker::Definition;
 use crate::store::StoreInnermost;
 use crate::{signatures::SignatureCollection, Engine, Extern};
 use anyhow::{bail, Context, Result};
+use std::fmt;
 use wasmtime_environ::wasm::{
     EntityType, Global, InstanceTypeIndex, Memory, ModuleTypeIndex, SignatureIndex, Table,
+    WasmFuncType,
 };
 use wasmtime_jit::TypeTables;
 use wasmtime_runtime::VMSharedSignatureIndex;
 
+/// The error returned when a function's type doesn't match the expected
+/// type, carrying both types so callers that know the import's name can
+/// report a `LinkError::SignatureMismatch`.
+#[derive(Debug)]
+pub struct FuncTypeMismatch {
+    pub expected: WasmFuncType,
+    pub actual: WasmFuncType,
+}
+
+impl fmt::Display for FuncTypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "function types incompatible")
+    }
+}
+
+impl std::error::Error for FuncTypeMismatch {}
+
 pub struct MatchCx<'a> {
     pub signatures: &'a SignatureCollection,
     pub types: &'a TypeTables,
@@ -96,9 +115,15 @@ impl MatchCx<'_> {
             None => false,
         };
         if matches {
-            Ok(())
-        } else {
-            bail!("function types incompatible")
+            return Ok(());
+        }
+        match self.engine.signatures().lookup_type(actual) {
+            Some(actual) => Err(FuncTypeMismatch {
+                expected: self.types.wasm_signatures[expected].clone(),
+                actual,
+            }
+            .into()),
+            None => bail!("function types incompatible"),
         }
     }
 
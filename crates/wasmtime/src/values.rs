@@ -296,3 +296,26 @@ pub(crate) unsafe fn from_checked_anyfunc(
 ) -> Val {
     Val::FuncRef(Func::from_caller_checked_anyfunc(store, anyfunc))
 }
+
+/// Reads the value of type `ty` out of `definition`, cloning reference values
+/// so the returned `Val` holds its own reference.
+pub(crate) unsafe fn from_global_definition(
+    definition: &runtime::VMGlobalDefinition,
+    ty: &ValType,
+    store: &mut StoreOpaque,
+) -> Val {
+    match ty {
+        ValType::I32 => Val::from(*definition.as_i32()),
+        ValType::I64 => Val::from(*definition.as_i64()),
+        ValType::F32 => Val::F32(*definition.as_u32()),
+        ValType::F64 => Val::F64(*definition.as_u64()),
+        ValType::V128 => Val::V128(*definition.as_u128()),
+        ValType::ExternRef => Val::ExternRef(
+            definition
+                .as_externref()
+                .clone()
+                .map(|inner| ExternRef { inner }),
+        ),
+        ValType::FuncRef => from_checked_anyfunc(definition.as_anyfunc() as *mut _, store),
+    }
+}
@@ -83,6 +83,12 @@ pub struct RunCommand {
     #[structopt(long = "dir", number_of_values = 1, value_name = "DIRECTORY")]
     dirs: Vec<String>,
 
+    /// Print the field-by-field layout of the main module's `VMContext` to
+    /// stderr before running it; setting `WASMTIME_DUMP_VMCTX_LAYOUT=1` has
+    /// the same effect
+    #[structopt(long = "dump-vmctx-layout")]
+    dump_vmctx_layout: bool,
+
     /// Pass an environment variable to the program
     #[structopt(long = "env", number_of_values = 1, value_name = "NAME=VAL", parse(try_from_str = parse_env_var))]
     vars: Vec<(String, String)>,
@@ -266,6 +272,11 @@ impl RunCommand {
         // Read the wasm module binary either as `*.wat` or a raw binary.
         // Use "" as a default module name.
         let module = Module::from_file(linker.engine(), &self.module)?;
+        if self.dump_vmctx_layout
+            || std::env::var("WASMTIME_DUMP_VMCTX_LAYOUT").as_deref() == Ok("1")
+        {
+            eprint!("{}", module.vmctx_layout());
+        }
         linker
             .module(&mut *store, "", &module)
             .context(format!("failed to instantiate {:?}", self.module))?;
@@ -280,6 +280,11 @@ struct CommonOptions {
     /// Enable Cranelift's internal NaN canonicalization
     #[structopt(long)]
     enable_cranelift_nan_canonicalization: bool,
+
+    /// Write the CLIF of every compiled function to this directory.
+    /// Defaults to the `WASMTIME_CLIF_DIR` environment variable, if set.
+    #[structopt(long, parse(from_os_str), value_name = "DIR", verbatim_doc_comment)]
+    dump_clif: Option<PathBuf>,
 }
 
 impl CommonOptions {
@@ -348,6 +353,14 @@ impl CommonOptions {
             config.dynamic_memory_guard_size(size);
         }
 
+        let clif_dir = self
+            .dump_clif
+            .clone()
+            .or_else(|| std::env::var_os("WASMTIME_CLIF_DIR").map(PathBuf::from));
+        if clif_dir.is_some() {
+            config.cranelift_dump_clif(clif_dir);
+        }
+
         Ok(config)
     }
 
@@ -2,7 +2,7 @@ use anyhow::{bail, Context as _, Result};
 use target_lexicon::Triple;
 use wasmparser::WasmFeatures;
 use wasmtime::Strategy;
-use wasmtime_environ::{ModuleEnvironment, Tunables};
+use wasmtime_environ::{ModuleEnvironment, OptLevel, Tunables};
 use wasmtime_jit::Compiler;
 
 /// Creates object file from binary wasm data.
@@ -34,16 +34,13 @@ pub fn compile_to_obj(
         features.simd = true;
     }
 
-    match opt_level {
-        wasmtime::OptLevel::None => {}
-        wasmtime::OptLevel::Speed => {
-            builder.set("opt_level", "speed").unwrap();
-        }
-        wasmtime::OptLevel::SpeedAndSize => {
-            builder.set("opt_level", "speed_and_size").unwrap();
-        }
+    let opt_level = match opt_level {
+        wasmtime::OptLevel::None => OptLevel::None,
+        wasmtime::OptLevel::Speed => OptLevel::Speed,
+        wasmtime::OptLevel::SpeedAndSize => OptLevel::SpeedAndSize,
         other => bail!("unknown optimization level {:?}", other),
-    }
+    };
+    builder.optimization_level(opt_level)?;
 
     // TODO: Expose the tunables as command-line flags.
     let mut tunables = Tunables::default();
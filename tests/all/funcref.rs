@@ -147,3 +147,39 @@ fn func_new_returns_wrong_store() -> anyhow::Result<()> {
         }
     }
 }
+
+#[test]
+fn ref_is_null() -> anyhow::Result<()> {
+    let (mut store, module) = ref_types_module(
+        r#"
+            (module
+                (func (export "funcref_is_null") (param funcref) (result i32)
+                    local.get 0
+                    ref.is_null
+                )
+                (func (export "externref_is_null") (param externref) (result i32)
+                    local.get 0
+                    ref.is_null
+                )
+            )
+        "#,
+    )?;
+
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let funcref_is_null =
+        instance.get_typed_func::<Option<Func>, i32, _>(&mut store, "funcref_is_null")?;
+    let externref_is_null =
+        instance.get_typed_func::<Option<ExternRef>, i32, _>(&mut store, "externref_is_null")?;
+
+    let f = Func::wrap(&mut store, || {});
+    assert_eq!(funcref_is_null.call(&mut store, None)?, 1);
+    assert_eq!(funcref_is_null.call(&mut store, Some(f))?, 0);
+
+    assert_eq!(externref_is_null.call(&mut store, None)?, 1);
+    assert_eq!(
+        externref_is_null.call(&mut store, Some(ExternRef::new(42_u32)))?,
+        0
+    );
+
+    Ok(())
+}
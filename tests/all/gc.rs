@@ -235,6 +235,57 @@ fn drop_externref_via_table_set() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg(not(feature = "old-x86-backend"))] // uses atomic instrs not implemented here
+fn table_grow_externref_counts_refs() -> anyhow::Result<()> {
+    let (mut store, module) = ref_types_module(
+        r#"
+            (module
+                (table $t (export "t") 0 externref)
+
+                (func (export "grow") (param externref i32) (result i32)
+                  (table.grow $t (local.get 0) (local.get 1))
+                )
+            )
+        "#,
+    )?;
+
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let grow = instance.get_typed_func::<(Option<ExternRef>, u32), i32, _>(&mut store, "grow")?;
+    let table = instance.get_table(&mut store, "t").unwrap();
+
+    let is_dropped = Arc::new(AtomicBool::new(false));
+    let externref = ExternRef::new(SetFlagOnDrop(is_dropped.clone()));
+
+    assert_eq!(grow.call(&mut store, (Some(externref.clone()), 3))?, 0);
+    assert_eq!(grow.call(&mut store, (None, 2))?, 3);
+    store.gc();
+
+    // Each new slot holds its own reference.
+    assert_eq!(externref.strong_count(), 4);
+    for i in 0..3 {
+        match table.get(&mut store, i) {
+            Some(Val::ExternRef(Some(r))) => assert!(r.ptr_eq(&externref)),
+            _ => panic!("expected a non-null externref in slot {}", i),
+        }
+    }
+    for i in 3..5 {
+        assert!(matches!(
+            table.get(&mut store, i),
+            Some(Val::ExternRef(None))
+        ));
+    }
+    assert_eq!(externref.strong_count(), 4);
+
+    drop(store);
+    assert_eq!(externref.strong_count(), 1);
+    assert!(!is_dropped.load(SeqCst));
+    drop(externref);
+    assert!(is_dropped.load(SeqCst));
+
+    Ok(())
+}
+
 #[test]
 fn global_drops_externref() -> anyhow::Result<()> {
     test_engine(&Engine::default())?;
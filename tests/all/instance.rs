@@ -1,5 +1,6 @@
 use anyhow::Result;
 use wasmtime::*;
+use wasmtime_environ::wasm::WasmType;
 
 #[test]
 fn wrong_import_numbers() -> Result<()> {
@@ -12,6 +13,39 @@ fn wrong_import_numbers() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn signature_mismatch_is_a_typed_link_error() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(
+        store.engine(),
+        r#"(module (import "env" "f" (func (param i32) (result i64))))"#,
+    )?;
+    let func = Func::wrap(&mut store, |_: i64| {});
+
+    let err = Instance::new(&mut store, &module, &[func.into()]).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("incompatible import type for `env::f`"));
+    assert!(format!("{:#}", err).contains("expected (i32) -> (i64), found (i64) -> ()"));
+    match err.downcast_ref::<wasmtime_runtime::LinkError>() {
+        Some(wasmtime_runtime::LinkError::SignatureMismatch {
+            import_module,
+            import_name,
+            expected,
+            actual,
+        }) => {
+            assert_eq!(import_module, "env");
+            assert_eq!(import_name, "f");
+            assert_eq!(&*expected.params, [WasmType::I32]);
+            assert_eq!(&*expected.returns, [WasmType::I64]);
+            assert_eq!(&*actual.params, [WasmType::I64]);
+            assert!(actual.returns.is_empty());
+        }
+        _ => panic!("unexpected error: {:?}", err),
+    }
+    Ok(())
+}
+
 #[test]
 fn initializes_linear_memory() -> Result<()> {
     // Test for https://github.com/bytecodealliance/wasmtime/issues/2784
@@ -81,3 +115,45 @@ fn linear_memory_limits() -> Result<()> {
         Ok(())
     }
 }
+
+#[test]
+fn replace_import() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "f" (func $f (result i32)))
+                (table 1 funcref)
+                (elem (i32.const 0) $f)
+                (func (export "call") (result i32) call $f)
+                (func (export "call_indirect") (result i32)
+                    (call_indirect (result i32) (i32.const 0))))
+        "#,
+    )?;
+    let one = Func::wrap(&mut store, || 1);
+    let two = Func::wrap(&mut store, || 2);
+    let instance = Instance::new(&mut store, &module, &[one.into()])?;
+    let call = instance.get_typed_func::<(), i32, _>(&mut store, "call")?;
+    let call_indirect = instance.get_typed_func::<(), i32, _>(&mut store, "call_indirect")?;
+    assert_eq!(call.call(&mut store, ())?, 1);
+
+    let old = instance.replace_import(&mut store, "", "f", two.into())?;
+    assert_eq!(call.call(&mut store, ())?, 2);
+    assert_eq!(call_indirect.call(&mut store, ())?, 2);
+
+    // The old function can still be called, and put back.
+    let old = old.into_func().unwrap();
+    assert_eq!(old.typed::<(), i32, _>(&store)?.call(&mut store, ())?, 1);
+    instance.replace_import(&mut store, "", "f", old.into())?;
+    assert_eq!(call.call(&mut store, ())?, 1);
+
+    let wrong_type = Func::wrap(&mut store, || {});
+    assert!(instance
+        .replace_import(&mut store, "", "f", wrong_type.into())
+        .is_err());
+    assert!(instance
+        .replace_import(&mut store, "", "g", two.into())
+        .is_err());
+    Ok(())
+}
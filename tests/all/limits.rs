@@ -385,3 +385,54 @@ fn test_custom_limiter() -> Result<()> {
 
     Ok(())
 }
+
+#[derive(Default)]
+struct GrowthLog {
+    grows: Vec<(u32, u64, Option<u64>)>,
+}
+
+impl ResourceLimiter for GrowthLog {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> bool {
+        desired <= 3 * 0x10000
+    }
+
+    fn table_growing(&mut self, _current: u32, _desired: u32, _maximum: Option<u32>) -> bool {
+        true
+    }
+
+    fn memory_grew(&mut self, memory: u32, delta: u64, result: Option<u64>) {
+        self.grows.push((memory, delta, result));
+    }
+}
+
+#[test]
+fn test_monitor_memory_grow() -> Result<()> {
+    let mut config = Config::new();
+    config.monitor_memory_grow(true).wasm_multi_memory(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory 0)
+                (memory $m 1)
+                (func (export "grow") (param i32) (result i32)
+                    (memory.grow $m (local.get 0))))
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, GrowthLog::default());
+    store.limiter(|s| s as &mut dyn ResourceLimiter);
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let grow = instance.get_typed_func::<i32, i32, _>(&mut store, "grow")?;
+
+    assert_eq!(grow.call(&mut store, 2)?, 1);
+    assert_eq!(grow.call(&mut store, 1)?, -1);
+    assert_eq!(grow.call(&mut store, 0)?, 3);
+    assert_eq!(
+        store.data().grows,
+        [(1, 2, Some(1)), (1, 1, None), (1, 0, Some(3))]
+    );
+
+    Ok(())
+}
@@ -242,6 +242,46 @@ fn guards_present_pooling() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn extra_guards_present() -> Result<()> {
+    const GUARD_SIZE: u64 = 65536;
+    const EXTRA_PAGES: u32 = 4;
+
+    let mut config = Config::new();
+    config.static_memory_maximum_size(1 << 20);
+    config.static_memory_guard_size(GUARD_SIZE);
+    config.extra_memory_guard_pages(EXTRA_PAGES);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+
+    let m = Module::new(&engine, "(module (memory (export \"\") 1 2))")?;
+    let mem = Instance::new(&mut store, &m, &[])?
+        .get_memory(&mut store, "")
+        .unwrap();
+
+    // Host pages are at least 4KiB, so the last byte of the smallest possible
+    // extra guard region must be inaccessible.
+    let extra_size = u64::from(EXTRA_PAGES) * 4096;
+    unsafe {
+        assert_faults(mem.data_ptr(&store).add(mem.data_size(&store)));
+        assert_faults(
+            mem.data_ptr(&store)
+                .add((1 << 20) + (GUARD_SIZE + extra_size) as usize - 1),
+        );
+    }
+
+    // Pooling memory slots have a fixed size, so extra guard pages are
+    // rejected up front.
+    config.allocation_strategy(InstanceAllocationStrategy::Pooling {
+        strategy: PoolingAllocationStrategy::default(),
+        module_limits: ModuleLimits::default(),
+        instance_limits: InstanceLimits { count: 1 },
+    });
+    assert!(Engine::new(&config).is_err());
+
+    Ok(())
+}
+
 unsafe fn assert_faults(ptr: *mut u8) {
     use std::io::Error;
     #[cfg(unix)]
@@ -321,3 +361,117 @@ fn massive_64_bit_still_limited() -> Result<()> {
         }
     }
 }
+
+#[test]
+fn advise_memory_ranges() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let mem = Memory::new(&mut store, MemoryType::new(2, None))?;
+    let size = mem.data_size(&store) as u64;
+
+    mem.advise_sequential(&store, 0, size)?;
+    mem.advise_random(&store, 100, 0x1_0000)?;
+    mem.advise_random(&store, size, 0)?;
+
+    assert!(mem.advise_sequential(&store, 1, size).is_err());
+    assert!(mem.advise_random(&store, size + 1, 0).is_err());
+    assert!(mem.advise_random(&store, u64::MAX, 2).is_err());
+    Ok(())
+}
+
+#[test]
+fn can_grow_checks_limits() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let mem = Memory::new(&mut store, MemoryType::new(1, Some(3)))?;
+
+    assert!(mem.can_grow(&store, 0));
+    assert!(mem.can_grow(&store, 2));
+    assert!(!mem.can_grow(&store, 3));
+
+    mem.grow(&mut store, 2)?;
+    assert!(mem.can_grow(&store, 0));
+    assert!(!mem.can_grow(&store, 1));
+    // Checking doesn't change the memory.
+    assert_eq!(mem.size(&store), 3);
+
+    let unbounded = Memory::new(&mut store, MemoryType::new(0, None))?;
+    assert!(unbounded.can_grow(&store, 1));
+    assert!(!unbounded.can_grow(&store, u32::MAX));
+    Ok(())
+}
+
+#[test]
+fn memory_fill_without_bounds_checks() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_multi_memory(true);
+    unsafe {
+        config.skip_bulk_memory_bounds_checks(true);
+    }
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m0") 1)
+                (memory (export "m1") 1)
+                (func (export "fill0") (param i32 i32 i32)
+                    (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+                (func (export "fill1") (param i32 i32 i32)
+                    (memory.fill 1 (local.get 0) (local.get 1) (local.get 2)))
+            )
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let fill0 = instance.get_typed_func::<(u32, u32, u32), (), _>(&mut store, "fill0")?;
+    let fill1 = instance.get_typed_func::<(u32, u32, u32), (), _>(&mut store, "fill1")?;
+    let m0 = instance.get_memory(&mut store, "m0").unwrap();
+    let m1 = instance.get_memory(&mut store, "m1").unwrap();
+
+    fill0.call(&mut store, (10, 0xab, 5))?;
+    fill1.call(&mut store, (0xfff0, 0x1cd, 0x10))?;
+
+    let data = m0.data(&store);
+    assert_eq!(&data[9..16], &[0, 0xab, 0xab, 0xab, 0xab, 0xab, 0]);
+    assert!(m1.data(&store)[..0xfff0].iter().all(|b| *b == 0));
+    assert!(m1.data(&store)[0xfff0..].iter().all(|b| *b == 0xcd));
+    Ok(())
+}
+
+#[test]
+fn memory_copy_without_bounds_checks() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_multi_memory(true);
+    unsafe {
+        config.skip_bulk_memory_bounds_checks(true);
+    }
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m0") 1)
+                (memory (export "m1") 1)
+                (data (memory 0) (i32.const 0) "abcdefgh")
+                (func (export "copy00") (param i32 i32 i32)
+                    (memory.copy (local.get 0) (local.get 1) (local.get 2)))
+                (func (export "copy01") (param i32 i32 i32)
+                    (memory.copy 1 0 (local.get 0) (local.get 1) (local.get 2)))
+            )
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let copy00 = instance.get_typed_func::<(u32, u32, u32), (), _>(&mut store, "copy00")?;
+    let copy01 = instance.get_typed_func::<(u32, u32, u32), (), _>(&mut store, "copy01")?;
+    let m0 = instance.get_memory(&mut store, "m0").unwrap();
+    let m1 = instance.get_memory(&mut store, "m1").unwrap();
+
+    // Overlapping copies within a memory behave like `memmove`.
+    copy00.call(&mut store, (2, 0, 6))?;
+    assert_eq!(&m0.data(&store)[..8], b"ababcdef");
+    copy01.call(&mut store, (0xfffc, 4, 4))?;
+    assert_eq!(&m1.data(&store)[0xfffc..], b"cdef");
+    Ok(())
+}
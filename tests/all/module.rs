@@ -78,3 +78,231 @@ fn aot_compiles() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn fingerprint_ignores_section_encoding_and_names() -> Result<()> {
+    let wat = r#"(module $m (func $named (export "f") (result i32) i32.const 1))"#;
+    let engine = Engine::default();
+    let module = Module::new(&engine, wat)?;
+
+    // The same module without a name section and with a padded LEB128 for
+    // the code section size.
+    let padded = Module::from_binary(
+        &engine,
+        &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+            0x0a, 0x86, 0x80, 0x80, 0x80, 0x00, // code section header
+            0x01, 0x04, 0x00, 0x41, 0x01, 0x0b, // code section
+        ],
+    )?;
+    assert_eq!(module.fingerprint(), padded.fingerprint());
+
+    // Function bodies are hashed by their locals and instructions rather than
+    // their bytes, so splitting up the locals and padding the LEB128 index of
+    // `local.get` doesn't change the fingerprint either.
+    let body = |code: &[u8]| {
+        let mut wasm = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+        ];
+        wasm.extend_from_slice(&[0x0a, code.len() as u8 + 2, 0x01, code.len() as u8]);
+        wasm.extend_from_slice(code);
+        Module::from_binary(&engine, &wasm)
+    };
+    // (local i32 i32) local.get 0
+    let grouped = body(&[0x01, 0x02, 0x7f, 0x20, 0x00, 0x0b])?;
+    let split = body(&[0x02, 0x01, 0x7f, 0x01, 0x7f, 0x20, 0x80, 0x00, 0x0b])?;
+    assert_eq!(grouped.fingerprint(), split.fingerprint());
+    assert_ne!(grouped.fingerprint(), module.fingerprint());
+
+    let other = Module::new(
+        &engine,
+        r#"(module (func (export "f") (result i32) i32.const 2))"#,
+    )?;
+    assert_ne!(module.fingerprint(), other.fingerprint());
+
+    let bytes = module.serialize()?;
+    let deserialized = unsafe { Module::deserialize(&engine, &bytes)? };
+    assert_eq!(module.fingerprint(), deserialized.fingerprint());
+    Ok(())
+}
+
+#[test]
+fn equivalence_class_ignores_toolchain_differences() -> Result<()> {
+    let engine = Engine::default();
+    let a = Module::new(
+        &engine,
+        r#"
+            (module $a
+                (func $f (export "f") (result i32)
+                    (local i32 i32)
+                    local.get 0)
+                (func $g (export "g")))
+        "#,
+    )?;
+    // No names, exports listed in another order and compiled with a
+    // different configuration.
+    let b = Module::new(
+        &Engine::new(Config::new().cranelift_opt_level(OptLevel::None))?,
+        r#"
+            (module
+                (func (result i32) (local i32 i32) local.get 0)
+                (func)
+                (export "g" (func 1))
+                (export "f" (func 0)))
+        "#,
+    )?;
+    assert_eq!(a.equivalence_class(), b.equivalence_class());
+
+    let c = Module::new(
+        &engine,
+        r#"
+            (module
+                (func (export "f") (result i32) (local i32 i32) local.get 1)
+                (func (export "g")))
+        "#,
+    )?;
+    assert_ne!(a.equivalence_class(), c.equivalence_class());
+    Ok(())
+}
+
+#[test]
+fn max_stack_depth_estimate() -> Result<()> {
+    let engine = Engine::default();
+    let empty = Module::new(&engine, "(module)")?;
+    assert_eq!(empty.max_stack_depth_estimate(), 0);
+
+    let leaf = Module::new(&engine, "(module (func))")?;
+    assert!(leaf.max_stack_depth_estimate() > 0);
+
+    // Every frame on the longest chain of calls is counted, and calls to
+    // imports aren't followed.
+    let chain = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "" (func $import))
+                (func $a call $b call $import)
+                (func $b call $c)
+                (func $c))
+        "#,
+    )?;
+    assert!(chain.max_stack_depth_estimate() >= 3 * leaf.max_stack_depth_estimate());
+
+    let recursive = Module::new(&engine, "(module (func $f call $f))")?;
+    assert_eq!(recursive.max_stack_depth_estimate(), u32::max_value());
+    Ok(())
+}
+
+#[test]
+fn new_with_roots_traps_in_unreachable_functions() -> Result<()> {
+    let engine = Engine::default();
+    let wat = r#"
+        (module
+            (func $helper (result i32) i32.const 1)
+            (func (export "used") (result i32) call $helper)
+            (func (export "unused") (result i32) i32.const 2))
+    "#;
+    let module = Module::new_with_roots(&engine, wat, &["used"])?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let used = instance.get_typed_func::<(), i32, _>(&mut store, "used")?;
+    assert_eq!(used.call(&mut store, ())?, 1);
+    let unused = instance.get_typed_func::<(), i32, _>(&mut store, "unused")?;
+    let trap = unused.call(&mut store, ()).unwrap_err();
+    assert_eq!(trap.trap_code(), Some(TrapCode::UnreachableCodeReached));
+
+    assert!(Module::new_with_roots(&engine, wat, &["missing"]).is_err());
+    Ok(())
+}
+
+#[test]
+fn strip_and_restore_names_section() -> Result<()> {
+    let engine = Engine::default();
+    let mut module = Module::new(
+        &engine,
+        "(module $m (func $f unreachable) (export \"f\" (func $f)))",
+    )?;
+    let names = module.strip_names_section()?;
+    assert_eq!(module.name(), None);
+    assert_eq!(names.module_name.as_deref(), Some("m"));
+    assert_eq!(names.func_names.values().collect::<Vec<_>>(), ["f"]);
+
+    module.restore_names_section(names)?;
+    assert_eq!(module.name(), Some("m"));
+
+    // The restored function name shows up in backtraces again.
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let f = instance.get_typed_func::<(), (), _>(&mut store, "f")?;
+    let trap = f.call(&mut store, ()).unwrap_err();
+    assert_eq!(trap.trace()[0].func_name(), Some("f"));
+
+    // Instances may be reading the names, so they can't be changed anymore.
+    assert!(module.strip_names_section().is_err());
+    Ok(())
+}
+
+#[test]
+fn dump_clif_per_defined_function() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let mut config = Config::new();
+    config.cranelift_dump_clif(Some(dir.path().to_path_buf()));
+    Module::new(
+        &Engine::new(&config)?,
+        r#"
+            (module $m
+                (import "" "" (func))
+                (func)
+                (func))
+        "#,
+    )?;
+
+    let mut names = Vec::new();
+    for module_dir in std::fs::read_dir(dir.path())? {
+        for file in std::fs::read_dir(module_dir?.path())? {
+            names.push(file?.file_name().into_string().unwrap());
+        }
+    }
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "m.0.post.clif",
+            "m.0.pre.clif",
+            "m.1.post.clif",
+            "m.1.pre.clif"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn dump_clif_of_unnamed_modules() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let mut config = Config::new();
+    config.cranelift_dump_clif(Some(dir.path().to_path_buf()));
+    let engine = Engine::new(&config)?;
+    let a = Module::new(&engine, "(module (func))")?;
+    let b = Module::new(&engine, "(module (func (result i32) i32.const 1))")?;
+
+    // Both modules are named `module` in their dumps, which are kept apart by
+    // their fingerprints.
+    for module in [a, b].iter() {
+        let module_dir = dir.path().join(
+            module
+                .fingerprint()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+        );
+        assert!(module_dir.join("module.0.pre.clif").is_file());
+        assert!(module_dir.join("module.0.post.clif").is_file());
+    }
+    Ok(())
+}
@@ -58,3 +58,84 @@ fn host_always_has_some_stack() -> anyhow::Result<()> {
         consume_some_stack(space.as_mut_ptr() as usize, stack.saturating_sub(1024))
     }
 }
+
+#[test]
+fn wasm_call_stack_depth() -> anyhow::Result<()> {
+    let mut store = Store::<Vec<usize>>::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $host))
+                (func $recursive (export "foo") (param i32)
+                    local.get 0
+                    if
+                        local.get 0
+                        i32.const 1
+                        i32.sub
+                        call $recursive
+                    else
+                        call $host
+                    end)
+            )
+        "#,
+    )?;
+    let func = Func::wrap(&mut store, |mut caller: Caller<'_, Vec<usize>>| {
+        caller
+            .data_mut()
+            .push(wasmtime_runtime::wasm_call_stack_depth());
+    });
+    let instance = Instance::new(&mut store, &module, &[func.into()])?;
+    let foo = instance.get_typed_func::<i32, (), _>(&mut store, "foo")?;
+
+    assert_eq!(wasmtime_runtime::wasm_call_stack_depth(), 0);
+    foo.call(&mut store, 0)?;
+    foo.call(&mut store, 3)?;
+    assert_eq!(store.data(), &[1, 4]);
+
+    Ok(())
+}
+
+#[test]
+fn instance_wasm_call_stack_depth() -> anyhow::Result<()> {
+    // Instances `a` and `b` of different modules, where `a` recurses three
+    // times and then calls `b`, which calls back into the host.
+    let mut store = Store::<Option<[Instance; 2]>>::default();
+    let a = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $b))
+                (func $recursive (export "foo") (param i32)
+                    local.get 0
+                    if
+                        local.get 0
+                        i32.const 1
+                        i32.sub
+                        call $recursive
+                    else
+                        call $b
+                    end)
+            )
+        "#,
+    )?;
+    let b = Module::new(
+        store.engine(),
+        r#"(module (import "" "" (func $host)) (func (export "") call $host))"#,
+    )?;
+    let host = Func::wrap(&mut store, |caller: Caller<'_, Option<[Instance; 2]>>| {
+        let [a, b] = caller.data().unwrap();
+        assert_eq!(a.wasm_call_stack_depth(&caller), 4);
+        assert_eq!(b.wasm_call_stack_depth(&caller), 1);
+    });
+    let b = Instance::new(&mut store, &b, &[host.into()])?;
+    let b_export = b.get_func(&mut store, "").unwrap();
+    let a = Instance::new(&mut store, &a, &[b_export.into()])?;
+    *store.data_mut() = Some([a, b]);
+
+    assert_eq!(a.wasm_call_stack_depth(&store), 0);
+    a.get_typed_func::<i32, (), _>(&mut store, "foo")?
+        .call(&mut store, 3)?;
+    assert_eq!(a.wasm_call_stack_depth(&store), 0);
+    Ok(())
+}
@@ -50,3 +50,75 @@ fn copy_wrong() {
         "tables do not have the same element type"
     );
 }
+
+#[test]
+fn size_after_grow() -> anyhow::Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (table $t 1 funcref)
+                (func (export "grow") (param i32) (result i32)
+                    (table.grow $t (ref.null func) (local.get 0)))
+                (func (export "size") (result i32)
+                    (table.size $t)))
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let grow = instance.get_typed_func::<i32, i32, _>(&mut store, "grow")?;
+    let size = instance.get_typed_func::<(), i32, _>(&mut store, "size")?;
+
+    assert_eq!(size.call(&mut store, ())?, 1);
+    assert_eq!(grow.call(&mut store, 3)?, 1);
+    assert_eq!(size.call(&mut store, ())?, 4);
+    Ok(())
+}
+
+#[test]
+fn compact_funcref_table() -> anyhow::Result<()> {
+    let mut store = Store::<()>::default();
+    let ty = TableType::new(ValType::FuncRef, 1, None);
+    let table = Table::new(&mut store, ty, Val::FuncRef(None))?;
+    table.grow(&mut store, 4, Val::FuncRef(None))?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "t" (table $t 1 funcref))
+                (elem (i32.const 1) $one)
+                (elem (i32.const 3) $two)
+                (func $one (result i32) i32.const 1)
+                (func $two (result i32) i32.const 2)
+                (func (export "call") (param i32) (result i32)
+                    (call_indirect $t (result i32) (local.get 0))))
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[table.into()])?;
+    let call = instance.get_typed_func::<i32, i32, _>(&mut store, "call")?;
+
+    assert_eq!(call.call(&mut store, 3)?, 2);
+    assert_eq!(table.compact_funcref_table(&mut store)?, (2, 3));
+    assert_eq!(table.size(&store), 2);
+    assert_eq!(call.call(&mut store, 0)?, 1);
+    assert_eq!(call.call(&mut store, 1)?, 2);
+    assert!(call.call(&mut store, 2).is_err());
+    assert_eq!(table.compact_funcref_table(&mut store)?, (2, 0));
+    Ok(())
+}
+
+#[test]
+fn compact_funcref_table_keeps_minimum() -> anyhow::Result<()> {
+    let mut store = Store::<()>::default();
+    let ty = TableType::new(ValType::FuncRef, 3, None);
+    let table = Table::new(&mut store, ty, Val::FuncRef(None))?;
+    table.grow(&mut store, 2, Val::FuncRef(None))?;
+
+    assert_eq!(table.compact_funcref_table(&mut store)?, (3, 2));
+    assert_eq!(table.size(&store), 3);
+
+    let ty = TableType::new(ValType::ExternRef, 1, None);
+    let table = Table::new(&mut store, ty, Val::ExternRef(None))?;
+    assert!(table.compact_funcref_table(&mut store).is_err());
+    Ok(())
+}